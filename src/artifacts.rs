@@ -0,0 +1,69 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::checksums;
+use crate::options::Options;
+
+// Links are made under a temporary name ending in this before being renamed over a duplicate
+const LINK_SUFFIX: &str = ".dedup-link";
+// Image caches are written to their path with this added before replacing the last one
+const CACHE_SUFFIX: &str = ".tmp";
+
+// Files and folders the tool writes its outputs to or keeps its state in, like plans, caches and spill folders,
+// left out of scans when they're under the scanned folder so later runs never hash, report or delete them
+pub struct Artifacts {
+    // As they'd be found by the walk, under --path as given
+    paths: Vec<PathBuf>
+}
+
+impl Artifacts {
+    pub fn new(options: &Options) -> Self {
+        let checksums = checksums::manifest_path(options);
+        let outputs = [
+            &options.write_plan,
+            &options.user_scripts,
+            &options.spill_dir,
+            &options.results,
+            &options.ignore_groups,
+            &options.image_cache,
+            &options.progress_file,
+            &options.export_graph,
+            &checksums,
+            &options.session
+        ];
+        let root = options.path.canonicalize().ok();
+
+        let paths = outputs.iter()
+            .filter_map(|output| output.as_deref())
+            .filter_map(|output| Some(canonical(output)?.strip_prefix(root.as_ref()?).ok()?.to_owned()))
+            // Outputs in the scanned folder itself, like a spill folder given as --path, leave nothing to scan
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .map(|relative| options.path.join(relative))
+            .flat_map(|artifact| {
+                let mut temp = OsString::from(artifact.as_os_str());
+                temp.push(CACHE_SUFFIX);
+
+                [artifact, PathBuf::from(temp)]
+            })
+            .collect();
+
+        Self { paths }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.iter().any(|artifact| artifact == path)
+            || path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(LINK_SUFFIX))
+    }
+}
+
+// Outputs may not have been written yet, in which case their folder is resolved instead
+fn canonical(path: &Path) -> Option<PathBuf> {
+    path.canonicalize().ok().or_else(|| {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize().ok()?,
+            _ => std::env::current_dir().ok()?
+        };
+
+        Some(parent.join(path.file_name()?))
+    })
+}
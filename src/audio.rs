@@ -0,0 +1,93 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::fs;
+use std::path::Path;
+
+// Extensions we know how to strip metadata from
+// Everything else is hashed in full, even with --ignore-tags
+const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "mp2", "aac", "flac"];
+
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            AUDIO_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+// Returns the byte range (offset, length) of the audio stream within the file,
+// skipping leading ID3v2 tags and FLAC metadata blocks (which hold vorbis comments and pictures),
+// and trailing APEv2 and ID3v1 tags
+pub fn stream_range(path: &Path) -> io::Result<(u64, u64)> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut start = skip_id3v2(&mut file, 0)?;
+
+    if read_at(&mut file, start, 4)?.as_deref() == Some(b"fLaC") {
+        start = skip_flac_metadata(&mut file, start + 4)?;
+    }
+
+    let mut end = len;
+
+    if end >= start + 128 && read_at(&mut file, end - 128, 3)?.as_deref() == Some(b"TAG") {
+        end -= 128;
+    }
+
+    if end >= start + 32 {
+        if let Some(footer) = read_at(&mut file, end - 32, 32)? {
+            if &footer[..8] == b"APETAGEX" {
+                let size = u64::from(u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]));
+                let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+                // The size covers the items and the footer, but not the optional header
+                let header = if flags & (1 << 31) == 0 { 0 } else { 32 };
+
+                end = end.saturating_sub(size + header).max(start);
+            }
+        }
+    }
+
+    Ok((start, end.saturating_sub(start)))
+}
+
+fn skip_id3v2(file: &mut fs::File, offset: u64) -> io::Result<u64> {
+    let header = match read_at(file, offset, 10)? {
+        Some(header) if &header[..3] == b"ID3" => header,
+        _ => return Ok(offset)
+    };
+
+    // Tag size is a 28-bit "syncsafe" integer, the high bit of each byte is always unset
+    let size = header[6..10].iter()
+        .fold(0u64, |acc, byte| (acc << 7) | u64::from(byte & 0x7f));
+    let footer = if header[5] & 0x10 == 0 { 0 } else { 10 };
+
+    Ok(offset + 10 + size + footer)
+}
+
+fn skip_flac_metadata(file: &mut fs::File, mut offset: u64) -> io::Result<u64> {
+    loop {
+        let Some(header) = read_at(file, offset, 4)? else {
+            return Ok(offset);
+        };
+
+        let last = header[0] & 0x80 != 0;
+        let size = u64::from(u32::from_be_bytes([0, header[1], header[2], header[3]]));
+
+        offset += 4 + size;
+
+        if last {
+            return Ok(offset);
+        }
+    }
+}
+
+fn read_at(file: &mut fs::File, offset: u64, len: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut buf = vec![0; len];
+
+    file.seek(SeekFrom::Start(offset))?;
+
+    match file.read_exact(&mut buf) {
+        Ok(()) => Ok(Some(buf)),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err)
+    }
+}
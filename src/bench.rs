@@ -0,0 +1,168 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::ValueEnum;
+use walkdir::WalkDir;
+
+use crate::deduplicator::{self, Deduplicator};
+use crate::options::{HashAlgo, Options};
+use crate::random::Random;
+
+// Files hashed by every run, along with the synthetic file to clean up afterwards, if any
+struct Workload {
+    files: Vec<PathBuf>,
+    bytes: u64,
+    synthetic: Option<PathBuf>
+}
+
+impl Drop for Workload {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.synthetic {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Hashes the same workload with every algorithm and read buffer size, printing the throughput of each
+// The files are read once beforehand, so every run reads them from the cache,
+// which leaves out the seeks and round trips that larger buffers help most with on cold disks and shares
+pub fn run(options: &Options, size: u64, buffers: &[u64]) {
+    let workload = if options.path.as_os_str().is_empty() {
+        synthetic(size)
+    } else {
+        Ok(sample(&options.path, size))
+    };
+
+    let workload = match workload {
+        Ok(workload) => workload,
+        Err(err) => {
+            eprintln!("Failure while writing the synthetic workload: {}", std::env::temp_dir().to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+            return;
+        }
+    };
+
+    if workload.bytes == 0 {
+        println!("Found nothing to hash");
+        return;
+    }
+
+    println!("Hashing {} in {} files with each combination", Deduplicator::format_size(workload.bytes, 2), workload.files.len());
+    println!();
+
+    // Warm up the cache, so the first run isn't the only one reading from disk
+    let _ = hash_all(&workload.files, HashAlgo::Sha256, 1024 * 1024);
+
+    let mut fastest = None;
+
+    for &algo in HashAlgo::value_variants() {
+        for &buffer in buffers {
+            let Ok(buffer_size) = usize::try_from(buffer) else {
+                continue;
+            };
+
+            let start = Instant::now();
+
+            if let Err(err) = hash_all(&workload.files, algo, buffer_size) {
+                eprintln!("Failure while hashing the workload with {}", algo.name());
+                eprintln!("{err:?}");
+                eprintln!();
+                return;
+            }
+
+            let throughput = per_second(workload.bytes, start.elapsed().as_secs_f64());
+
+            println!("{:<8} {:>8} buffer: {}/s", algo.name(), size_flag(buffer), Deduplicator::format_size(throughput, 2));
+
+            if fastest.is_none_or(|(_, _, best)| throughput > best) {
+                fastest = Some((algo, buffer, throughput));
+            }
+        }
+    }
+
+    if let Some((algo, buffer, _)) = fastest {
+        println!();
+        println!("Fastest: --hash-algo {} --read-buffer {}", algo.name(), size_flag(buffer));
+    }
+}
+
+// A file of pseudorandom bytes in the temp folder, random so compression and deduplication below can't skew it
+fn synthetic(size: u64) -> io::Result<Workload> {
+    let path = std::env::temp_dir().join(format!("deduplicator-bench-{}", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&path)?);
+    let mut random = Random::new(Random::seed_from_time());
+    let mut written = 0;
+
+    // Removed once dropped, even if writing fails halfway
+    let workload = Workload {
+        files: vec![path.clone()],
+        bytes: size,
+        synthetic: Some(path)
+    };
+
+    while written < size {
+        writer.write_all(&random.next_u64().to_le_bytes())?;
+        written += 8;
+    }
+
+    writer.flush()?;
+
+    Ok(workload)
+}
+
+// Files from the folder, in walk order, until they add up to the size
+fn sample(path: &Path, size: u64) -> Workload {
+    let mut files = Vec::new();
+    let mut bytes = 0;
+
+    for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
+        if bytes >= size {
+            break;
+        }
+
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        if metadata.is_file() && metadata.len() > 0 {
+            bytes += metadata.len();
+            files.push(entry.into_path());
+        }
+    }
+
+    Workload {
+        files,
+        bytes,
+        synthetic: None
+    }
+}
+
+fn hash_all(files: &[PathBuf], algo: HashAlgo, buffer: usize) -> io::Result<()> {
+    for path in files {
+        let file = File::open(path)?;
+
+        deduplicator::reader_digest(algo.algorithm(), BufReader::with_capacity(buffer, file))?;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn per_second(bytes: u64, seconds: f64) -> u64 {
+    (bytes as f64 / seconds.max(f64::EPSILON)) as u64
+}
+
+// How the size would be passed to --read-buffer
+fn size_flag(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 && bytes.is_multiple_of(1024 * 1024) {
+        format!("{}M", bytes / (1024 * 1024))
+    } else if bytes >= 1024 && bytes.is_multiple_of(1024) {
+        format!("{}K", bytes / 1024)
+    } else {
+        bytes.to_string()
+    }
+}
@@ -0,0 +1,26 @@
+use walkdir::DirEntry;
+
+// Folders that macOS presents as a single file. Deleting duplicated resources inside them breaks
+// code signing or corrupts the library, so they are treated as opaque unless --scan-bundles is passed
+const BUNDLE_EXTENSIONS: [&str; 10] = [
+    "app",
+    "appex",
+    "bundle",
+    "framework",
+    "kext",
+    "plugin",
+    "xpc",
+    "pkg",
+    "photoslibrary",
+    "xcodeproj"
+];
+
+// The scanned folder itself is never considered a bundle, pointing the tool inside one is explicit enough
+pub fn is_bundle(entry: &DirEntry) -> bool {
+    entry.depth() > 0
+        && entry.file_type().is_dir()
+        && entry.path()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| BUNDLE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
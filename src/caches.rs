@@ -0,0 +1,33 @@
+use std::fs;
+use std::io::Read;
+
+use walkdir::DirEntry;
+
+// https://bford.info/cachedir/, the tag also used by tar, restic and borg
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+// Caches that commonly don't tag themselves, skipped with --exclude-caches
+const KNOWN_CACHES: [&str; 6] = [".cache", "__pycache__", ".npm", "Caches", "Cache", "GPUCache"];
+
+// The scanned folder itself is never considered a cache, pointing the tool at one is explicit enough
+pub fn is_cache(entry: &DirEntry, known: bool) -> bool {
+    if entry.depth() == 0 || !entry.file_type().is_dir() {
+        return false;
+    }
+
+    if known && KNOWN_CACHES.iter().any(|name| entry.file_name() == *name) {
+        return true;
+    }
+
+    has_tag(entry)
+}
+
+fn has_tag(entry: &DirEntry) -> bool {
+    let Ok(file) = fs::File::open(entry.path().join("CACHEDIR.TAG")) else {
+        return false;
+    };
+
+    let mut signature = Vec::with_capacity(CACHEDIR_SIGNATURE.len());
+
+    file.take(CACHEDIR_SIGNATURE.len() as u64).read_to_end(&mut signature).is_ok() && signature == CACHEDIR_SIGNATURE
+}
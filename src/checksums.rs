@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use ring::digest::{SHA256, SHA512};
+
+use crate::deduplicator::{hex, reader_digest, Deduplicator};
+use crate::options::{ChecksumFormat, HashAlgo, Options};
+use crate::progress::Progress;
+use crate::{md5, readahead};
+
+// The hashes manifests are made of, told apart by their BSD tag or else by their length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Md5,
+    Sha256,
+    Sha512
+}
+
+impl Kind {
+    const fn of(format: ChecksumFormat, hash_algo: HashAlgo) -> Self {
+        match (format, hash_algo) {
+            (ChecksumFormat::Md5sum, _) => Self::Md5,
+            (ChecksumFormat::Sha256sum, _) | (ChecksumFormat::Bsd, HashAlgo::Sha256) => Self::Sha256,
+            (ChecksumFormat::Bsd, HashAlgo::Sha512) => Self::Sha512
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        match tag.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Self::Md5),
+            "SHA256" => Some(Self::Sha256),
+            "SHA512" => Some(Self::Sha512),
+            _ => None
+        }
+    }
+
+    const fn from_len(hex_len: usize) -> Option<Self> {
+        match hex_len {
+            32 => Some(Self::Md5),
+            64 => Some(Self::Sha256),
+            128 => Some(Self::Sha512),
+            _ => None
+        }
+    }
+
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512"
+        }
+    }
+
+    fn digest(self, path: &Path) -> io::Result<Vec<u8>> {
+        let reader = io::BufReader::new(readahead::open_sequential(path)?);
+
+        match self {
+            Self::Md5 => Ok(md5::digest(reader)?.to_vec()),
+            Self::Sha256 => Ok(reader_digest(&SHA256, reader)?.as_ref().to_owned()),
+            Self::Sha512 => Ok(reader_digest(&SHA512, reader)?.as_ref().to_owned())
+        }
+    }
+}
+
+// A line of a manifest: the hash, the digest, and the file as written, usually relative to the manifest's folder
+struct Listed {
+    kind: Kind,
+    digest: Vec<u8>,
+    name: String
+}
+
+// Where the manifest of --export-checksums goes, named like the ones these tools are usually kept in
+pub fn manifest_path(options: &Options) -> Option<PathBuf> {
+    let name = match options.export_checksums? {
+        ChecksumFormat::Sha256sum => "SHA256SUMS",
+        ChecksumFormat::Md5sum => "MD5SUMS",
+        ChecksumFormat::Bsd => "CHECKSUMS"
+    };
+
+    Some(options.checksums_file.clone().unwrap_or_else(|| options.path.join(name)))
+}
+
+// Whether the digests hashed to find duplicates are the ones the manifest lists, so they needn't be hashed again
+pub const fn reuses_digests(options: &Options) -> bool {
+    match options.export_checksums {
+        Some(ChecksumFormat::Sha256sum) => matches!(options.hash_algo, HashAlgo::Sha256),
+        Some(ChecksumFormat::Bsd) => true,
+        Some(ChecksumFormat::Md5sum) | None => false
+    }
+}
+
+// Lists every file that's still there, sorted by path and relative to --path, taking its digest from `known`
+// or else hashing it now. Files that can't be read are left out and returned
+pub fn write(path: &Path, options: &Options, files: &[PathBuf], known: &HashMap<PathBuf, Vec<u8>>) -> io::Result<(usize, Vec<(PathBuf, io::Error)>)> {
+    let Some(format) = options.export_checksums else {
+        return Ok((0, Vec::new()));
+    };
+    let kind = Kind::of(format, options.hash_algo);
+
+    let mut sorted: Vec<&PathBuf> = files.iter().collect();
+    sorted.sort();
+
+    let mut manifest = String::new();
+    let mut listed = 0;
+    let mut unreadable = Vec::new();
+
+    for file in sorted {
+        // Deleted or renamed along the way
+        if !fs::symlink_metadata(file).is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+
+        let digest = match known.get(file) {
+            Some(digest) => digest.clone(),
+            None => match kind.digest(file) {
+                Ok(digest) => digest,
+                Err(err) => {
+                    unreadable.push((file.clone(), err));
+                    continue;
+                }
+            }
+        };
+
+        let (name, escaped) = escape(&relative(&options.path, file));
+        let prefix = if escaped { "\\" } else { "" };
+
+        let _ = match format {
+            ChecksumFormat::Sha256sum | ChecksumFormat::Md5sum => writeln!(manifest, "{prefix}{}  {name}", hex(&digest)),
+            ChecksumFormat::Bsd => writeln!(manifest, "{prefix}{} ({name}) = {}", kind.tag(), hex(&digest))
+        };
+
+        listed += 1;
+    }
+
+    fs::write(path, manifest)?;
+
+    Ok((listed, unreadable))
+}
+
+// With forward slashes on every platform, as the checking tools expect
+fn relative(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+
+    if cfg!(windows) {
+        relative.replace('\\', "/")
+    } else {
+        relative.into_owned()
+    }
+}
+
+// Escapes names the way GNU coreutils does, which marks their lines with a leading backslash
+fn escape(name: &str) -> (String, bool) {
+    if !name.contains(['\\', '\n', '\r']) {
+        return (name.to_owned(), false);
+    }
+
+    let escaped = name.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+
+    (escaped, true)
+}
+
+fn unescape(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\')
+        }
+    }
+
+    unescaped
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+// Reads `sha256sum`, `md5sum` and `sha512sum` lines, with a space or an asterisk before the name,
+// and BSD tagged lines, skipping blank lines and comments
+fn parse(line: &str) -> Option<Listed> {
+    let (line, escaped) = line.strip_prefix('\\').map_or((line, false), |rest| (rest, true));
+    let unescape = |name: &str| if escaped { unescape(name) } else { name.to_owned() };
+
+    if let Some((tag, rest)) = line.split_once(" (") {
+        if let (Some(kind), Some((name, hex))) = (Kind::from_tag(tag), rest.rsplit_once(") = ")) {
+            let digest = unhex(hex.trim()).filter(|digest| Kind::from_len(digest.len() * 2) == Some(kind))?;
+
+            return Some(Listed { kind, digest, name: unescape(name) });
+        }
+    }
+
+    let (hex, rest) = line.split_once(' ')?;
+    let name = rest.strip_prefix([' ', '*']).unwrap_or(rest);
+
+    Some(Listed {
+        kind: Kind::from_len(hex.len())?,
+        digest: unhex(hex)?,
+        name: unescape(name)
+    })
+}
+
+// Without `.` components, so names written as `./file` match the walk's paths
+fn clean(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+// Re-hashes every file a manifest lists, relative to --path or else the manifest's own folder,
+// reporting the ones that changed or are gone, and the files under it that the manifest doesn't list
+pub fn verify(mut options: Options, manifest: &Path) {
+    let contents = match fs::read_to_string(manifest) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("Failure while reading the checksum manifest: {}", manifest.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            process::exit(1);
+        }
+    };
+
+    if options.path.as_os_str().is_empty() {
+        options.path = manifest.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_owned);
+    }
+
+    let mut listed = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse(line) {
+            Some(entry) => listed.push(entry),
+            None => eprintln!("Skipping line {} of the manifest, it's not a checksum line", number + 1)
+        }
+    }
+
+    let mut progress = Progress::start();
+    let (mut unchanged, mut modified, mut missing, mut unreadable) = (0, 0, 0, 0);
+    let mut known = HashSet::new();
+
+    for (index, entry) in listed.iter().enumerate() {
+        let path = clean(&options.path.join(&entry.name));
+
+        progress.update(index, format_args!("Hashed {index} files out of {}", listed.len()));
+        known.insert(path.clone());
+
+        if !fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+            println!("Missing: {}", entry.name);
+            missing += 1;
+            continue;
+        }
+
+        match entry.kind.digest(&path) {
+            Ok(digest) if digest == entry.digest => unchanged += 1,
+            Ok(_) => {
+                println!("Modified: {}", entry.name);
+                modified += 1;
+            },
+            Err(err) => {
+                eprintln!("Failure while hashing: {}", path.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                unreadable += 1;
+            }
+        }
+    }
+
+    progress.finish();
+
+    let manifest = fs::canonicalize(manifest).ok();
+    let mut new = 0;
+
+    for entry in Deduplicator::new(options.clone()).list_entries() {
+        if !entry.file_type().is_file() || known.contains(&clean(entry.path())) {
+            continue;
+        }
+
+        if manifest.is_some() && fs::canonicalize(entry.path()).ok() == manifest {
+            continue;
+        }
+
+        println!("New: {}", relative(&options.path, entry.path()));
+        new += 1;
+    }
+
+    println!();
+    println!("Checked {} files: {unchanged} unchanged, {modified} modified, {missing} missing, {unreadable} unreadable, {new} not in the manifest", listed.len());
+
+    // New files alone are expected of a folder that's still growing
+    if modified + missing + unreadable > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,87 @@
+use std::io;
+use std::path::Path;
+
+use image::DynamicImage;
+#[cfg(feature = "clip")]
+use image::imageops::FilterType;
+#[cfg(feature = "clip")]
+use tract_onnx::prelude::*;
+
+// Side of the square images CLIP image encoders take
+#[cfg(feature = "clip")]
+const INPUT_SIZE: usize = 224;
+// Per channel mean and deviation of the images CLIP was trained on, which its inputs are normalized by
+#[cfg(feature = "clip")]
+const MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+#[cfg(feature = "clip")]
+const STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+
+// A CLIP image encoder exported to ONNX, for --clip-model, whose embeddings of two versions of a photo stay
+// close through crops, filters and edits that change its perceptual hash entirely
+#[cfg(feature = "clip")]
+pub struct Clip {
+    model: TypedRunnableModel<TypedModel>
+}
+
+#[cfg(feature = "clip")]
+impl Clip {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.with_input_fact(0, f32::fact([1, 3, INPUT_SIZE, INPUT_SIZE]).into()))
+            .and_then(InferenceModelExt::into_optimized)
+            .and_then(TypedModel::into_runnable)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{err:?}")))?;
+
+        Ok(Self { model })
+    }
+
+    // The image's embedding scaled to unit length, so the cosine similarity of two is their dot product
+    #[allow(clippy::cast_precision_loss)]
+    pub fn embed(&self, image: &DynamicImage) -> io::Result<Vec<f32>> {
+        #[allow(clippy::cast_possible_truncation)]
+        let side = INPUT_SIZE as u32;
+        let resized = image.resize_exact(side, side, FilterType::Triangle).to_rgb8();
+
+        let input: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, INPUT_SIZE, INPUT_SIZE), |(_, channel, y, x)| {
+            #[allow(clippy::cast_possible_truncation)]
+            let pixel = resized.get_pixel(x as u32, y as u32);
+
+            (f32::from(pixel[channel]) / 255.0 - MEAN[channel]) / STD[channel]
+        })
+        .into();
+
+        let outputs = self.model.run(tvec!(input.into()))
+            .map_err(|err| io::Error::other(format!("{err:?}")))?;
+        let mut embedding: Vec<f32> = outputs[0].to_array_view::<f32>()
+            .map_err(|err| io::Error::other(format!("{err:?}")))?
+            .iter()
+            .copied()
+            .collect();
+
+        let norm = embedding.iter().map(|value| value * value).sum::<f32>().sqrt();
+
+        if norm > 0.0 {
+            for value in &mut embedding {
+                *value /= norm;
+            }
+        }
+
+        Ok(embedding)
+    }
+}
+
+#[cfg(not(feature = "clip"))]
+pub struct Clip;
+
+#[cfg(not(feature = "clip"))]
+impl Clip {
+    pub fn load(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this build can't run CLIP models, build it with `--features clip`"))
+    }
+
+    #[allow(clippy::unused_self)]
+    pub fn embed(&self, _image: &DynamicImage) -> io::Result<Vec<f32>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this build can't run CLIP models"))
+    }
+}
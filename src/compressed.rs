@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use ring::digest::{Algorithm, Context, Digest};
+use ruzstd::StreamingDecoder;
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Gzip,
+    Xz,
+    Zstd
+}
+
+fn format(path: &Path) -> Option<Format> {
+    let ext = path.extension()?.to_str()?;
+
+    if ext.eq_ignore_ascii_case("gz") {
+        Some(Format::Gzip)
+    } else if ext.eq_ignore_ascii_case("xz") {
+        Some(Format::Xz)
+    } else if ext.eq_ignore_ascii_case("zst") {
+        Some(Format::Zstd)
+    } else {
+        None
+    }
+}
+
+pub fn is_compressed(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+// Size of the decompressed contents, found by decompressing them as none of the formats reliably record it
+pub fn uncompressed_len(path: &Path) -> io::Result<u64> {
+    let mut counter = Counter(0);
+
+    decompress(path, &mut counter)?;
+
+    Ok(counter.0)
+}
+
+// Hashes the decompressed contents, so they match the digest of the uncompressed file
+pub fn digest(path: &Path, algorithm: &'static Algorithm) -> io::Result<Digest> {
+    let mut hasher = Hasher(Context::new(algorithm));
+
+    decompress(path, &mut hasher)?;
+
+    Ok(hasher.0.finish())
+}
+
+fn decompress(path: &Path, output: &mut impl Write) -> io::Result<()> {
+    let format = format(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a compressed file"))?;
+    let reader = BufReader::new(File::open(path)?);
+
+    match format {
+        // Concatenated members are one stream, as written by `cat a.gz b.gz` or parallel compressors
+        Format::Gzip => io::copy(&mut MultiGzDecoder::new(reader), output).map(|_| ()),
+        Format::Xz => lzma_rs::xz_decompress(&mut { reader }, output)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Format::Zstd => {
+            let mut decoder = StreamingDecoder::new(reader)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            io::copy(&mut decoder, output).map(|_| ())
+        }
+    }
+}
+
+struct Hasher(Context);
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Settings of the config files, the user's and then a system-wide one that organizations can roll out
+// to every machine, which takes priority. Settings left out of both keep their defaults
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Config {
+    // Ask before deleting, renaming or linking duplicates, unless --yes is passed
+    pub confirm_actions: Option<bool>,
+    // Whether --yes can stand in for asking
+    pub allow_yes: Option<bool>
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let user = user_path().map(|path| read(&path)).unwrap_or_default();
+        let system = system_path().map(|path| read(&path)).unwrap_or_default();
+
+        Self {
+            confirm_actions: system.confirm_actions.or(user.confirm_actions),
+            allow_yes: system.allow_yes.or(user.allow_yes)
+        }
+    }
+
+    pub fn confirms_actions(self) -> bool {
+        self.confirm_actions.unwrap_or(true)
+    }
+
+    pub fn allows_yes(self) -> bool {
+        self.allow_yes.unwrap_or(true)
+    }
+}
+
+#[cfg(windows)]
+fn system_path() -> Option<PathBuf> {
+    env::var_os("ProgramData").map(|data| PathBuf::from(data).join("deduplicator").join("config.toml"))
+}
+
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+fn system_path() -> Option<PathBuf> {
+    Some(PathBuf::from("/etc/deduplicator.toml"))
+}
+
+#[cfg(windows)]
+fn user_path() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(|data| PathBuf::from(data).join("deduplicator").join("config.toml"))
+}
+
+#[cfg(not(windows))]
+fn user_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("deduplicator").join("config.toml"))
+}
+
+// Missing files set nothing, and settings that can't be understood are reported and left out
+fn read(path: &Path) -> Config {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Config::default(),
+        Err(err) => {
+            eprintln!("Failure while reading the config file: {}", path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            return Config::default();
+        }
+    };
+
+    let mut config = Config::default();
+
+    // Only top-level `key = true` or `key = false` settings are needed out of TOML
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let setting = line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .and_then(|(key, value)| Some((key, value.parse::<bool>().ok()?)));
+
+        match setting {
+            Some(("confirm_actions", value)) => config.confirm_actions = Some(value),
+            Some(("allow_yes", value)) => config.allow_yes = Some(value),
+            _ => {
+                eprintln!("Failure while reading the config file, ignoring `{line}`: {}", path.to_string_lossy());
+                eprintln!("Only `confirm_actions` and `allow_yes` can be set, to true or false");
+                eprintln!();
+            }
+        }
+    }
+
+    config
+}
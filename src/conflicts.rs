@@ -0,0 +1,27 @@
+// Name of the file a sync conflict copy was made of, in the same folder, if the name is one
+// Covers Dropbox and Nextcloud `name (… conflicted copy …).ext` and `name (Case Conflict).ext`,
+// and Syncthing `name.sync-conflict-<date>-<time>-<device>.ext`
+pub fn base_name(name: &str) -> Option<String> {
+    const SYNCTHING: &str = ".sync-conflict-";
+
+    if let Some(start) = name.find(SYNCTHING) {
+        let rest = &name[start + SYNCTHING.len()..];
+        let ext = rest.find('.').map_or("", |dot| &rest[dot..]);
+
+        return (start > 0).then(|| format!("{}{ext}", &name[..start]));
+    }
+
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, "")
+    };
+    let inner = stem.strip_suffix(')')?;
+    let open = inner.rfind(" (")?;
+    let marker = inner[open + 2..].to_lowercase();
+
+    let conflict = marker.contains("conflicted copy")
+        || marker == "case conflict"
+        || marker.strip_prefix("case conflict ").is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()));
+
+    (conflict && open > 0).then(|| format!("{}{ext}", &inner[..open]))
+}
@@ -1,21 +1,56 @@
-use std::io;
+use std::convert::TryFrom;
+use std::cmp::{self, Reverse};
+use std::fmt::Write;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::fs;
-use std::collections::HashMap;
-use std::path::Path;
-use std::sync::mpsc;
-use std::time::Instant;
-
-use ring::digest::{SHA256, Digest, Context};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use ring::digest::{Algorithm, Digest, Context};
+use itertools::Itertools;
 use walkdir::{DirEntry, WalkDir};
+use serde_json::{json, Value};
 use threadpool::ThreadPool;
 
-use crate::options::{Options, FileOrdering, Keep};
-
-fn sha256_digest<R>(mut reader: R) -> io::Result<Digest>
+use crate::{audio, bundles, caches, checksums, compressed, conflicts, diff, estimate, failures, forensic, generated, generations, hidden, images, links, office, owners, paths, pdf, plan, policy, rationale, readahead, reparse, session, space, streams, text, trash};
+use crate::artifacts::Artifacts;
+use crate::estimate::Stratum;
+use crate::external::Sorter;
+use crate::dirpolicy::{DirPolicy, Policies};
+use crate::dirtimes::DirTimes;
+use crate::failures::Failures;
+use crate::i18n::Message;
+use crate::random::Random;
+use crate::rationale::Reason;
+use crate::sample::Sample;
+use crate::limits::ScanLimit;
+use crate::links::LinkKind;
+use crate::progress::{Progress, ProgressLog, Step};
+use crate::options::{Options, FileOrdering, HashOrder, Keep, Lang, Mode, OutputOrdering, PermissionOrder, SummaryFormat};
+use crate::owners::{Removals, Users};
+use crate::source::{FileInfo, FileSource};
+use crate::spill::{self, Spill};
+use crate::stats::{Memory, Stats, Timings};
+use crate::storage::Storage;
+use crate::throttle::Throttle;
+use crate::trash::Trash;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring;
+use crate::vss::Snapshot;
+use crate::watchdog::Watchdog;
+
+pub fn reader_digest<R>(algorithm: &'static Algorithm, mut reader: R) -> io::Result<Digest>
 where
     R: io::Read
 {
-    let mut ctx = Context::new(&SHA256);
+    let mut ctx = Context::new(algorithm);
     let mut buf = [0; 1024];
 
     loop {
@@ -30,76 +65,473 @@ where
     Ok(ctx.finish())
 }
 
-pub struct Deduplicator {
+// Files are first sorted into buckets that can only contain duplicates of each other,
+// so that only files sharing a bucket need to be read and hashed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Bucket {
+    Size(u64),
+    // Normalized documents can have the same content despite differing in size on disk
+    Pdf,
+    Office,
+    // Normalized images can differ in size on disk too, but not in their dimensions, smallest first
+    Image(u32, u32)
+}
+
+impl Bucket {
+    fn of(file: &impl FileSource, size: u64, options: &Options) -> Self {
+        // Normalizing needs to seek into and decode files, so files elsewhere are compared as they are
+        let Some(path) = file.local_path() else {
+            return Self::Size(size);
+        };
+
+        // Signatures are of the bytes as they are, so normalizing would only mix sizes together
+        if matches!(options.mode, Mode::QuickSignature) {
+            return Self::Size(size);
+        }
+
+        if options.normalize_pdfs && pdf::is_pdf(path) {
+            return Self::Pdf;
+        }
+
+        if options.normalize_office && office::is_office(path) {
+            return Self::Office;
+        }
+
+        if options.normalize_images && images::is_image(path) {
+            if let Some((short, long)) = images::dimensions(path) {
+                return Self::Image(short, long);
+            }
+        }
+
+        if options.decompress_before_hash && compressed::is_compressed(path) {
+            if let Ok(len) = compressed::uncompressed_len(path) {
+                return Self::Size(len);
+            }
+        }
+
+        if !options.text_normalize.is_empty() && text::is_text(path) {
+            if let Ok(normalized) = text::normalized(path, &options.text_normalize) {
+                return Self::Size(normalized.len() as u64);
+            }
+        }
+
+        // Tagged audio files are bucketed by the length of their audio stream,
+        // as the same song with different tags will differ in total size
+        if options.ignore_tags && audio::is_audio(path) {
+            if let Ok((_, len)) = audio::stream_range(path) {
+                if len > 0 {
+                    return Self::Size(len);
+                }
+            }
+        }
+
+        Self::Size(size)
+    }
+
+    // Size of the files in the bucket, normalized buckets hold files of any size so take the largest
+    fn file_size(self, files: &[impl FileSource]) -> u64 {
+        match self {
+            Self::Size(size) => size,
+            Self::Pdf | Self::Office | Self::Image(..) => files.iter()
+                .filter_map(|file| file.info().ok())
+                .map(|info| info.size)
+                .max()
+                .unwrap_or(0)
+        }
+    }
+
+    // Fixed-size key for spilling files to disk, a tag followed by the size or dimensions
+    fn key(self) -> spill::Key {
+        let (tag, value) = match self {
+            Self::Size(size) => (0, size),
+            Self::Pdf => (1, 0),
+            Self::Office => (2, 0),
+            Self::Image(short, long) => (3, u64::from(short) << 32 | u64::from(long))
+        };
+
+        let mut key = [0; spill::KEY_LEN];
+        key[0] = tag;
+        key[1..].copy_from_slice(&value.to_le_bytes());
+        key
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_key(key: spill::Key) -> Self {
+        let mut value = [0; 8];
+        value.copy_from_slice(&key[1..]);
+        let value = u64::from_le_bytes(value);
+
+        match key[0] {
+            1 => Self::Pdf,
+            2 => Self::Office,
+            3 => Self::Image((value >> 32) as u32, value as u32),
+            _ => Self::Size(value)
+        }
+    }
+
+    // Coarse size class used to stratify samples, files within a class tend to duplicate alike
+    const fn class(self) -> u32 {
+        match self {
+            Self::Size(size) => u64::BITS - size.leading_zeros(),
+            Self::Pdf => u64::BITS + 1,
+            Self::Office => u64::BITS + 2,
+            Self::Image(..) => u64::BITS + 3
+        }
+    }
+}
+
+// How loose a file's permissions are, ordered by whether others can write to it, then its group, then how
+// many permission bits are set. On Windows, only whether it's read-only is known
+#[cfg(unix)]
+fn permission_rank(path: &Path) -> Option<(bool, bool, u32)> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o7777;
+
+    Some((mode & 0o002 != 0, mode & 0o020 != 0, mode.count_ones()))
+}
+
+#[cfg(not(unix))]
+fn permission_rank(path: &Path) -> Option<(bool, bool, u32)> {
+    let readonly = fs::metadata(path).ok()?.permissions().readonly();
+
+    Some((false, false, u32::from(!readonly)))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+// Replaces `{var}` placeholders in a single pass, so substituted values are never expanded again
+// Unknown placeholders are left as-is
+fn expand_pattern(pattern: &str, vars: &[(&str, &str)]) -> String {
+    let mut expanded = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let var = rest.find('}')
+            .and_then(|close| vars.iter().find(|(name, _)| *name == &rest[1..close]).map(|var| (close, var.1)));
+
+        if let Some((close, value)) = var {
+            expanded.push_str(value);
+            rest = &rest[close + 1..];
+        } else {
+            expanded.push('{');
+            rest = &rest[1..];
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+// Bytes freed by deleting every file of a group but one, keeping the largest when normalized sizes differ
+fn reclaimable<'a, F: FileSource>(files: impl IntoIterator<Item = &'a F>) -> u64 {
+    let sizes: Vec<u64> = files.into_iter()
+        .filter_map(|file| file.info().ok())
+        .map(|info| info.size)
+        .collect();
+
+    sizes.iter().sum::<u64>() - sizes.iter().max().unwrap_or(&0)
+}
+
+// Records sorted in memory at a time with --spill-dir, unless --max-memory says otherwise
+const RUN_BYTES: usize = 64 * 1024 * 1024;
+
+// Wait before the first round of --retries, doubling with each one after
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Bytes hashed off the start of each file with --mode quick-signature
+pub const SIGNATURE_LEN: u64 = 4096;
+
+// Lines of each --show-diff preview
+const MAX_DIFF_LINES: usize = 20;
+// How far short of the predicted space the freed space may fall before it's pointed out
+const FREED_TOLERANCE: f64 = 0.1;
+
+// Digest shared by the group, and its members
+pub type DuplicateGroup<'dirs, F = DirEntry> = (Vec<u8>, Vec<&'dirs F>);
+
+// What the groups of a hash mode run add up to, as they're handled one by one
+#[derive(Default)]
+struct Tally {
+    stats: Stats,
+    // Groups for --write-plan
+    planned: Vec<Value>,
+    users: BTreeMap<u32, Stats>,
+    // Duplicates for --user-scripts
+    removals: BTreeMap<u32, Removals>,
+    // Groups entirely within generated folders, by the folders they're in, with --collapse-generated
+    generated: BTreeMap<Vec<PathBuf>, Stats>,
+    // Groups shown at the end instead of all of them, with --sample-groups
+    sample: Option<Sample<SampledGroup>>,
+    actions: Duration
+}
+
+// A group kept for --sample-groups, with what's needed to list it later
+struct SampledGroup {
+    group: usize,
+    source: DirEntry,
+    duplicates: Vec<DirEntry>,
+    reasons: Vec<String>,
+    owner: Option<u32>
+}
+
+pub struct Deduplicator<F = DirEntry> {
     start: Instant,
-    options: Options,
+    options: Arc<Options>,
     pool: ThreadPool,
-    sizes: HashMap<u64, Vec<DirEntry>>
+    // Picked from the storage the scanned folder is on
+    read_buffer: usize,
+    sizes: HashMap<Bucket, Vec<F>>,
+    // Walk position of the first file seen in each bucket, for --hash-order walk-order
+    first_seen: HashMap<Bucket, usize>,
+    // Digests computed ahead of time in one batch, with --io-uring
+    batched: HashMap<PathBuf, Vec<u8>>,
+    timings: Timings,
+    // Language of the report
+    lang: Lang,
+    failures: Failures,
+    // Watches for files taking too long to read, unless --stall-warning is 0
+    watchdog: Option<Watchdog>,
+    // Bytes taken by the size map right now, which is spilled to disk past --max-memory
+    sizes_memory: usize,
+    spill: Option<Spill>,
+    // How many files of each bucket were spilled
+    spilled: HashMap<Bucket, usize>,
+    memory: Memory,
+    // Updated from `get_true_dupes`, which only borrows
+    hashes_memory: AtomicUsize,
+    // Shadow copy files are read from with --vss
+    snapshot: Option<Snapshot>,
+    // Size and modification time of every file hashed, as they were right before
+    scanned: Mutex<HashMap<PathBuf, (u64, Option<SystemTime>)>>,
+    // From the .dedup-policy.toml files under the scanned folder
+    policies: Policies,
+    throttle: Throttle,
+    trash: Option<Trash>,
+    // Files and bytes deleted so far against --max-delete-files and --max-delete-bytes, None once they're used up
+    delete_budget: Mutex<Option<(usize, u64)>>,
+    // Folders duplicates were deleted from, to remove the ones left empty with --prune-empty-dirs
+    deleted_from: Mutex<BTreeSet<PathBuf>>,
+    // Free space on the volume before any duplicate was deleted or linked, to tell how much was really freed
+    free_before: Option<u64>,
+    // JSON progress for wrappers, with --progress-fd or --progress-file
+    progress_log: Option<ProgressLog>,
+    // Every scanned file, for --export-checksums and --session
+    all_files: Vec<PathBuf>,
+    // The digests hashed to find duplicates that --export-checksums can list as they are
+    plain_digests: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    // Every digest hashed to find duplicates, for --session
+    digests: Mutex<HashMap<PathBuf, Vec<u8>>>
 }
 
 impl Deduplicator {
     pub fn new(options: Options) -> Self  {
-        Self {
-            start: Instant::now(),
-            pool: ThreadPool::new(options.threads),
-            options,
-            sizes: HashMap::new()
-        }
+        Self::with_options(options)
     }
 
-    fn list_entries(&self) -> impl Iterator<Item=DirEntry> {
-        WalkDir::new(&self.options.path)
+    pub fn list_entries(&self) -> impl Iterator<Item=DirEntry> {
+        let mut limit = ScanLimit::new(&self.options);
+        let scan_bundles = self.options.scan_bundles;
+        let include_caches = self.options.include_caches;
+        let exclude_caches = self.options.exclude_caches;
+        let include_hidden = self.options.include_hidden;
+        let skip_hidden = self.options.skip_hidden;
+        let artifacts = Artifacts::new(&self.options);
+
+        let walk = WalkDir::new(&self.options.path)
             .max_depth(if self.options.no_recursive {
                 1
             } else {
-                std::usize::MAX
+                usize::MAX
+            });
+        // Folders are otherwise listed in whatever order the file system keeps them in
+        let walk = if self.options.deterministic { walk.sort_by_file_name() } else { walk };
+
+        walk.into_iter()
+            .filter_entry(move |entry| {
+                (scan_bundles || !bundles::is_bundle(entry))
+                    && (include_caches || !caches::is_cache(entry, exclude_caches))
+                    && (include_hidden || !hidden::is_hidden(entry, skip_hidden))
+                    && !artifacts.contains(entry.path())
             })
-            .into_iter()
             .filter_map(Result::ok)
+            .filter(|entry| !reparse::is_link(entry))
+            .take_while(move |entry| limit.admit(entry))
     }
+}
 
-    fn digest(entry: &DirEntry) -> Option<Vec<u8>> {
-        let file = match fs::File::open(entry.path()) {
-            // Ignore any inaccessible files or folders that can't be read
-            Err(_) => {
-                return None;
+impl<F: FileSource> Deduplicator<F> {
+    // For files from other sources than the walk, which are handed to `scan` directly
+    pub fn with_options(options: Options) -> Self {
+        let storage = Storage::detect(&options.path);
+        let snapshot = match options.vss.then(|| Snapshot::create(&options.path)) {
+            Some(Ok(snapshot)) => Some(snapshot),
+            Some(Err(err)) => {
+                eprintln!("Failure while creating a shadow copy, reading from the live volume: {}", options.path.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                None
             },
-            Ok(file) => file
+            None => None
         };
-        let digest = match sha256_digest(io::BufReader::new(file)) {
-            Err(_) => {
-                return None;
-            },
-            Ok(digest) => digest
+
+        Self {
+            start: Instant::now(),
+            pool: ThreadPool::new(options.io_threads.unwrap_or_else(|| storage.threads())),
+            read_buffer: options.read_buffer
+                .and_then(|size| usize::try_from(size).ok())
+                .unwrap_or_else(|| storage.read_buffer()),
+            lang: Lang::detect(options.lang),
+            watchdog: (options.stall_warning > 0).then(|| Watchdog::start(Duration::from_secs(options.stall_warning))),
+            policies: Policies::new(&options.path),
+            throttle: Throttle::new(&options),
+            trash: Trash::new(&options),
+            delete_budget: Mutex::new(Some((0, 0))),
+            deleted_from: Mutex::new(BTreeSet::new()),
+            free_before: (options.delete || options.hardlink || options.reflink)
+                .then(|| space::free(&options.path).ok())
+                .flatten(),
+            progress_log: ProgressLog::open(&options),
+            options: Arc::new(options),
+            sizes: HashMap::new(),
+            first_seen: HashMap::new(),
+            batched: HashMap::new(),
+            timings: Timings::default(),
+            failures: Failures::default(),
+            sizes_memory: 0,
+            spill: None,
+            spilled: HashMap::new(),
+            memory: Memory::default(),
+            hashes_memory: AtomicUsize::new(0),
+            snapshot,
+            scanned: Mutex::new(HashMap::new()),
+            all_files: Vec::new(),
+            plain_digests: Mutex::new(HashMap::new()),
+            digests: Mutex::new(HashMap::new())
+        }
+    }
+
+    pub fn digest(&self, path: &Path) -> io::Result<Vec<u8>> {
+        if let Some(digest) = self.batched.get(path) {
+            return Ok(digest.clone());
+        }
+
+        let snapshotted = self.snapshot.as_ref().map(|snapshot| snapshot.translate(path));
+
+        self.hash(snapshotted.as_deref().unwrap_or(path))
+    }
+
+    // Like `digest`, but always reads the file as it is now, even if it was hashed ahead of time or --vss is passed
+    fn hash(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = readahead::open_sequential(path)?;
+        let algorithm = self.options.hash_algo.algorithm();
+
+        let digest = if matches!(self.options.mode, Mode::QuickSignature) {
+            reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file).take(SIGNATURE_LEN))?
+        } else if self.options.ignore_tags && audio::is_audio(path) {
+            let (offset, len) = audio::stream_range(path)?;
+
+            // Tags claiming to run past the end leave nothing to hash, which every such broken file would share,
+            // so those are hashed in full
+            let (offset, len) = if len == 0 { (0, u64::MAX) } else { (offset, len) };
+
+            file.seek(SeekFrom::Start(offset))?;
+
+            reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file).take(len))?
+        } else if self.options.decompress_before_hash && compressed::is_compressed(path) {
+            // Files that fail to decompress are still compared byte for byte
+            compressed::digest(path, algorithm).or_else(|_| reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file)))?
+        } else if self.options.normalize_pdfs && pdf::is_pdf(path) {
+            reader_digest(algorithm, &pdf::normalize(&fs::read(path)?)[..])?
+        } else if self.options.normalize_office && office::is_office(path) {
+            // Documents that aren't valid zip files are still compared byte for byte
+            office::digest(path, algorithm).or_else(|_| reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file)))?
+        } else if !self.options.text_normalize.is_empty() && text::is_text(path) {
+            reader_digest(algorithm, &text::normalized(path, &self.options.text_normalize)?[..])?
+        } else if self.options.normalize_images && images::is_image(path) {
+            // Images that fail to decode are still compared byte for byte
+            images::digest(path).or_else(|_| reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file)))?
+        } else {
+            reader_digest(algorithm, io::BufReader::with_capacity(self.read_buffer, file))?
         };
 
-        Some(digest.as_ref().to_owned())
+        if self.options.alternate_streams {
+            return streams::extend_digest(path, digest.as_ref());
+        }
+
+        Ok(digest.as_ref().to_owned())
+    }
+
+    // Notes the size and modification time of a file about to be hashed
+    pub fn remember(&self, path: &Path) {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            self.remember_as(path, metadata.len(), metadata.modified().ok());
+        }
+    }
+
+    // Like `remember`, for files read by other means, like the images of similarity mode
+    pub fn remember_as(&self, path: &Path, size: u64, modified: Option<SystemTime>) {
+        self.scanned.lock()
+            .expect("scanned files are never poisoned")
+            .insert(path.to_owned(), (size, modified));
+    }
+
+    // Files that couldn't be read or acted on so far
+    pub const fn failures(&self) -> &Failures {
+        &self.failures
+    }
+
+    fn log_progress(&self, phase: &'static str, step: Step) {
+        if let Some(ref log) = self.progress_log {
+            log.update(phase, step);
+        }
+    }
+
+    fn finish_progress(&self, phase: &'static str, step: Step) {
+        if let Some(ref log) = self.progress_log {
+            log.finish(phase, step);
+        }
+    }
+
+    // Digest of a file on disk through `digest`, or of its raw contents otherwise
+    fn digest_file(&self, file: &F) -> io::Result<Vec<u8>> {
+        if let Some(path) = file.local_path() {
+            return self.digest(path);
+        }
+
+        let reader = io::BufReader::with_capacity(self.read_buffer, file.open()?);
+
+        reader_digest(self.options.hash_algo.algorithm(), reader)
+            .map(|digest| digest.as_ref().to_owned())
     }
 
-    fn map_with_metadata(files: &[DirEntry]) -> impl Iterator<Item=(fs::Metadata, &DirEntry)> {
+    fn map_with_info(files: &[F]) -> impl Iterator<Item=(FileInfo, &F)> {
         files.iter()
-            .map(|entry| (entry.metadata(), entry))
-            .filter_map(|(meta, entry)| {
-                if let Ok(metadata) = meta {
-                    Some((metadata, entry))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|file| file.info().ok().map(|info| (info, file)))
     }
 
-    fn select<'dirs>(&self, files: &'dirs [DirEntry]) -> (&'dirs DirEntry, Vec<&'dirs DirEntry>) {
-        let mut mapped: Vec<_> = Self::map_with_metadata(files).collect();
+    // The kept file of a group and its duplicates, along with the rule that picked it
+    pub fn select<'dirs>(&self, files: &'dirs [F]) -> (&'dirs F, Vec<&'dirs F>, Reason) {
+        let mut mapped: Vec<_> = Self::map_with_info(files).collect();
 
         match self.options.order {
             FileOrdering::Modified => {
-                mapped.sort_by_cached_key(|(meta, _)| meta.modified().unwrap());
+                mapped.sort_by_key(|(info, _)| info.modified);
             },
             FileOrdering::Created => {
-                mapped.sort_by_cached_key(|(meta, _)| meta.created().unwrap());
+                mapped.sort_by_key(|(info, _)| info.created);
             },
             FileOrdering::Name => {
-                mapped.sort_by_cached_key(|(_, entry)| entry.file_name());
+                mapped.sort_by_cached_key(|(_, file)| file.path().file_name().map(paths::normalize));
             }
         }
 
@@ -107,217 +539,2236 @@ impl Deduplicator {
             .map(|(_, entry)| entry)
             .collect();
 
-        match self.options.keep {
-            Keep::First => {
-                let first = sorted.remove(0);
+        let index = match self.options.keep {
+            Keep::First => 0,
+            Keep::Last => sorted.len() - 1
+        };
+        let (index, reason) = self.policy_index(&sorted).map(|index| (index, Reason::Policy))
+            .or_else(|| self.preferred_index(&sorted).map(|index| (index, Reason::DirOrder)))
+            .or_else(|| self.permission_index(&sorted).map(|index| (index, Reason::Permissions)))
+            .or_else(|| self.compressed_index(&sorted).map(|index| (index, Reason::Compressed)))
+            .unwrap_or((index, Reason::Order));
+        let source = sorted.remove(index);
+
+        (source, sorted, reason)
+    }
+
+    // Like `select`, but leaves the choice to the --select-cmd command if there is one,
+    // returning nothing when it fails, as acting on a group against its rules could lose the wrong copy
+    // Duplicates protected by a .dedup-policy.toml are left out either way
+    pub fn pick<'dirs>(&self, digest: &[u8], files: &'dirs [F]) -> Option<(&'dirs F, Vec<&'dirs F>, Reason)> {
+        let (source, mut duplicates, reason) = self.select(files);
+
+        let Some(ref command) = self.options.select_cmd else {
+            duplicates.retain(|dup| !self.policies.protects(dup.path()));
+
+            return Some((source, duplicates, reason));
+        };
+
+        duplicates.insert(0, source);
 
-                (first, sorted)
+        match policy::select(command, digest, &duplicates, source.path()) {
+            Ok(index) => {
+                let source = duplicates.remove(index);
+
+                duplicates.retain(|dup| !self.policies.protects(dup.path()));
+
+                Some((source, duplicates, Reason::Command))
             },
-            Keep::Last => {
-                let last = sorted.pop().unwrap();
+            Err(err) => {
+                eprintln!("Failure while running the selection command, skipping the group of: {}", source.path().to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
 
-                (last, sorted)
+                None
             }
         }
     }
 
-    pub fn execute(mut self) {
-        self.collect();
+    // Takes out the duplicates that are clones of the kept file, sharing its blocks already,
+    // as removing them frees nothing and only loses a path. Returns how many there were
+    pub fn drop_clones(source: &F, duplicates: &mut Vec<&F>) -> usize {
+        let Some(id) = source.local_path().and_then(links::clone_id) else {
+            return 0;
+        };
+        let count = duplicates.len();
 
-        self.consume();
+        duplicates.retain(|dup| dup.local_path().and_then(links::clone_id) != Some(id));
+
+        count - duplicates.len()
     }
 
-    fn collect(&mut self) {
-        let (tx, rx) = mpsc::channel();
-        let mut iterations = 0;
+    // Index of the first or last file according to --keep that a .dedup-policy.toml prefers,
+    // or else that one protects, as keeping it lets the other copies go
+    fn policy_index(&self, sorted: &[&F]) -> Option<usize> {
+        let policies: Vec<_> = sorted.iter()
+            .map(|entry| self.policies.of(entry.path()))
+            .collect();
+        let find = |wanted: fn(DirPolicy) -> bool| {
+            let mut matching = policies.iter()
+                .enumerate()
+                .filter(|&(_, &policy)| wanted(policy))
+                .map(|(index, _)| index);
+
+            match self.options.keep {
+                Keep::First => matching.next(),
+                Keep::Last => matching.next_back()
+            }
+        };
 
-        let entries: Vec<_> = self.list_entries().collect();
-        let count = entries.len();
+        find(DirPolicy::prefers).or_else(|| find(DirPolicy::protects))
+    }
 
-        println!("Found {} files", count);
+    // Index of the file in the earliest directory listed in --keep-dir-order, if any
+    // Files within the same directory are still picked according to --keep
+    fn preferred_index(&self, sorted: &[&F]) -> Option<usize> {
+        let ranked = sorted.iter()
+            .enumerate()
+            .filter_map(|(index, entry)| self.dir_rank(entry.path()).map(|rank| (rank, index)));
 
-        self.sizes.reserve(count);
+        let preferred = match self.options.keep {
+            Keep::First => ranked.min_by_key(|&(rank, index)| (rank, index)),
+            Keep::Last => ranked.min_by_key(|&(rank, index)| (rank, Reverse(index)))
+        };
 
-        for entry in entries {
-            iterations += 1;
+        preferred.map(|(_, index)| index)
+    }
 
-            let tx = tx.clone();
-            self.pool.execute(move || {
-                let metadata = match entry.metadata() {
-                    Err(_) => {
-                        tx.send(None).expect("channel is available for sending");
-                        return;
-                    },
-                    Ok(v) => v
-                };
+    // Index of the first or last file according to --keep with the strictest or loosest permissions,
+    // with --keep-permissions. Leaves the choice to the other rules when every copy has the same ones
+    fn permission_index(&self, sorted: &[&F]) -> Option<usize> {
+        let order = self.options.keep_permissions?;
+        let ranked: Vec<(usize, (bool, bool, u32))> = sorted.iter()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((index, permission_rank(entry.local_path()?)?)))
+            .collect();
 
-                tx.send(Some((metadata, entry))).expect("channel is available for sending");
-            });
+        if ranked.iter().map(|&(_, rank)| rank).all_equal() {
+            return None;
         }
 
-        eprint!("{}", ansi_escapes::CursorHide);
+        let best = match order {
+            PermissionOrder::Strictest => ranked.iter().map(|&(_, rank)| rank).min()?,
+            PermissionOrder::Loosest => ranked.iter().map(|&(_, rank)| rank).max()?
+        };
+        let mut matching = ranked.iter()
+            .filter(|&&(_, rank)| rank == best)
+            .map(|&(index, _)| index);
 
-        for (idx, (metadata, entry)) in rx.iter().take(iterations).filter_map(|x| x).enumerate() {
-            eprint!("\rProcessed {} files out of {}", idx, count);
+        match self.options.keep {
+            Keep::First => matching.next(),
+            Keep::Last => matching.next_back()
+        }
+    }
 
-            self.sizes.entry(metadata.len())
-                .or_insert_with(Vec::new)
-                .push(entry);
+    // Index of the first or last compressed file according to --keep, with --keep-compressed
+    fn compressed_index(&self, sorted: &[&F]) -> Option<usize> {
+        if !self.options.keep_compressed {
+            return None;
         }
 
-        eprintln!("{}", ansi_escapes::CursorShow);
-        println!();
+        let mut compressed = sorted.iter()
+            .enumerate()
+            .filter(|(_, entry)| compressed::is_compressed(entry.path()))
+            .map(|(index, _)| index);
+
+        match self.options.keep {
+            Keep::First => compressed.next(),
+            Keep::Last => compressed.next_back()
+        }
     }
 
-    fn shorten_path(&self, path: &Path) -> String {
-        let path_char_count = self.options.path.to_string_lossy().chars().count();
+    fn dir_rank(&self, path: &Path) -> Option<usize> {
+        self.options.keep_dir_order.iter()
+            .position(|dir| path.starts_with(self.options.path.join(dir)))
+    }
 
-        path.to_string_lossy()
-            .chars()
-            // Skip the path characters + 1 for the leading path separator
-            .skip(path_char_count + 1)
-            .collect()
+    // Why a duplicate goes instead of the kept file, in a few words, e.g. "modified 42 days before the kept file"
+    pub fn rationale(&self, reason: Reason, source: &F, dup: &F) -> String {
+        match reason {
+            Reason::Policy => String::from("a .dedup-policy.toml prefers or protects the kept file's folder"),
+            Reason::DirOrder => match (self.dir_rank(source.path()), self.dir_rank(dup.path())) {
+                (Some(kept), Some(rank)) if kept != rank => format!(
+                    "in {}, after {} in --keep-dir-order",
+                    self.options.keep_dir_order[rank].display(),
+                    self.options.keep_dir_order[kept].display()
+                ),
+                (Some(kept), None) => format!("outside {}, the first folder in --keep-dir-order with a copy", self.options.keep_dir_order[kept].display()),
+                _ => self.order_rationale(source, dup)
+            },
+            Reason::Permissions => {
+                let kept = source.local_path().and_then(permission_rank);
+                let rank = dup.local_path().and_then(permission_rank);
+
+                match self.options.keep_permissions {
+                    _ if kept == rank => self.order_rationale(source, dup),
+                    Some(PermissionOrder::Loosest) => String::from("stricter permissions than the kept file"),
+                    _ => String::from("looser permissions than the kept file")
+                }
+            },
+            Reason::Compressed if compressed::is_compressed(dup.path()) => self.order_rationale(source, dup),
+            Reason::Compressed => String::from("not compressed, unlike the kept file"),
+            Reason::Command => String::from("the selection command kept another file"),
+            Reason::Conflict => String::from("sync conflict copy of the kept file"),
+            Reason::Generation => String::from("unchanged in a newer backup generation than the kept file"),
+            Reason::Order => self.order_rationale(source, dup)
+        }
     }
 
-    fn get_true_dupes(entries: &[DirEntry]) -> (Vec<Vec<&DirEntry>>, i32) {
-        if entries.len() == 1 {
-            return (Vec::new(), 0);
+    // How a duplicate compares to the kept file by --order, which picked the kept file as the first or last
+    fn order_rationale(&self, source: &F, dup: &F) -> String {
+        let (Ok(kept), Ok(info)) = (source.info(), dup.info()) else {
+            return String::from("listed after the kept file");
+        };
+
+        match self.options.order {
+            FileOrdering::Modified => rationale::time_difference("modified", kept.modified, info.modified),
+            FileOrdering::Created => rationale::time_difference("created", kept.created, info.created),
+            FileOrdering::Name => {
+                let kept = source.path().file_name().map(paths::normalize);
+                let name = dup.path().file_name().map(paths::normalize);
+
+                match name.cmp(&kept) {
+                    cmp::Ordering::Less => String::from("name sorts before the kept file's"),
+                    cmp::Ordering::Equal => String::from("same name as the kept file, listed after it"),
+                    cmp::Ordering::Greater => String::from("name sorts after the kept file's")
+                }
+            }
         }
+    }
+}
 
-        let mut map: HashMap<Vec<u8>, Vec<&DirEntry>> = HashMap::new();
+impl Deduplicator {
+    pub fn execute(mut self) {
+        if let Some(dir) = self.options.spill_dir.clone() {
+            self.consume_external(&dir);
+            return;
+        }
 
-        for entry in entries {
-            let digest = match Self::digest(entry) {
-                None => continue,
-                Some(digest) => digest
-            };
+        self.collect();
 
-            map.entry(digest)
-                .or_insert_with(Vec::new)
-                .push(entry);
+        if self.options.case_collisions {
+            self.report_case_collisions();
         }
 
-        let mut dupes = Vec::new();
-        let mut collisions = 0;
+        self.consume();
+    }
 
-        for (_, entries) in map.into_iter() {
-            if entries.len() > 1 {
-                dupes.push(entries);
-            } else {
-                collisions += 1;
-            }
+    // Like `collect` and `consume`, but with every file, and then every digest, sorted in runs on disk
+    // under --spill-dir instead of kept in memory, so only a group at a time is ever held
+    fn consume_external(mut self, dir: &Path) {
+        let user_names = self.per_user().then(Users::load);
+        let mut tally = Tally::default();
+
+        if let Err(err) = self.sort_external(dir, &mut tally, user_names.as_ref()) {
+            eprintln!("Failure while sorting files in: {}", dir.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            return;
         }
 
-        (dupes, collisions)
+        self.finish(&tally, user_names.as_ref());
     }
 
-    fn consume(mut self) {
-        let mut duplicate_groups = 0;
-        let mut duplicate_count = 0;
-        let mut collision_count = 0;
+    fn sort_external(&mut self, dir: &Path, tally: &mut Tally, user_names: Option<&Users>) -> io::Result<()> {
+        let run_bytes = self.options.max_memory
+            .map_or(RUN_BYTES, |max| usize::try_from(max).unwrap_or(usize::MAX));
 
-        let elapsed = self.start.elapsed();
+        let walk_start = Instant::now();
+        let sizes = self.sort_by_size(dir, run_bytes)?;
+        // Metadata is read along the walk here, so it's all timed as the walk
+        self.timings.walk = walk_start.elapsed();
 
-        let mut files: Vec<_> = self.sizes.into_iter().collect();
-        self.sizes = HashMap::new();
+        let hashing_start = Instant::now();
+        let digests = self.sort_by_digest(dir, run_bytes, sizes, &mut tally.stats)?;
+        self.timings.hashing = hashing_start.elapsed();
 
-        if let Some(ref sorter) = self.options.sort_output {
-            match sorter {
-                FileOrdering::Created => {
-                    files.sort_by_cached_key(|(_, f)| f.last().unwrap().metadata().unwrap().created().unwrap())
-                }
-                FileOrdering::Modified => {
-                    files.sort_by_cached_key(|(_, f)| f.last().unwrap().metadata().unwrap().modified().unwrap())
-                },
-                FileOrdering::Name => {
-                    files.sort_by(|(_, a), (_, b)| {
-                        let a_name = a.last().unwrap().file_name();
-                        let b_name = b.last().unwrap().file_name();
+        let grouping_start = Instant::now();
+
+        for group in digests.merge()?.groups() {
+            let (key, paths) = group?;
+            let files: Vec<DirEntry> = paths.iter()
+                .filter_map(|path| DirEntry::reopen(Path::new(path)))
+                .collect();
 
-                        a_name.cmp(b_name)
-                    });
+            for (digest, group) in self.split_group(&key[spill::KEY_LEN..], files.iter().collect(), &mut tally.stats) {
+                let cloned: Vec<DirEntry> = group.into_iter().cloned().collect();
+
+                if self.handle_group(tally, user_names, &digest, &cloned) {
+                    break;
                 }
             }
+
+            if self.options.limit_groups == Some(tally.stats.duplicate_groups) {
+                break;
+            }
         }
 
-        let mut space_saved: u64 = 0;
+        self.timings.grouping = grouping_start.elapsed().saturating_sub(tally.actions);
 
-        for (size, files) in files {
-            let (dupes_vec, collisions) = Self::get_true_dupes(&files);
+        Ok(())
+    }
 
-            collision_count += collisions;
+    // Walks the folder, sorting files by their bucket
+    fn sort_by_size(&self, dir: &Path, run_bytes: usize) -> io::Result<Sorter> {
+        let mut sizes = Sorter::new(dir, "sizes", run_bytes);
+        let mut progress = (!self.options.summary_only).then(Progress::start);
+        let mut count = 0;
+        let mut placeholders = 0;
 
-            for dupes in dupes_vec {
-                let cloned: Vec<_> = dupes.into_iter().cloned().collect();
-                let (source, duplicates) = self.select(&cloned);
+        for entry in self.list_entries() {
+            count += 1;
 
-                if !self.options.quiet {
-                    println!("Found {} duplicate files:", duplicates.len() + 1);
-                    println!("Source: {}", self.shorten_path(source.path()));
+            if let Some(ref mut progress) = progress {
+                progress.update(count, format_args!("{}", self.lang.text(Message::FoundFiles(count))));
+            }
 
-                    for file in &duplicates {
-                        let short_path = self.shorten_path(file.path());
+            self.log_progress("walk", Step { files_done: count, ..Step::default() });
 
-                        println!("Copy:   {}", short_path);
-                    }
+            if !entry.file_type().is_file() {
+                continue;
+            }
 
-                    space_saved += size * duplicates.len() as u64;
+            let info = match entry.info() {
+                Ok(info) => info,
+                Err(err) => {
+                    self.failures.record(entry.path(), "metadata", &err);
+                    continue;
                 }
+            };
 
-                if self.options.delete {
-                    Self::delete(&duplicates);
-                }
+            if !self.options.hydrate && info.placeholder {
+                placeholders += 1;
+                continue;
+            }
 
-                if !self.options.quiet {
-                    println!();
-                }
+            // Runs are read back as UTF-8
+            let Some(path) = entry.path().to_str() else {
+                self.failures.record(entry.path(), "metadata", &io::Error::new(io::ErrorKind::InvalidData, "the path isn't valid UTF-8"));
+                continue;
+            };
 
-                duplicate_groups += 1;
-                duplicate_count += duplicates.len() + 1;
-            }
+            sizes.push(Bucket::of(&entry, info.size, &self.options).key().to_vec(), path.to_owned())?;
         }
 
-        println!("Summary:");
-        println!("{} duplicate groups", duplicate_groups);
-        println!("{} duplicates found", duplicate_count);
-        println!("{} size collisions", collision_count);
-        println!("{} space saved after deletion of duplicates", Self::format_size(space_saved, 2));
-        println!();
-        println!("Done in {}ms!", self.start.elapsed().as_millis());
-        println!("Scan took {}ms", elapsed.as_millis());
-    }
+        self.finish_progress("walk", Step { files_done: count, ..Step::default() });
 
-    fn format_size(bytes: u64, decimals: usize) -> String {
-        if bytes == 0 {
-            return "0 bytes".to_string();
+        if let Some(progress) = progress {
+            progress.finish();
+            println!();
+            println!("{}", self.lang.text(Message::FoundFiles(count)));
+            println!();
         }
 
-        let k = 1024;
+        if placeholders > 0 {
+            println!("{}", self.lang.text(Message::SkippedPlaceholders(placeholders)));
+            println!();
+        }
 
-        let bf = bytes as f64;
-        let kf = k as f64;
+        Ok(sizes)
+    }
 
-        let sizes = [" bytes", "kb", "mb", "gb", "tb", "pb", "eb", "zb", "yb", "bb"];
-        let i = (bf.ln() / kf.ln()).floor() as i32;
+    // Hashes the files sharing a bucket with another, sorting them by their bucket and digest
+    fn sort_by_digest(&self, dir: &Path, run_bytes: usize, sizes: Sorter, stats: &mut Stats) -> io::Result<Sorter> {
+        let mut digests = Sorter::new(dir, "digests", run_bytes);
 
-        let unit = bf / kf.powi(i);
-        // Index cannot overflow, as u64 cannot fit numbers large enough to even reach yottabytes
-        let size = sizes[i as usize];
-        let formatted = format!("{unit:.decimals$}{size}");
+        for group in sizes.merge()?.groups() {
+            let (key, paths) = group?;
+
+            if paths.len() < 2 {
+                continue;
+            }
+
+            stats.candidates += paths.len();
+
+            for path in paths {
+                self.remember(Path::new(&path));
+
+                let reading = self.watchdog.as_ref().map(|watchdog| watchdog.reading(Path::new(&path)));
+                let digest = self.digest(Path::new(&path));
+
+                drop(reading);
+
+                match digest {
+                    Ok(digest) => digests.push([&key[..], &digest].concat(), path)?,
+                    Err(err) => {
+                        self.failures.record(Path::new(&path), "hashing", &err);
+                        stats.unreadable += 1;
+                    }
+                }
+            }
+        }
 
-        return formatted;
+        Ok(digests)
     }
 
-    fn delete(duplicates: &[&DirEntry]) {
-        for dup in duplicates.iter() {
-            match fs::remove_file(dup.path()) {
-                Ok(_) => {},
+    // Files whose names only differ in case can't coexist on case-insensitive filesystems,
+    // so syncing them from a case-sensitive one will clobber or rename one of them
+    fn report_case_collisions(&mut self) {
+        let mut listed: Vec<PathBuf> = self.sizes.values()
+            .flatten()
+            .map(|entry| entry.path().to_owned())
+            .collect();
+
+        // Spilled files only need their paths here
+        if let Some(ref mut spill) = self.spill {
+            let spilled = spill.read().and_then(|records| {
+                records.map(|record| record.map(|(_, path)| path))
+                    .collect::<io::Result<Vec<_>>>()
+            });
+
+            match spilled {
+                Ok(spilled) => listed.extend(spilled),
                 Err(err) => {
-                    eprintln!("Failure while deleting: {}", dup.path().to_string_lossy());
-                    eprintln!("{:?}", err);
+                    eprintln!("Failure while reading back spilled files: {}", std::env::temp_dir().to_string_lossy());
+                    eprintln!("{err:?}");
                     eprintln!();
                 }
             }
         }
+
+        let mut names: HashMap<(&Path, String), Vec<&Path>> = HashMap::new();
+
+        for path in &listed {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+
+            names.entry((parent, paths::normalize(name).to_lowercase()))
+                .or_default()
+                .push(path);
+        }
+
+        let mut collisions: Vec<_> = names.into_values()
+            .filter(|entries| entries.len() > 1)
+            .map(|mut entries| {
+                entries.sort();
+                entries
+            })
+            .collect();
+        collisions.sort_by(|a, b| a[0].cmp(b[0]));
+
+        println!("Found {} case collisions:", collisions.len());
+
+        for entries in collisions {
+            for path in entries {
+                println!("  {}", self.shorten_path(path));
+            }
+
+            println!();
+        }
+    }
+
+    fn collect(&mut self) {
+        let walk_start = Instant::now();
+        let entries: Vec<_> = self.list_entries()
+            .enumerate()
+            .map(|(idx, entry)| {
+                self.log_progress("walk", Step { files_done: idx + 1, ..Step::default() });
+                entry
+            })
+            .collect();
+        let count = entries.len();
+
+        self.timings.walk = walk_start.elapsed();
+        self.finish_progress("walk", Step { files_done: count, ..Step::default() });
+
+        let summary_only = self.options.summary_only;
+
+        if !summary_only {
+            println!("{}", self.lang.text(Message::FoundFiles(count)));
+        }
+
+        let mut progress = (!summary_only).then(Progress::start);
+
+        let lang = self.lang;
+        let log = self.progress_log.clone();
+        let metadata_start = Instant::now();
+        let placeholders = self.scan(entries, |idx, count| {
+            if let Some(ref mut progress) = progress {
+                progress.update(idx, format_args!("{}", lang.text(Message::ProcessedFiles(idx, count))));
+            }
+
+            if let Some(ref log) = log {
+                log.update("metadata", Step { files_done: idx + 1, files_total: Some(count), ..Step::default() });
+            }
+        });
+
+        self.timings.metadata = metadata_start.elapsed();
+        self.finish_progress("metadata", Step { files_done: count, files_total: Some(count), ..Step::default() });
+
+        if let Some(progress) = progress {
+            progress.finish();
+            println!();
+        }
+
+        if placeholders > 0 {
+            println!("{}", self.lang.text(Message::SkippedPlaceholders(placeholders)));
+            println!();
+        }
+    }
+
+}
+
+impl<F: FileSource> Deduplicator<F> {
+    // Reads the metadata of every entry to sort them into buckets, reporting progress as (done, total)
+    // Returns how many cloud placeholders were skipped
+    pub fn scan(&mut self, entries: Vec<F>, mut progress: impl FnMut(usize, usize)) -> usize {
+        let (tx, rx) = mpsc::channel();
+        let mut iterations = 0;
+        let count = entries.len();
+
+        self.sizes.reserve(count);
+
+        if let (Some(_), true, None) = (self.options.max_memory, F::REOPENABLE, &self.spill) {
+            match Spill::create(&std::env::temp_dir()) {
+                Ok(spill) => self.spill = Some(spill),
+                Err(err) => {
+                    eprintln!("Failure while creating the spill file, keeping every file in memory: {}", std::env::temp_dir().to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+        }
+
+        let placeholders = Arc::new(AtomicUsize::new(0));
+
+        for (position, entry) in entries.into_iter().enumerate() {
+            iterations += 1;
+
+            let tx = tx.clone();
+            let options = Arc::clone(&self.options);
+            let placeholders = Arc::clone(&placeholders);
+            let failures = self.failures.clone();
+            self.pool.execute(move || {
+                let info = match entry.info() {
+                    Ok(info) => info,
+                    Err(err) => {
+                        failures.record(entry.path(), "metadata", &err);
+                        tx.send(None).expect("channel is available for sending");
+                        return;
+                    }
+                };
+
+                if !options.hydrate && info.placeholder {
+                    placeholders.fetch_add(1, Ordering::Relaxed);
+                    tx.send(None).expect("channel is available for sending");
+                    return;
+                }
+
+                let bucket = Bucket::of(&entry, info.size, &options);
+
+                tx.send(Some((position, bucket, entry))).expect("channel is available for sending");
+            });
+        }
+
+        for (idx, (position, bucket, entry)) in rx.iter().take(iterations).flatten().enumerate() {
+            progress(idx, count);
+
+            self.sizes_memory += entry.footprint();
+            self.memory.sizes = self.memory.sizes.max(self.sizes_memory);
+
+            self.sizes.entry(bucket)
+                .or_default()
+                .push(entry);
+
+            let first_seen = self.first_seen.entry(bucket).or_insert(position);
+            *first_seen = position.min(*first_seen);
+
+            if self.options.max_memory.is_some_and(|max| self.sizes_memory as u64 > max) {
+                self.spill_sizes();
+            }
+        }
+
+        placeholders.load(Ordering::Relaxed)
+    }
+
+    // Writes the files in the size map out to disk, keeping only how many each bucket has
+    // Files that can't be, like ones with paths that aren't valid UTF-8, stay in memory
+    fn spill_sizes(&mut self) {
+        let Some(ref mut spill) = self.spill else {
+            return;
+        };
+
+        let mut kept: Vec<(Bucket, F)> = Vec::new();
+
+        for (bucket, files) in self.sizes.drain() {
+            for file in files {
+                if file.path().to_str().is_some_and(|path| spill.write(bucket.key(), path)) {
+                    *self.spilled.entry(bucket).or_default() += 1;
+                    self.memory.spilled += 1;
+                } else {
+                    kept.push((bucket, file));
+                }
+            }
+        }
+
+        self.sizes_memory = kept.iter().map(|(_, file)| file.footprint()).sum();
+
+        for (bucket, file) in kept {
+            self.sizes.entry(bucket).or_default().push(file);
+        }
+    }
+
+    // Reads back the spilled files in buckets with more than one file, leaving out the rest
+    fn unspill(&mut self) {
+        let Some(mut spill) = self.spill.take() else {
+            return;
+        };
+        let spilled = std::mem::take(&mut self.spilled);
+
+        let read = spill.read().and_then(|records| {
+            for record in records {
+                let (key, path) = record?;
+                let bucket = Bucket::from_key(key);
+                let in_memory = self.sizes.get(&bucket).map_or(0, Vec::len);
+
+                if spilled.get(&bucket).copied().unwrap_or(0) + in_memory < 2 {
+                    continue;
+                }
+
+                if let Some(file) = F::reopen(&path) {
+                    self.sizes.entry(bucket).or_default().push(file);
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(err) = read {
+            eprintln!("Failure while reading back spilled files: {}", std::env::temp_dir().to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+        }
+    }
+
+    // Peak memory taken so far
+    pub fn memory(&self) -> Memory {
+        Memory {
+            hashes: self.hashes_memory.load(Ordering::Relaxed),
+            ..self.memory
+        }
+    }
+
+    // Takes the scanned buckets, in the order requested by --sort-output, or else --hash-order
+    pub fn take_buckets(&mut self) -> Vec<Vec<F>> {
+        self.unspill();
+
+        let mut buckets: Vec<_> = std::mem::take(&mut self.sizes).into_iter().collect();
+        let first_seen = std::mem::take(&mut self.first_seen);
+
+        // Files land in their buckets as the metadata threads get to them, and buckets come out of a hash map,
+        // so both are put in walk order first, which the sorts below keep for ties
+        if self.options.deterministic {
+            for (_, files) in &mut buckets {
+                files.sort_by(|a, b| a.path().cmp(b.path()));
+            }
+
+            buckets.sort_by_key(|(bucket, _)| first_seen.get(bucket).copied());
+        }
+
+        match self.options.hash_order {
+            HashOrder::WalkOrder => {
+                buckets.sort_by_key(|(bucket, _)| first_seen.get(bucket).copied());
+            },
+            HashOrder::LargestFirst => {
+                buckets.sort_by_cached_key(|(bucket, files)| Reverse(bucket.file_size(files)));
+            },
+            HashOrder::SmallestFirst => {
+                buckets.sort_by_cached_key(|(bucket, files)| bucket.file_size(files));
+            }
+        }
+
+        let mut files: Vec<_> = buckets.into_iter()
+            .map(|(_, files)| files)
+            .collect();
+
+        if let Some(ref sorter) = self.options.sort_output {
+            match sorter {
+                OutputOrdering::Created => {
+                    files.sort_by_cached_key(|f| f.last().unwrap().info().ok().and_then(|info| info.created));
+                }
+                OutputOrdering::Modified => {
+                    files.sort_by_cached_key(|f| f.last().unwrap().info().ok().and_then(|info| info.modified));
+                },
+                OutputOrdering::Name => {
+                    files.sort_by_cached_key(|f| f.last().unwrap().path().file_name().map(paths::normalize));
+                },
+                // Buckets may still hold files that turn out to differ, so this is only an upper bound
+                OutputOrdering::Size => {
+                    files.sort_by_cached_key(|f| Reverse(reclaimable(f)));
+                }
+            }
+        }
+
+        files
+    }
+
+    fn shorten_path(&self, path: &Path) -> String {
+        paths::relative_display(&self.options.path, path)
+    }
+
+    // Hashes a bucket of candidates, returning the groups of identical files among them,
+    // along with the candidate counters of the run's statistics
+    pub fn get_true_dupes<'dirs>(&self, entries: &'dirs [F]) -> (Vec<DuplicateGroup<'dirs, F>>, Stats) {
+        self.try_get_true_dupes(entries, true).unwrap_or_default()
+    }
+
+    // Like `get_true_dupes`, but gives up on the bucket as soon as a file fails with an error that may go away,
+    // unless this is the last attempt, so it can be retried later as a whole
+    fn try_get_true_dupes<'dirs>(&self, entries: &'dirs [F], last_attempt: bool) -> Option<(Vec<DuplicateGroup<'dirs, F>>, Stats)> {
+        let mut stats = Stats::default();
+
+        // Folders get bucketed too, for --case-collisions, but have no contents to compare
+        let files: Vec<&F> = entries.iter()
+            .filter(|entry| entry.is_file())
+            .collect();
+
+        if files.len() < 2 {
+            return Some((Vec::new(), stats));
+        }
+
+        stats.candidates = files.len();
+
+        let mut map: HashMap<Vec<u8>, Vec<&F>> = HashMap::new();
+        let mut unreadable = Vec::new();
+
+        for (n, entry) in files.iter().enumerate() {
+            if self.options.prefetch {
+                if let Some(next) = files.get(n + 1).and_then(|next| next.local_path()) {
+                    readahead::prefetch(next);
+                }
+            }
+
+            if let Some(path) = entry.local_path() {
+                self.remember(path);
+            }
+
+            let reading = self.watchdog.as_ref().map(|watchdog| watchdog.reading(entry.path()));
+            let digest = self.digest_file(entry);
+
+            drop(reading);
+
+            let digest = match digest {
+                Ok(digest) => {
+                    if let Some(path) = entry.local_path() {
+                        if self.is_plain_digest(path) {
+                            self.plain_digests.lock()
+                                .expect("plain digests are never poisoned")
+                                .insert(path.to_owned(), digest.clone());
+                        }
+
+                        if self.options.session.is_some() {
+                            self.digests.lock()
+                                .expect("digests are never poisoned")
+                                .insert(path.to_owned(), digest.clone());
+                        }
+                    }
+
+                    digest
+                },
+                // Inaccessible files are left out, and only reported with --summary-format json
+                Err(err) if !last_attempt && failures::classify(&err) == "transient" => return None,
+                Err(err) => {
+                    unreadable.push((entry.path(), err));
+                    continue;
+                }
+            };
+
+            map.entry(digest)
+                .or_default()
+                .push(entry);
+        }
+
+        let hashes_memory = map.keys()
+            .map(|digest| digest.len() + mem::size_of::<(Vec<u8>, Vec<&F>)>())
+            .sum::<usize>() + files.len() * mem::size_of::<&F>();
+
+        self.hashes_memory.fetch_max(hashes_memory, Ordering::Relaxed);
+
+        // Only recorded once the bucket is done with, so retried buckets don't report them twice
+        for (path, err) in unreadable {
+            self.failures.record(path, "hashing", &err);
+            stats.unreadable += 1;
+        }
+
+        let mut digests: Vec<_> = map.into_iter().collect();
+
+        if self.options.deterministic {
+            digests.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        }
+
+        let mut dupes = Vec::new();
+
+        for (digest, entries) in digests {
+            dupes.extend(self.split_group(&digest, entries, &mut stats));
+        }
+
+        Some((dupes, stats))
+    }
+
+    // Splits files sharing a digest into the groups of duplicates among them, by contents with --forensic
+    // and by owner with --per-user, counting the ones left on their own
+    fn split_group<'dirs>(&self, digest: &[u8], entries: Vec<&'dirs F>, stats: &mut Stats) -> Vec<DuplicateGroup<'dirs, F>> {
+        let groups = if self.options.forensic && entries.len() > 1 {
+            self.split_by_content(digest, entries, stats)
+        } else {
+            vec![entries]
+        };
+
+        let mut dupes = Vec::new();
+
+        for entries in groups {
+            if entries.len() == 1 {
+                stats.false_candidates += 1;
+            } else if self.per_user() {
+                // Nobody gets to delete another user's files, even when they hold the same contents
+                let owned = entries.into_iter()
+                    .into_group_map_by(|entry| entry.local_path().and_then(owners::owner))
+                    .into_iter()
+                    .sorted_unstable_by_key(|(owner, _)| *owner)
+                    .map(|(_, entries)| entries)
+                    .filter(|entries| entries.len() > 1)
+                    .map(|entries| (digest.to_owned(), entries));
+
+                dupes.extend(owned);
+            } else {
+                dupes.push((digest.to_owned(), entries));
+            }
+        }
+
+        dupes
+    }
+
+    // Byte-compares files that share a digest, splitting them by actual content and reporting any true collision
+    // Files hashed after normalization legitimately differ in their raw bytes, so those are left as they are
+    fn split_by_content<'dirs>(&self, digest: &[u8], entries: Vec<&'dirs F>, stats: &mut Stats) -> Vec<Vec<&'dirs F>> {
+        if entries.iter().filter_map(|entry| entry.local_path()).any(|path| self.is_normalized(path)) {
+            return vec![entries];
+        }
+
+        let mut groups: Vec<Vec<&F>> = Vec::new();
+
+        'entries: for entry in entries {
+            for group in &mut groups {
+                match forensic::first_difference(group[0], entry) {
+                    Ok(None) => {
+                        group.push(entry);
+                        continue 'entries;
+                    },
+                    Ok(Some(offset)) => forensic::report(digest, group[0], entry, offset),
+                    Err(err) => {
+                        eprintln!("Failure while comparing: {}", entry.path().to_string_lossy());
+                        eprintln!("{err:?}");
+                        eprintln!();
+
+                        self.failures.record(entry.path(), "comparing", &err);
+
+                        stats.unreadable += 1;
+                        continue 'entries;
+                    }
+                }
+            }
+
+            if !groups.is_empty() {
+                stats.hash_collisions += 1;
+            }
+
+            groups.push(vec![entry]);
+        }
+
+        groups
+    }
+
+    // Hashes every candidate file up front through io_uring, so that reads for many files are in flight
+    // at once across buckets, which otherwise tend to hold just a couple of files each
+    // Normalized files are left to be hashed as usual
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    fn prefill_digests(&mut self, buckets: &[Vec<F>]) {
+        if !self.options.io_uring || matches!(self.options.mode, Mode::QuickSignature) {
+            return;
+        }
+
+        let paths: Vec<&Path> = buckets.iter()
+            .filter(|files| files.len() > 1)
+            .flatten()
+            .filter(|entry| entry.is_file())
+            .filter_map(FileSource::local_path)
+            .filter(|path| !self.is_normalized(path))
+            .collect();
+
+        match uring::digests(&paths, self.options.hash_algo.algorithm(), self.read_buffer) {
+            Ok(digests) => {
+                self.batched = paths.into_iter()
+                    .zip(digests)
+                    .filter_map(|(path, digest)| Some((path.to_owned(), digest?)))
+                    .collect();
+            },
+            Err(err) => {
+                eprintln!("Could not set up io_uring, hashing files one at a time");
+                eprintln!("{err:?}");
+                eprintln!();
+            }
+        }
+    }
+
+    fn is_normalized(&self, path: &Path) -> bool {
+        (self.options.ignore_tags && audio::is_audio(path))
+            || (self.options.normalize_pdfs && pdf::is_pdf(path))
+            || (self.options.normalize_office && office::is_office(path))
+            || (self.options.normalize_images && images::is_image(path))
+            || (self.options.decompress_before_hash && compressed::is_compressed(path))
+            || (!self.options.text_normalize.is_empty() && text::is_text(path))
+    }
+
+    // Whether the digest of a file is of its bytes as they are on the live volume, and with the hash
+    // --export-checksums lists, so the manifest can take it as is
+    fn is_plain_digest(&self, path: &Path) -> bool {
+        checksums::reuses_digests(&self.options)
+            && self.snapshot.is_none()
+            && !self.options.alternate_streams
+            && !self.is_normalized(path)
+    }
+
+    fn per_user(&self) -> bool {
+        self.options.per_user || self.options.user_scripts.is_some()
+    }
+}
+
+impl Deduplicator {
+    // Only files sharing a bucket with another can be duplicates, so the cheap metadata pass
+    // is done in full, and only a stratified random sample of the candidate buckets gets hashed
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    pub fn estimate(mut self, samples: usize, seed: Option<u64>) {
+        self.collect();
+
+        let seed = seed.unwrap_or_else(Random::seed_from_time);
+        let mut random = Random::new(seed);
+
+        let mut classes: HashMap<u32, Vec<Vec<DirEntry>>> = HashMap::new();
+        let mut candidate_files = 0;
+
+        for (bucket, files) in std::mem::take(&mut self.sizes) {
+            if files.len() > 1 {
+                candidate_files += files.len();
+                classes.entry(bucket.class())
+                    .or_default()
+                    .push(files);
+            }
+        }
+
+        let candidates: usize = classes.values().map(Vec::len).sum();
+        let mut strata = Vec::new();
+        let mut hashed = 0;
+
+        println!("{candidate_files} files in {candidates} groups share their size with another file");
+
+        let mut progress = Progress::start();
+
+        for (_, mut buckets) in classes {
+            // Proportional allocation, sampling at least two buckets per class so its variance can be measured
+            let count = (samples * buckets.len()).div_ceil(candidates).max(2).min(buckets.len());
+
+            random.shuffle(&mut buckets);
+
+            let measured = buckets[..count].iter()
+                .map(|files| {
+                    hashed += 1;
+                    progress.update(hashed, format_args!("Hashed {hashed} sampled groups"));
+
+                    self.wasted_bytes(files) as f64
+                })
+                .collect();
+
+            strata.push(Stratum {
+                population: buckets.len(),
+                samples: measured
+            });
+        }
+
+        progress.finish();
+        println!();
+
+        let (total, margin) = estimate::total(&strata);
+        let low = (total - margin).max(0.0) as u64;
+        let high = (total + margin) as u64;
+
+        println!("Sampled {hashed} of {candidates} groups (seed {seed})");
+        println!("Estimated {} duplicated", Self::format_size(total as u64, 2));
+        println!("95% confidence between {} and {}", Self::format_size(low, 2), Self::format_size(high, 2));
+        println!();
+        println!("Done in {}ms!", self.start.elapsed().as_millis());
+    }
+
+    // Lists every file under the root with the same contents as the given file, or with the given hex digest
+    // Only files of the same size need hashing when given a file, while a digest alone means hashing them all
+    pub fn find_copies(self, query: &str) {
+        let path = Path::new(query);
+        let wanted = if path.is_file() {
+            match self.digest(path).and_then(|digest| Ok((hex(&digest), Some(fs::metadata(path)?.len())))) {
+                Ok(wanted) => wanted,
+                Err(err) => {
+                    eprintln!("Failure while hashing the query file: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+
+                    return;
+                }
+            }
+        } else if query.len() == self.options.hash_algo.algorithm().output_len * 2 && query.chars().all(|c| c.is_ascii_hexdigit()) {
+            (query.to_ascii_lowercase(), None)
+        } else {
+            eprintln!("`{query}` is neither a file nor a {} digest", self.options.hash_algo.name());
+
+            return;
+        };
+
+        let (digest, size) = wanted;
+        let query_path = paths::absolute(path);
+        let mut progress = Progress::start();
+        let mut hashed = 0;
+        let mut copies = Vec::new();
+
+        for entry in self.list_entries() {
+            if !entry.file_type().is_file() || paths::absolute(entry.path()) == query_path {
+                continue;
+            }
+
+            if size.is_some_and(|size| entry.metadata().map(|metadata| metadata.len()).ok() != Some(size)) {
+                continue;
+            }
+
+            hashed += 1;
+            progress.update(hashed, format_args!("Hashed {hashed} files"));
+
+            match self.digest(entry.path()) {
+                Ok(found) if hex(&found) == digest => copies.push(entry),
+                Ok(_) => {},
+                Err(err) => {
+                    eprintln!("Failure while hashing: {}", entry.path().to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+
+                    self.failures.record(entry.path(), "hashing", &err);
+                }
+            }
+        }
+
+        progress.finish();
+
+        println!("{} files with the same contents as {query}:", copies.len());
+
+        for copy in copies {
+            println!("{}", paths::display(copy.path()));
+        }
+    }
+
+    // Bytes that would be freed by keeping a single copy of each duplicate in the bucket
+    fn wasted_bytes(&self, files: &[DirEntry]) -> u64 {
+        let (dupes_vec, _) = self.get_true_dupes(files);
+
+        dupes_vec.iter()
+            .map(|(_, dupes)| reclaimable(dupes.iter().copied()))
+            .sum()
+    }
+
+    // Matches every sync conflict copy against the file it was made of, and handles the ones with the same contents
+    // like duplicates of it, so the original is always the one kept and unrelated copies are never touched
+    pub fn clean_conflicts(mut self) {
+        let mut conflicts: BTreeMap<PathBuf, Vec<DirEntry>> = BTreeMap::new();
+
+        for entry in self.list_entries().filter(|entry| entry.file_type().is_file()) {
+            let base = entry.file_name()
+                .to_str()
+                .and_then(conflicts::base_name)
+                .map(|name| entry.path().with_file_name(name));
+
+            if let Some(base) = base {
+                conflicts.entry(base).or_default().push(entry);
+            }
+        }
+
+        println!("{}", self.lang.text(Message::FoundFiles(conflicts.values().map(Vec::len).sum())));
+        println!();
+
+        let mut tally = Tally::default();
+
+        for (base, copies) in conflicts {
+            let Some(source) = WalkDir::new(&base).max_depth(0).into_iter().find_map(Result::ok) else {
+                continue;
+            };
+
+            if !source.file_type().is_file() {
+                continue;
+            }
+
+            tally.stats.candidates += copies.len() + 1;
+
+            let Some(digest) = self.conflict_digest(&source, &mut tally.stats) else {
+                continue;
+            };
+
+            let size = source.metadata().map(|metadata| metadata.len()).ok();
+            let matching: Vec<&DirEntry> = copies.iter()
+                .filter(|copy| !self.policies.protects(copy.path()))
+                .filter(|copy| copy.metadata().ok().map(|metadata| metadata.len()) == size)
+                .filter(|copy| self.conflict_digest(copy, &mut tally.stats).is_some_and(|copy_digest| copy_digest == digest))
+                .collect();
+
+            // The original counts as unique too when none of its copies match it
+            tally.stats.false_candidates += copies.len() - matching.len() + usize::from(matching.is_empty());
+
+            if !matching.is_empty() && self.handle_kept(&mut tally, &digest, &source, &matching, Reason::Conflict) {
+                break;
+            }
+        }
+
+        self.finish(&tally, None);
+    }
+
+    fn conflict_digest(&self, file: &DirEntry, stats: &mut Stats) -> Option<Vec<u8>> {
+        self.remember(file.path());
+
+        match self.digest(file.path()) {
+            Ok(digest) => Some(digest),
+            Err(err) => {
+                eprintln!("Failure while hashing a sync conflict copy or its original: {}", file.path().to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                self.failures.record(file.path(), "hashing", &err);
+                stats.unreadable += 1;
+
+                None
+            }
+        }
+    }
+
+    // Same as `handle_group`, but the given file is always the one kept, like the original of sync conflict copies
+    fn handle_kept(&self, tally: &mut Tally, digest: &[u8], source: &DirEntry, copies: &[&DirEntry], reason: Reason) -> bool {
+        let listing = !self.options.quiet && !self.options.summary_only;
+        let group = tally.stats.duplicate_groups + 1;
+
+        let reasons: Vec<String> = copies.iter()
+            .map(|copy| self.rationale(reason, source, copy))
+            .collect();
+
+        if listing {
+            self.print_group(group, source, copies, if self.options.verbose { &reasons } else { &[] }, None);
+        }
+
+        tally.stats += Self::group_stats(copies);
+
+        let copy_paths: Vec<&Path> = copies.iter()
+            .map(|copy| copy.path())
+            .collect();
+
+        let actions_start = Instant::now();
+
+        if self.skips_group(group, copies.len() + 1) {
+            // Still listed and counted, only left alone
+        } else if self.options.write_plan.is_some() {
+            tally.planned.push(plan::group_record(group, digest, source.path(), &copy_paths, &reasons));
+        } else {
+            self.act(group, digest, source.path(), &copy_paths);
+        }
+
+        tally.actions += actions_start.elapsed();
+
+        if listing {
+            println!();
+        }
+
+        if self.options.limit_groups == Some(tally.stats.duplicate_groups) {
+            println!("{}", self.lang.text(Message::Stopped(tally.stats.duplicate_groups)));
+            println!();
+
+            return true;
+        }
+
+        false
+    }
+
+    // Treats the folders in the root with a date in their name as generations of the same backup, and compares each file
+    // with the same path in the generation before it. A file unchanged across a run of generations is kept in the oldest
+    // of them and its copies in the newer ones are handled as its duplicates, so --hardlink links them like rsnapshot would have
+    pub fn link_generations(mut self) {
+        let mut generations: Vec<(u64, DirEntry)> = Vec::new();
+        let mut undated = 0;
+
+        let folders = WalkDir::new(&self.options.path)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_dir());
+
+        for folder in folders {
+            match folder.file_name().to_str().and_then(generations::timestamp) {
+                Some(timestamp) => generations.push((timestamp, folder)),
+                None => undated += 1
+            }
+        }
+
+        generations.sort_by(|(a, a_folder), (b, b_folder)| a.cmp(b).then_with(|| a_folder.file_name().cmp(b_folder.file_name())));
+
+        if let (Some((_, first)), Some((_, last))) = (generations.first(), generations.last()) {
+            println!("Found {} backup generations, from {} to {}", generations.len(), paths::display(first.file_name().as_ref()), paths::display(last.file_name().as_ref()));
+        } else {
+            println!("Found no folders with a date in their name to treat as backup generations");
+        }
+
+        if undated > 0 {
+            println!("Leaving out {undated} folders without a date in their name");
+        }
+
+        println!();
+
+        let index: HashMap<&std::ffi::OsStr, usize> = generations.iter()
+            .enumerate()
+            .map(|(index, (_, folder))| (folder.file_name(), index))
+            .collect();
+        let mut files: BTreeMap<PathBuf, Vec<(usize, DirEntry)>> = BTreeMap::new();
+
+        for entry in self.list_entries().filter(|entry| entry.file_type().is_file()) {
+            let Ok(relative) = entry.path().strip_prefix(&self.options.path) else {
+                continue;
+            };
+            let mut components = relative.components();
+            let Some(generation) = components.next().and_then(|folder| index.get(folder.as_os_str())) else {
+                continue;
+            };
+
+            files.entry(components.as_path().to_owned())
+                .or_default()
+                .push((*generation, entry));
+        }
+
+        let mut tally = Tally::default();
+
+        'files: for (_, mut copies) in files {
+            if copies.len() < 2 {
+                continue;
+            }
+
+            copies.sort_by_key(|(generation, _)| *generation);
+            tally.stats.candidates += copies.len();
+
+            // Each run of generations the file is unchanged in, from the one it last changed in
+            let mut runs: Vec<(&DirEntry, Vec<u8>, Vec<&DirEntry>)> = Vec::new();
+
+            for (_, copy) in &copies {
+                let mut copy_digest = Vec::new();
+                let unchanged = match runs.last_mut() {
+                    Some((kept, digest, _)) if kept.metadata().ok().map(|metadata| metadata.len()) == copy.metadata().ok().map(|metadata| metadata.len()) => {
+                        if digest.is_empty() {
+                            match self.generation_digest(kept, &mut tally.stats) {
+                                Some(kept_digest) => *digest = kept_digest,
+                                None => continue 'files
+                            }
+                        }
+
+                        match self.generation_digest(copy, &mut tally.stats) {
+                            Some(hashed) => copy_digest = hashed,
+                            None => continue 'files
+                        }
+
+                        copy_digest == *digest
+                    },
+                    _ => false
+                };
+
+                match runs.last_mut() {
+                    Some((_, _, run)) if unchanged => run.push(copy),
+                    // Hashed once the next generation's copy turns out to have the same size, if it wasn't already
+                    _ => runs.push((copy, copy_digest, Vec::new()))
+                }
+            }
+
+            for (kept, digest, run) in runs {
+                // Copies linked to the kept file by an earlier run, or by the backup tool itself, free nothing
+                let unlinked: Vec<&DirEntry> = run.into_iter()
+                    .filter(|copy| !self.policies.protects(copy.path()))
+                    .filter(|copy| !links::same_file(kept.path(), copy.path()))
+                    .collect();
+
+                if unlinked.is_empty() {
+                    tally.stats.false_candidates += 1;
+                    continue;
+                }
+
+                if self.handle_kept(&mut tally, &digest, kept, &unlinked, Reason::Generation) {
+                    self.finish(&tally, None);
+                    return;
+                }
+            }
+        }
+
+        self.finish(&tally, None);
+    }
+
+    fn generation_digest(&self, file: &DirEntry, stats: &mut Stats) -> Option<Vec<u8>> {
+        self.remember(file.path());
+
+        match self.digest(file.path()) {
+            Ok(digest) => Some(digest),
+            Err(err) => {
+                eprintln!("Failure while hashing a file of a backup generation: {}", file.path().to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                self.failures.record(file.path(), "hashing", &err);
+                stats.unreadable += 1;
+
+                None
+            }
+        }
+    }
+
+    // The groups picked by --sample-groups, along with the seed to pick the same ones again
+    fn print_sample(&self, sample: Option<&Sample<SampledGroup>>, user_names: Option<&Users>) {
+        let Some(sample) = sample else {
+            return;
+        };
+        let picked = sample.picked();
+
+        println!("A sample of {} of {} duplicate groups (seed {}):", picked.len(), sample.seen(), sample.seed());
+        println!();
+
+        for sampled in picked {
+            let duplicates: Vec<&DirEntry> = sampled.duplicates.iter().collect();
+
+            self.print_group(sampled.group, &sampled.source, &duplicates, &sampled.reasons, user_names.zip(sampled.owner));
+            println!();
+        }
+    }
+
+    // Groups of generated files, one line per family of folders they're spread across, largest first
+    fn print_generated(&self, generated: &BTreeMap<Vec<PathBuf>, Stats>) {
+        let mut families: Vec<_> = generated.iter().collect();
+
+        families.sort_by_key(|(_, stats)| Reverse(stats.reclaimable_bytes));
+
+        for (folders, stats) in families {
+            let folders: Vec<String> = folders.iter()
+                .map(|folder| self.shorten_path(folder))
+                .collect();
+
+            println!(
+                "Generated files, {} duplicate groups of {} files ({}) across: {}",
+                stats.duplicate_groups,
+                stats.duplicate_files,
+                Self::format_size(stats.reclaimable_bytes, 2),
+                folders.join(", ")
+            );
+        }
+
+        if !generated.is_empty() {
+            println!();
+        }
+    }
+
+    // Whether a group has more files than --skip-groups-larger-than, which are more likely generated data
+    // than copies anyone made, so they're left out of plans and actions for a person to look at
+    fn skips_group(&self, group: usize, files: usize) -> bool {
+        let skips = self.options.skip_groups_larger_than.is_some_and(|max| files > max);
+
+        if skips && !self.options.quiet {
+            println!("Leaving group {group} alone, its {files} files are more than --skip-groups-larger-than");
+        }
+
+        skips
+    }
+
+    // Whether deleting a group's duplicates would go past --max-delete-files or --max-delete-bytes, which limit
+    // how much a misconfigured run can take out. Once a group would, every group after it is left alone too
+    fn exceeds_delete_budget(&self, group: usize, duplicates: &[&Path]) -> bool {
+        let (max_files, max_bytes) = (self.options.max_delete_files, self.options.max_delete_bytes);
+
+        if !self.options.delete || (max_files.is_none() && max_bytes.is_none()) {
+            return false;
+        }
+
+        let bytes: u64 = duplicates.iter()
+            .filter_map(|dup| fs::symlink_metadata(dup).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let mut budget = self.delete_budget.lock().expect("the delete budget is never poisoned");
+        let spent = budget.map(|(files, total)| (files + duplicates.len(), total + bytes));
+        let exceeds = spent.is_none_or(|(files, total)| max_files.is_some_and(|max| files > max) || max_bytes.is_some_and(|max| total > max));
+
+        if exceeds && budget.is_some() && !self.options.quiet {
+            println!("Stopped deleting at group {group}, its duplicates would go past --max-delete-files or --max-delete-bytes, the groups after it are only listed");
+        } else if exceeds && !self.options.quiet {
+            println!("Leaving group {group} alone, the deletion budget is used up");
+        }
+
+        *budget = if exceeds { None } else { spent };
+        drop(budget);
+
+        exceeds
+    }
+
+    // Reasons are printed after each duplicate when given, in the same order
+    fn print_group(&self, group: usize, source: &DirEntry, duplicates: &[&DirEntry], reasons: &[String], owner: Option<(&Users, u32)>) {
+        if matches!(self.options.mode, Mode::QuickSignature) {
+            println!("{}", self.lang.text(Message::ProbableFiles(group, duplicates.len() + 1)));
+        } else {
+            println!("{}", self.lang.text(Message::DuplicateFiles(group, duplicates.len() + 1)));
+        }
+
+        if let Some((names, uid)) = owner {
+            println!("{}", self.lang.text(Message::Owner(&names.name(uid))));
+        }
+
+        println!("{}", self.lang.text(Message::Source(&self.shorten_path(source.path()))));
+
+        for (index, file) in duplicates.iter().enumerate() {
+            let short_path = self.shorten_path(file.path());
+
+            match reasons.get(index) {
+                Some(reason) => println!("{} ({reason})", self.lang.text(Message::Copy(&short_path))),
+                None => println!("{}", self.lang.text(Message::Copy(&short_path)))
+            }
+        }
+    }
+
+    // Counters of a single group, given the files that aren't kept
+    fn group_stats(duplicates: &[&DirEntry]) -> Stats {
+        Stats {
+            duplicate_groups: 1,
+            duplicate_files: duplicates.len() + 1,
+            reclaimable_bytes: duplicates.iter()
+                .filter_map(|dup| dup.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum(),
+            ..Stats::default()
+        }
+    }
+
+    // Links, deletes or renames the duplicates of a group right away, as requested, unless deleting them would go past
+    // the deletion budget, returning the ones that weren't left alone
+    pub fn act<'a>(&self, group: usize, digest: &[u8], source: &Path, duplicates: &[&'a Path]) -> Vec<&'a Path> {
+        // Report runs only list groups, so there's nothing to re-check
+        if !self.acts() {
+            return Vec::new();
+        }
+
+        if self.changed_since_scan(source) {
+            self.say(&format!("Skipping the group of {}, the kept file changed since it was scanned", source.display()));
+            return Vec::new();
+        }
+
+        if self.options.paranoid && !self.source_unchanged(digest, source) {
+            return Vec::new();
+        }
+
+        let unchanged = self.actionable(source, duplicates);
+
+        // Only what's left counts against the budget
+        if self.exceeds_delete_budget(group, &unchanged) {
+            return Vec::new();
+        }
+        let duplicates = &unchanged[..];
+
+        if self.options.hardlink || self.options.reflink {
+            self.link(source, duplicates);
+        } else if self.options.delete {
+            Self::delete(duplicates, &self.failures, &self.throttle, self.trash.as_ref(), self.options.preserve_dir_times);
+            self.deleted_from(duplicates);
+        } else if let Some(ref pattern) = self.options.rename_dupes {
+            Self::rename(pattern, digest, duplicates, &self.failures);
+        }
+
+        unchanged
+    }
+
+    // The duplicates of a group that may be acted on, leaving alone the ones that are protected, changed since they
+    // were scanned, or fall outside --only-older-copies and --only-delete-older-than
+    fn actionable<'a>(&self, source: &Path, duplicates: &[&'a Path]) -> Vec<&'a Path> {
+        let source_modified = fs::metadata(source).and_then(|metadata| metadata.modified()).ok();
+
+        duplicates.iter()
+            .copied()
+            .filter(|dup| {
+                // Policies may have been added since, for plans and served groups
+                if self.policies.protects(dup) {
+                    self.say(&format!("Skipping {}, a .dedup-policy.toml protects it", dup.display()));
+                    return false;
+                }
+
+                if self.changed_since_scan(dup) {
+                    self.say(&format!("Skipping {}, it changed since it was scanned", dup.display()));
+                    return false;
+                }
+
+                // Copies with the same timestamp may well be the original, as far as anyone can tell
+                if self.options.only_older_copies {
+                    let modified = fs::metadata(dup).and_then(|metadata| metadata.modified()).ok();
+
+                    if !matches!((modified, source_modified), (Some(modified), Some(source)) if modified < source) {
+                        self.say(&format!("Skipping {}, it isn't older than the kept file", dup.display()));
+                        return false;
+                    }
+                }
+
+                // Recent copies may still be in use, like in a folder files are coming into
+                if let Some(age) = self.options.only_delete_older_than {
+                    let modified = fs::metadata(dup).and_then(|metadata| metadata.modified()).ok();
+
+                    if modified.and_then(|modified| modified.elapsed().ok()).is_none_or(|elapsed| elapsed <= age) {
+                        self.say(&format!("Skipping {}, it was modified less than --only-delete-older-than ago", dup.display()));
+                        return false;
+                    }
+                }
+
+                true
+            })
+            .collect()
+    }
+
+    // Switches to deleting or renaming duplicates, for the server, which scans first and is told what to do after.
+    // It answers over stdout, so nothing else is printed there
+    pub fn set_action(&mut self, delete: bool, rename_dupes: Option<String>) {
+        let options = Arc::make_mut(&mut self.options);
+
+        options.delete = delete;
+        options.rename_dupes = rename_dupes;
+        options.hardlink = false;
+        options.reflink = false;
+        options.quiet = true;
+
+        self.throttle = Throttle::new(&self.options);
+        self.trash = Trash::new(&self.options);
+        // Earlier ones were already answered with
+        self.failures = Failures::default();
+    }
+
+    // Deletes files found to be copies of a kept one by other means than hashing them both, like the ones identical to
+    // packaged files or thumbnails of larger images, through the same checks as the duplicates of a group
+    pub fn delete_unchanged(&self, group: usize, kept: &Path, paths: &[&Path]) {
+        if self.changed_since_scan(kept) {
+            self.say(&format!("Skipping the copies of {}, it changed since it was scanned", kept.display()));
+            return;
+        }
+
+        let unchanged = self.actionable(kept, paths);
+
+        if self.exceeds_delete_budget(group, &unchanged) {
+            return;
+        }
+
+        Self::delete(&unchanged, &self.failures, &self.throttle, self.trash.as_ref(), self.options.preserve_dir_times);
+    }
+
+    // Why files are left alone, unless --quiet
+    fn say(&self, message: &str) {
+        if !self.options.quiet {
+            println!("{message}");
+        }
+    }
+
+    // Whether duplicates are deleted, renamed or linked, rather than only listed
+    fn acts(&self) -> bool {
+        self.options.delete || self.options.rename_dupes.is_some() || self.options.hardlink || self.options.reflink
+    }
+
+    // Whether a file was written to or replaced since it was hashed, in which case its contents may no longer match
+    fn changed_since_scan(&self, path: &Path) -> bool {
+        let scanned = self.scanned.lock()
+            .expect("scanned files are never poisoned")
+            .get(path)
+            .copied();
+
+        let Some((size, modified)) = scanned else {
+            return false;
+        };
+
+        fs::symlink_metadata(path).map_or(true, |metadata| metadata.len() != size || metadata.modified().ok() != modified)
+    }
+
+    // Hashes the kept file again right before acting on its duplicates, for --paranoid,
+    // as it may have been written to since it was hashed, which would leave no copy of the old contents
+    fn source_unchanged(&self, digest: &[u8], source: &Path) -> bool {
+        match self.hash(source) {
+            Ok(current) if current == digest => true,
+            Ok(_) => {
+                self.say(&format!("Skipping the group of {}, the kept file changed since it was hashed", source.display()));
+
+                false
+            },
+            Err(err) => {
+                eprintln!("Failure while hashing the kept file again, skipping its group: {}", source.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                self.failures.record(source, "verifying", &err);
+
+                false
+            }
+        }
+    }
+
+    fn consume(mut self) {
+        let mut hashing = Stats::default();
+        let mut tally = Tally::default();
+
+        let grouping_start = Instant::now();
+        let buckets = self.take_buckets();
+        let mut grouping = grouping_start.elapsed();
+
+        if self.options.export_checksums.is_some() || self.options.session.is_some() {
+            self.all_files = buckets.iter()
+                .flatten()
+                .filter(|entry| entry.is_file())
+                .map(|entry| entry.path().to_owned())
+                .collect();
+        }
+        let mut hashing_time = Duration::ZERO;
+
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        {
+            let prefill_start = Instant::now();
+            self.prefill_digests(&buckets);
+            hashing_time += prefill_start.elapsed();
+        }
+
+        let user_names = self.per_user().then(Users::load);
+
+        // Normalized buckets hold files of different sizes, but going by the first is close enough for progress
+        let bucket_bytes = |files: &[DirEntry]| files.first()
+            .and_then(|file| file.info().ok())
+            .map_or(0, |info| info.size * files.len() as u64);
+        let mut hashed = Step {
+            files_total: Some(buckets.iter().map(Vec::len).sum()),
+            bytes_total: self.progress_log.as_ref().map(|_| buckets.iter().map(|files| bucket_bytes(files)).sum()),
+            ..Step::default()
+        };
+
+        // Buckets with files that failed for reasons that may go away are retried after every other one,
+        // along with their attempt, waiting longer before each round of retries
+        let deferred = RefCell::new(VecDeque::new());
+        let pending = buckets.into_iter()
+            .map(|files| (0, files))
+            .chain(iter::from_fn(|| deferred.borrow_mut().pop_front()));
+        let mut waited = 0;
+
+        let mut groups: Box<dyn Iterator<Item = (Vec<u8>, Vec<DirEntry>)>> = Box::new(pending.flat_map(|(attempt, files)| {
+            if attempt > waited {
+                thread::sleep(RETRY_DELAY * 2_u32.pow(attempt - 1));
+                waited = attempt;
+            }
+
+            let hashing_start = Instant::now();
+            let Some((dupes_vec, bucket_stats)) = self.try_get_true_dupes(&files, attempt == self.options.retries) else {
+                hashing_time += hashing_start.elapsed();
+                deferred.borrow_mut().push_back((attempt + 1, files));
+
+                return Vec::new();
+            };
+
+            hashing += bucket_stats;
+            hashing_time += hashing_start.elapsed();
+
+            if self.progress_log.is_some() {
+                hashed.files_done += files.len();
+                hashed.bytes_done += bucket_bytes(&files);
+                self.log_progress("hashing", hashed);
+            }
+
+            if self.options.show_diff && !self.options.quiet && !self.options.summary_only {
+                self.show_diff(&files, &dupes_vec);
+            }
+
+            dupes_vec.into_iter()
+                .map(|(digest, dupes)| (digest, dupes.into_iter().cloned().collect()))
+                .collect::<Vec<_>>()
+        }));
+
+        // Reclaimable space is only known after hashing, so every bucket is hashed before any group is reported
+        if matches!(self.options.sort_output, Some(OutputOrdering::Size)) {
+            let mut sorted: Vec<_> = groups.collect();
+
+            let sort_start = Instant::now();
+            sorted.sort_by_cached_key(|(_, files)| Reverse(reclaimable(files)));
+            grouping += sort_start.elapsed();
+
+            groups = Box::new(sorted.into_iter());
+        }
+
+        // Groups are acted on as their buckets are hashed, so that's all part of the hashing phase
+        for (digest, cloned) in groups {
+            if self.handle_group(&mut tally, user_names.as_ref(), &digest, &cloned) {
+                break;
+            }
+        }
+
+        self.finish_progress("hashing", hashed);
+
+        tally.stats += hashing;
+
+        self.timings.hashing = hashing_time;
+        self.timings.grouping = grouping;
+        self.finish(&tally, user_names.as_ref());
+    }
+
+    // Diffs two files of a bucket with different contents, taking one from each group of duplicates
+    // or a file with contents of its own, for --show-diff
+    fn show_diff(&self, files: &[DirEntry], groups: &[DuplicateGroup<'_>]) {
+        let grouped: Vec<&Path> = groups.iter()
+            .flat_map(|(_, dupes)| dupes.iter().map(|dup| dup.path()))
+            .collect();
+        let distinct: Vec<&Path> = groups.iter()
+            .filter_map(|(_, dupes)| dupes.first().map(|dup| dup.path()))
+            .chain(files.iter().filter(|file| file.file_type().is_file()).map(DirEntry::path).filter(|path| !grouped.contains(path)))
+            .collect();
+
+        let [old, new, ..] = distinct[..] else {
+            return;
+        };
+        let (Some(old_text), Some(new_text)) = (diff::read_text(old), diff::read_text(new)) else {
+            return;
+        };
+        let Some(lines) = diff::preview(&old_text, &new_text, MAX_DIFF_LINES) else {
+            return;
+        };
+
+        println!("Same size, different contents:");
+        println!("--- {}", self.shorten_path(old));
+        println!("+++ {}", self.shorten_path(new));
+
+        for line in lines {
+            println!("{line}");
+        }
+
+        println!();
+    }
+
+    // Lists a group of identical files and acts on its duplicates, adding it up in the tally
+    // Returns whether --limit-groups was reached
+    fn handle_group(&self, tally: &mut Tally, user_names: Option<&Users>, digest: &[u8], files: &[DirEntry]) -> bool {
+        let Some((source, mut duplicates, reason)) = self.pick(digest, files) else {
+            return false;
+        };
+
+        tally.stats.clones += Self::drop_clones(source, &mut duplicates);
+
+        if duplicates.is_empty() {
+            return false;
+        }
+
+        let owner = user_names.and_then(|_| owners::owner(source.path()));
+        let family = self.options.collapse_generated
+            .then(|| generated::family(&self.options.path, iter::once(source.path()).chain(duplicates.iter().map(|dup| dup.path()))))
+            .flatten();
+        // Generated groups are listed by family at the end instead
+        let listing = !self.options.quiet && !self.options.summary_only && family.is_none() && self.options.sample_groups.is_none();
+        // Numbered in the order groups are listed, the same in plans and any other output
+        let group = tally.stats.duplicate_groups + 1;
+
+        // Explaining a group reads the metadata of its files again, so it's only done when shown
+        let reasons: Vec<String> = if self.options.verbose || self.options.write_plan.is_some() {
+            duplicates.iter()
+                .map(|dup| self.rationale(reason, source, dup))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        if listing {
+            self.print_group(group, source, &duplicates, if self.options.verbose { &reasons } else { &[] }, user_names.zip(owner));
+        }
+
+        if let (Some(size), None) = (self.options.sample_groups, &family) {
+            tally.sample.get_or_insert_with(|| Sample::new(size, self.options.seed))
+                .offer(digest, group, SampledGroup {
+                    group,
+                    source: source.clone(),
+                    duplicates: duplicates.iter().map(|&dup| dup.clone()).collect(),
+                    reasons: if self.options.verbose { reasons.clone() } else { Vec::new() },
+                    owner
+                });
+        }
+
+        let group_stats = Self::group_stats(&duplicates);
+
+        tally.stats += group_stats;
+
+        if let Some(family) = family {
+            *tally.generated.entry(family).or_default() += group_stats;
+        }
+
+        if let Some(uid) = owner {
+            *tally.users.entry(uid).or_default() += group_stats;
+        }
+
+        let duplicate_paths: Vec<&Path> = duplicates.iter()
+            .map(|dup| dup.path())
+            .collect();
+
+        let actions_start = Instant::now();
+
+        if self.skips_group(group, duplicates.len() + 1) {
+            // Still listed and counted, only left alone
+        } else if self.options.write_plan.is_some() {
+            tally.planned.push(plan::group_record(group, digest, source.path(), &duplicate_paths, &reasons));
+        } else if self.options.user_scripts.is_some() {
+            if let Some(uid) = owner {
+                tally.removals.entry(uid)
+                    .or_default()
+                    .extend(duplicate_paths.iter().map(|dup| (dup.to_path_buf(), source.path().to_owned())));
+            }
+        } else {
+            self.act(group, digest, source.path(), &duplicate_paths);
+        }
+
+        tally.actions += actions_start.elapsed();
+
+        if listing {
+            println!();
+        }
+
+        if self.options.limit_groups == Some(tally.stats.duplicate_groups) {
+            println!("{}", self.lang.text(Message::Stopped(tally.stats.duplicate_groups)));
+            println!();
+
+            return true;
+        }
+
+        false
+    }
+
+    // Writes out whatever was deferred to the end of the run, and sums it up
+    fn finish(&mut self, tally: &Tally, user_names: Option<&Users>) {
+        if !self.options.quiet && !self.options.summary_only {
+            self.print_sample(tally.sample.as_ref(), user_names);
+            self.print_generated(&tally.generated);
+        }
+
+        let outputs_start = Instant::now();
+        self.prune_empty_dirs();
+        self.write_outputs(&tally.stats, &tally.planned, &tally.removals, user_names);
+        self.finish_progress("done", Step::default());
+
+        self.timings.actions = tally.actions + outputs_start.elapsed();
+
+        if !self.options.no_summary {
+            self.print_summary(&tally.stats, &tally.users, user_names);
+        }
+    }
+
+    // Plans and user scripts are written once all groups are known, instead of acting on each
+    fn write_outputs(&self, stats: &Stats, planned: &[Value], removals: &BTreeMap<u32, Removals>, user_names: Option<&Users>) {
+        if let Some(ref path) = self.options.write_plan {
+            match plan::write(path, &self.options, planned) {
+                Ok(()) => println!("Wrote a plan for {} duplicate groups to {}", stats.duplicate_groups, path.display()),
+                Err(err) => {
+                    eprintln!("Failure while writing the plan: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+
+            println!();
+        }
+
+        if let Some(path) = checksums::manifest_path(&self.options) {
+            let known = self.plain_digests.lock().expect("plain digests are never poisoned");
+
+            match checksums::write(&path, &self.options, &self.all_files, &known) {
+                Ok((listed, unreadable)) => {
+                    for (file, err) in unreadable {
+                        self.failures.record(&file, "checksums", &err);
+                    }
+
+                    println!("Wrote the checksums of {listed} files to {}", path.display());
+                },
+                Err(err) => {
+                    eprintln!("Failure while writing the checksum manifest: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+
+            println!();
+        }
+
+        if let Some(ref path) = self.options.session {
+            let files = self.session_files();
+
+            match session::record(path, &self.options, &files) {
+                Ok(()) => println!("Recorded {} files of {} in the session {}", files.len(), session::volume_name(&self.options), path.display()),
+                Err(err) => {
+                    eprintln!("Failure while writing the session: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+
+            println!();
+        }
+
+        if let (Some(dir), Some(names)) = (&self.options.user_scripts, user_names) {
+            match owners::write_scripts(dir, names, removals) {
+                Ok(()) => println!("Wrote deletion scripts for {} users to {}", removals.len(), dir.display()),
+                Err(err) => {
+                    eprintln!("Failure while writing the user scripts: {}", dir.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+
+            println!();
+        }
+    }
+
+    // Every scanned file that's still there with its size and digest, hashing the ones that didn't need it
+    // to find duplicates, as files on volumes scanned later may be copies of any of them
+    fn session_files(&self) -> Vec<(PathBuf, u64, Vec<u8>)> {
+        let digests = mem::take(&mut *self.digests.lock().expect("digests are never poisoned"));
+        let mut progress = (!self.options.summary_only).then(Progress::start);
+        let mut files = Vec::with_capacity(self.all_files.len());
+
+        for (index, path) in self.all_files.iter().enumerate() {
+            let Ok(metadata) = fs::symlink_metadata(path) else {
+                continue;
+            };
+
+            if let Some(ref mut progress) = progress {
+                progress.update(index, format_args!("Hashed {index} files out of {} for the session", self.all_files.len()));
+            }
+
+            let digest = match digests.get(path) {
+                Some(digest) => digest.clone(),
+                None => match self.digest(path) {
+                    Ok(digest) => digest,
+                    Err(err) => {
+                        self.failures.record(path, "session", &err);
+                        continue;
+                    }
+                }
+            };
+
+            files.push((path.clone(), metadata.len(), digest));
+        }
+
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+
+        files
+    }
+
+    fn print_summary(&self, stats: &Stats, users: &BTreeMap<u32, Stats>, user_names: Option<&Users>) {
+        match self.options.summary_format {
+            SummaryFormat::Text => {
+                let lang = self.lang;
+
+                println!("{}", lang.text(Message::Summary));
+                println!("{}", lang.text(Message::DuplicateGroups(stats.duplicate_groups)));
+                println!("{}", lang.text(Message::DuplicatesFound(stats.duplicate_files)));
+
+                if matches!(self.options.mode, Mode::QuickSignature) {
+                    println!("{}", lang.text(Message::Probable));
+                }
+
+                println!("{}", lang.text(Message::SharedSize(stats.candidates)));
+                println!("{}", lang.text(Message::UniqueContents(stats.false_candidates)));
+
+                if stats.unreadable > 0 {
+                    println!("{}", lang.text(Message::Unreadable(stats.unreadable)));
+                }
+
+                if self.options.forensic {
+                    println!("{}", lang.text(Message::HashCollisions(stats.hash_collisions)));
+                }
+
+                println!("{}", lang.text(Message::SpaceSaved(&Self::format_size(stats.reclaimable_bytes, 2))));
+                self.print_trashed();
+                self.print_freed(stats);
+
+                if stats.clones > 0 {
+                    println!("{}", lang.text(Message::Clones(stats.clones)));
+                }
+
+                let memory = self.memory();
+                let sizes = Self::format_size(memory.sizes as u64, 2);
+                let hashes = Self::format_size(memory.hashes as u64, 2);
+
+                println!("{}", lang.text(Message::PeakMemory(&sizes, &hashes)));
+
+                if memory.spilled > 0 {
+                    println!("{}", lang.text(Message::Spilled(memory.spilled)));
+                }
+
+                if let Some(names) = user_names {
+                    println!();
+                    println!("{}", lang.text(Message::PerUser));
+
+                    for (&uid, user) in users {
+                        let size = Self::format_size(user.reclaimable_bytes, 2);
+
+                        println!("{}", lang.text(Message::UserStats(&names.name(uid), user.duplicate_groups, &size)));
+                    }
+                }
+
+                // Timings differ on every run
+                if !self.options.deterministic {
+                    println!();
+                    println!("{}", lang.text(Message::Done(self.start.elapsed().as_millis())));
+
+                    for (phase, duration) in self.timings.phases() {
+                        println!("  {phase}: {}ms", duration.as_millis());
+                    }
+                }
+            },
+            SummaryFormat::Json => {
+                let mut summary = stats.to_json();
+
+                if !self.options.deterministic {
+                    summary["elapsed_ms"] = json!(self.start.elapsed().as_millis());
+                    summary["timings"] = self.timings.to_json();
+                }
+
+                summary["errors"] = self.failures.to_json();
+
+                if matches!(self.options.mode, Mode::QuickSignature) {
+                    summary["probable"] = json!(true);
+                }
+
+                summary["memory"] = self.memory().to_json();
+
+                if let Some(ref trash) = self.trash {
+                    let (files, bytes) = trash.moved();
+
+                    summary["trashed"] = json!({ "files": files, "bytes": bytes });
+                }
+
+                if let Some(freed) = self.measured_freed() {
+                    summary["measured_freed_bytes"] = json!(freed);
+                }
+
+                if let Some(names) = user_names {
+                    let users: serde_json::Map<_, _> = users.iter()
+                        .map(|(&uid, user)| (names.name(uid), user.to_json()))
+                        .collect();
+
+                    summary["users"] = Value::Object(users);
+                }
+
+                println!("{summary}");
+            }
+        }
+    }
+
+    // How much the free space of the volume grew since the start, which can be negative if other programs wrote to it
+    fn measured_freed(&self) -> Option<i128> {
+        let after = space::free(&self.options.path).ok()?;
+
+        Some(i128::from(after) - i128::from(self.free_before?))
+    }
+
+    // What deleting or linking duplicates really freed next to what was predicted, which falls short when they
+    // were hard links or sparse files, failed to go, went to the trash, or other programs wrote to the volume meanwhile
+    #[allow(clippy::cast_precision_loss)]
+    fn print_freed(&self, stats: &Stats) {
+        let Some(freed) = self.measured_freed() else {
+            return;
+        };
+        let trashed = self.trash.as_ref().map_or(0, |trash| trash.moved().1);
+        let predicted = stats.reclaimable_bytes.saturating_sub(trashed);
+        let size = Self::format_size(u64::try_from(freed.abs()).unwrap_or(u64::MAX), 2);
+        let measured = if freed < 0 { format!("-{size}") } else { size };
+
+        println!("{measured} freed going by the volume's free space, {} expected", Self::format_size(predicted, 2));
+
+        if (freed as f64) < predicted as f64 * (1.0 - FREED_TOLERANCE) {
+            println!("That's less than expected, as some duplicates may have been hard links or sparse files, failed to be removed, or other programs wrote to the volume meanwhile");
+        }
+    }
+
+    pub fn deleted_from(&self, duplicates: &[&Path]) {
+        if !self.options.prune_empty_dirs {
+            return;
+        }
+
+        self.deleted_from.lock()
+            .expect("deleted folders are never poisoned")
+            .extend(duplicates.iter().filter_map(|dup| dup.parent()).map(Path::to_path_buf));
+    }
+
+    // Removes the folders left empty once duplicates were deleted from them, with --prune-empty-dirs, deepest first
+    // so that a folder that only held emptied ones goes too, up to the scanned folder and never where a .dedup-policy.toml protects
+    pub fn prune_empty_dirs(&self) {
+        let deleted_from = mem::take(&mut *self.deleted_from.lock().expect("deleted folders are never poisoned"));
+
+        let folders: BTreeSet<&Path> = deleted_from.iter()
+            .flat_map(|folder| folder.ancestors().take_while(|ancestor| *ancestor != self.options.path && ancestor.starts_with(&self.options.path)))
+            .collect();
+        let mut folders: Vec<&Path> = folders.into_iter().collect();
+
+        folders.sort_by_key(|folder| Reverse(folder.components().count()));
+
+        let mut pruned = 0;
+
+        for folder in folders {
+            if self.policies.protects(folder) || !fs::read_dir(folder).is_ok_and(|mut entries| entries.next().is_none()) {
+                continue;
+            }
+
+            match fs::remove_dir(folder) {
+                Ok(()) => pruned += 1,
+                Err(err) => {
+                    eprintln!("Failure while removing an empty folder: {}", folder.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+
+                    self.failures.record(folder, "pruning", &err);
+                }
+            }
+        }
+
+        if pruned > 0 {
+            println!("Removed {pruned} folders left empty by the deletions");
+        }
+    }
+
+    // Space taken by files moved to the trash, which isn't reclaimed until it's emptied, with --trash
+    fn print_trashed(&self) {
+        let Some((files, bytes)) = self.trash.as_ref().map(Trash::moved).filter(|&(files, _)| files > 0) else {
+            return;
+        };
+
+        println!("{} of it in {files} files moved to the trash, which still takes up the space until it's emptied", Self::format_size(bytes, 2));
+
+        if let Some(days) = self.options.empty_trash_after {
+            let date = trash::empty_date(days).unwrap_or_else(|| format!("{days} days"));
+
+            println!("Once nothing turned out to be missing, they can be emptied from the trash after {date}, e.g. with `trash-empty {days}`");
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn format_size(bytes: u64, decimals: usize) -> String {
+        if bytes == 0 {
+            return "0 bytes".to_string();
+        }
+
+        let k = 1024;
+
+        let bf = bytes as f64;
+        let kf = f64::from(k);
+
+        let sizes = [" bytes", "kb", "mb", "gb", "tb", "pb", "eb", "zb", "yb", "bb"];
+        let i = bf.log(kf).floor() as i32;
+
+        let unit = bf / kf.powi(i);
+        // Index cannot overflow, as u64 cannot fit numbers large enough to even reach yottabytes
+        let size = sizes[i as usize];
+        let formatted = format!("{unit:.decimals$}{size}");
+
+        formatted
+    }
+
+    pub fn rename(pattern: &str, digest: &[u8], duplicates: &[&Path], failures: &Failures) {
+        for (n, &path) in duplicates.iter().enumerate() {
+            if let Err(err) = Self::rename_one(pattern, digest, n, path) {
+                eprintln!("Failure while renaming: {}", path.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                failures.record(path, "renaming", &err);
+            }
+        }
+    }
+
+    // Renames the nth duplicate of a group according to the --rename-dupes pattern
+    // Never clobbers an existing file, which could well be the source
+    pub fn rename_one(pattern: &str, digest: &[u8], n: usize, path: &Path) -> io::Result<PathBuf> {
+        let target = Self::rename_target(pattern, digest, n, path);
+
+        if target.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.to_string_lossy())
+            ));
+        }
+
+        fs::rename(path, &target)?;
+
+        Ok(target)
+    }
+
+    // Where the nth duplicate of a group goes with the --rename-dupes pattern
+    pub fn rename_target(pattern: &str, digest: &[u8], n: usize, path: &Path) -> PathBuf {
+        let name = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let ext = path.extension().map_or_else(String::new, |ext| format!(".{}", ext.to_string_lossy()));
+        let n = (n + 1).to_string();
+        let hash = hex(digest);
+
+        let renamed = expand_pattern(pattern, &[
+            ("name", &name),
+            ("ext", &ext),
+            ("n", &n),
+            ("hash", &hash)
+        ]);
+
+        path.with_file_name(renamed)
+    }
+
+    fn link(&self, source: &Path, duplicates: &[&Path]) {
+        let kind = if self.options.reflink { LinkKind::Reflink } else { LinkKind::Hard };
+
+        // Read before linking, as hard links share the kept file's timestamps from then on
+        let newest = duplicates.iter()
+            .chain(Some(&source))
+            .filter_map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+            .max();
+
+        for dup in duplicates {
+            if let Err(err) = links::replace(source, dup, kind) {
+                eprintln!("Failure while linking: {}", dup.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                self.failures.record(dup, "linking", &err);
+            }
+        }
+
+        if let (true, Some(newest)) = (self.options.keep_newest_mtime, newest) {
+            if let Err(err) = links::set_modified(source, newest) {
+                eprintln!("Failure while updating the modification time: {}", source.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                self.failures.record(source, "linking", &err);
+            }
+        }
+    }
+
+    // Moved to the trash instead of removed for good when there's one, with --trash
+    pub fn delete(duplicates: &[&Path], failures: &Failures, throttle: &Throttle, trash: Option<&Trash>, preserve_dir_times: bool) {
+        let dir_times = preserve_dir_times.then(|| DirTimes::record(duplicates));
+
+        for dup in duplicates {
+            throttle.wait();
+
+            match trash.map_or_else(|| fs::remove_file(dup), |trash| trash.put(dup)) {
+                Ok(()) => {},
+                Err(err) => {
+                    eprintln!("Failure while deleting: {}", dup.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+
+                    failures.record(dup, "deleting", &err);
+                }
+            }
+        }
+
+        if let Some(dir_times) = dir_times {
+            dir_times.restore(failures);
+        }
     }
 }
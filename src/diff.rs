@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::Path;
+
+// Files past this size, or with more differing lines than this, aren't previewed
+const MAX_BYTES: u64 = 1024 * 1024;
+const MAX_LINES: usize = 2000;
+// Unchanged lines shown around the changes
+const CONTEXT: usize = 2;
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str)
+}
+
+// Contents of a file that looks like text, small enough to diff
+pub fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_BYTES {
+        return None;
+    }
+
+    let text = String::from_utf8(fs::read(path).ok()?).ok()?;
+
+    (!text.contains('\0')).then_some(text)
+}
+
+// A unified diff of the lines that differ between two texts, with a little context, cut short after `max_lines`
+// Returns nothing if the texts are too far apart to preview
+pub fn preview(old: &str, new: &str, max_lines: usize) -> Option<Vec<String>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Only the middle that differs gets diffed, as files of the same size tend to differ in few places
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    if old_middle.len() > MAX_LINES || new_middle.len() > MAX_LINES {
+        return None;
+    }
+
+    let start = prefix.saturating_sub(CONTEXT);
+    let old_end = (old.len() - suffix + CONTEXT).min(old.len());
+    let new_end = (new.len() - suffix + CONTEXT).min(new.len());
+
+    let mut lines = vec![format!("@@ -{},{} +{},{} @@", start + 1, old_end - start, start + 1, new_end - start)];
+
+    let changes = old[start..prefix].iter().map(|line| Line::Same(line))
+        .chain(diff_lines(old_middle, new_middle))
+        .chain(old[old.len() - suffix..old_end].iter().map(|line| Line::Same(line)));
+
+    for change in changes {
+        if lines.len() > max_lines {
+            lines.push("...".to_string());
+            break;
+        }
+
+        lines.push(match change {
+            Line::Same(line) => format!(" {line}"),
+            Line::Removed(line) => format!("-{line}"),
+            Line::Added(line) => format!("+{line}")
+        });
+    }
+
+    Some(lines)
+}
+
+// Lines removed and added going from one to the other, by their longest common subsequence
+#[allow(clippy::cast_possible_truncation)]
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let width = new.len() + 1;
+    // Length of the common subsequence of old[i..] and new[j..], at i * width + j
+    let mut common = vec![0_u16; (old.len() + 1) * width];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    lines.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    lines.extend(new[j..].iter().map(|line| Line::Added(line)));
+
+    lines
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const FILE_NAME: &str = ".dedup-policy.toml";
+
+// Settings of a .dedup-policy.toml, which apply to everything beneath its folder
+// Settings left out are inherited from the closest folder above that sets them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DirPolicy {
+    // Never delete, rename or link files in here, though they can still be the kept copy
+    pub protect: Option<bool>,
+    // Keep the copy in here over the ones elsewhere
+    pub prefer: Option<bool>
+}
+
+impl DirPolicy {
+    pub fn protects(self) -> bool {
+        self.protect.unwrap_or(false)
+    }
+
+    pub fn prefers(self) -> bool {
+        self.prefer.unwrap_or(false)
+    }
+
+    // Fills in what this policy leaves out from the one of the folder above
+    const fn inherit(self, parent: Self) -> Self {
+        Self {
+            protect: match self.protect {
+                Some(protect) => Some(protect),
+                None => parent.protect
+            },
+            prefer: match self.prefer {
+                Some(prefer) => Some(prefer),
+                None => parent.prefer
+            }
+        }
+    }
+}
+
+// Policies of the folders under the scanned root, merged with those above them as they're looked up
+pub struct Policies {
+    root: PathBuf,
+    resolved: Mutex<HashMap<PathBuf, DirPolicy>>
+}
+
+impl Policies {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+            resolved: Mutex::new(HashMap::new())
+        }
+    }
+
+    // What applies to a file, from the policy files in its folder and every folder above it up to the root
+    pub fn of(&self, path: &Path) -> DirPolicy {
+        path.parent().map_or_else(DirPolicy::default, |dir| self.resolve(dir))
+    }
+
+    // Policy files themselves are never acted on, even when they're copies of one another
+    pub fn protects(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| name == FILE_NAME) || self.of(path).protects()
+    }
+
+    fn resolve(&self, dir: &Path) -> DirPolicy {
+        if !dir.starts_with(&self.root) {
+            return DirPolicy::default();
+        }
+
+        if let Some(&policy) = self.resolved.lock().expect("policies are never poisoned").get(dir) {
+            return policy;
+        }
+
+        let parent = match dir.parent() {
+            Some(parent) if dir != self.root => self.resolve(parent),
+            _ => DirPolicy::default()
+        };
+        let policy = read(&dir.join(FILE_NAME)).inherit(parent);
+
+        self.resolved.lock()
+            .expect("policies are never poisoned")
+            .insert(dir.to_owned(), policy);
+
+        policy
+    }
+}
+
+// Missing files set nothing, and settings that can't be understood are reported and left out
+fn read(path: &Path) -> DirPolicy {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return DirPolicy::default(),
+        Err(err) => {
+            eprintln!("Failure while reading the folder policy: {}", path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            return DirPolicy::default();
+        }
+    };
+
+    let mut policy = DirPolicy::default();
+
+    // Only top-level `key = true` or `key = false` settings are needed out of TOML
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let setting = line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .and_then(|(key, value)| Some((key, value.parse::<bool>().ok()?)));
+
+        match setting {
+            Some(("protect", value)) => policy.protect = Some(value),
+            Some(("prefer", value)) => policy.prefer = Some(value),
+            _ => {
+                eprintln!("Failure while reading the folder policy, ignoring `{line}`: {}", path.to_string_lossy());
+                eprintln!("Only `protect` and `prefer` can be set, to true or false");
+                eprintln!();
+            }
+        }
+    }
+
+    policy
+}
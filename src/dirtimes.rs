@@ -0,0 +1,64 @@
+use std::fs::{self, FileTimes};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::failures::Failures;
+
+// Timestamps of the folders files are deleted from, with --preserve-dir-times, put back once they're gone,
+// as some photo managers and backup tools take a folder's modification time as a sign of new contents
+pub struct DirTimes(Vec<(PathBuf, SystemTime, SystemTime)>);
+
+impl DirTimes {
+    pub fn record(paths: &[&Path]) -> Self {
+        let mut folders: Vec<&Path> = paths.iter()
+            .filter_map(|path| path.parent())
+            .map(|folder| if folder.as_os_str().is_empty() { Path::new(".") } else { folder })
+            .collect();
+
+        folders.sort_unstable();
+        folders.dedup();
+
+        let times = folders.into_iter()
+            .filter_map(|folder| {
+                let metadata = fs::metadata(folder).ok()?;
+
+                Some((folder.to_path_buf(), metadata.accessed().ok()?, metadata.modified().ok()?))
+            })
+            .collect();
+
+        Self(times)
+    }
+
+    pub fn restore(&self, failures: &Failures) {
+        for (folder, accessed, modified) in &self.0 {
+            if let Err(err) = set_times(folder, *accessed, *modified) {
+                eprintln!("Failure while restoring the folder's timestamps: {}", folder.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                failures.record(folder, "restoring timestamps", &err);
+            }
+        }
+    }
+}
+
+// Folders can't be opened for writing, but their timestamps can still be set through a handle
+#[cfg(not(windows))]
+fn set_times(folder: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+    fs::File::open(folder)?.set_times(FileTimes::new().set_accessed(accessed).set_modified(modified))
+}
+
+#[cfg(windows)]
+fn set_times(folder: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(folder)?
+        .set_times(FileTimes::new().set_accessed(accessed).set_modified(modified))
+}
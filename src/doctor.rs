@@ -0,0 +1,158 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::links;
+use crate::options::Options;
+
+// Files made to try things out on the scanned folder's file system, removed once done
+struct Scratch {
+    dir: PathBuf
+}
+
+impl Scratch {
+    fn create(root: &Path) -> io::Result<Self> {
+        let dir = root.join(format!(".deduplicator-doctor-{}", std::process::id()));
+
+        fs::create_dir(&dir)?;
+        fs::write(dir.join("source"), b"deduplicator doctor")?;
+
+        Ok(Self { dir })
+    }
+
+    fn source(&self) -> PathBuf {
+        self.dir.join("source")
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Outcome of one check, and what to do about it if it failed
+struct Check {
+    name: &'static str,
+    // Whether an option passed for this run depends on it
+    needed: bool,
+    result: Result<String, String>
+}
+
+// Tries out on the scanned folder what the requested actions rely on, printing what works and what to do about
+// what doesn't, so a long run doesn't fail at the end. Exits with an error if a check a requested action needs fails
+pub fn run(options: &Options) {
+    let root = &options.path;
+    let acting = options.delete || options.rename_dupes.is_some() || options.hardlink || options.reflink;
+    let mut checks = vec![Check {
+        name: "Reading",
+        needed: true,
+        result: fs::read_dir(root)
+            .map(|_| format!("{} can be listed", root.display()))
+            .map_err(|err| format!("{} can't be listed ({err}), check that it exists and its permissions", root.display()))
+    }];
+
+    match Scratch::create(root) {
+        Ok(scratch) => {
+            checks.push(Check {
+                name: "Writing",
+                needed: acting,
+                result: Ok(format!("files can be made and removed in {}", root.display()))
+            });
+
+            checks.push(Check {
+                name: "Hard links",
+                needed: options.hardlink,
+                result: fs::hard_link(scratch.source(), scratch.dir.join("hard"))
+                    .map(|()| "supported on this file system".to_string())
+                    .map_err(|err| format!("not supported here ({err}), use --reflink or --delete instead"))
+            });
+
+            checks.push(Check {
+                name: "Reflinks",
+                needed: options.reflink,
+                result: links::reflink(&scratch.source(), &scratch.dir.join("reflink"))
+                    .map(|()| "supported on this file system".to_string())
+                    .map_err(|err| format!("not supported here ({err}), they need Btrfs, XFS, APFS, ReFS or a Dev Drive"))
+            });
+
+            if cfg!(windows) {
+                checks.push(Check {
+                    name: "Long paths",
+                    needed: false,
+                    result: long_path(&scratch.dir)
+                        .map(|()| "paths over 260 characters can be used".to_string())
+                        .map_err(|err| format!("paths over 260 characters fail ({err}), set LongPathsEnabled in the registry to act on files that deep"))
+                });
+            }
+        },
+        Err(err) => checks.push(Check {
+            name: "Writing",
+            needed: acting,
+            result: Err(format!("files can't be made in {} ({err}), so duplicates can't be deleted, renamed or linked either", root.display()))
+        })
+    }
+
+    checks.push(Check {
+        name: "Trash",
+        needed: false,
+        result: trash(root)
+            .map(|trash| format!("found at {}, but note --delete removes files for good rather than moving them there", trash.display()))
+            .ok_or_else(|| "none found for this folder, --delete removes files for good either way".to_string())
+    });
+
+    let mut problems = 0;
+
+    for check in &checks {
+        let needed = if check.needed { " (needed for this run)" } else { "" };
+
+        match check.result {
+            Ok(ref message) => println!("ok    {}: {message}", check.name),
+            // Only worth a warning when nothing asked for this run depends on it
+            Err(ref message) if !check.needed => println!("warn  {}: {message}", check.name),
+            Err(ref message) => {
+                println!("FAIL  {}{needed}: {message}", check.name);
+                problems += 1;
+            }
+        }
+    }
+
+    println!();
+
+    if problems > 0 {
+        println!("{problems} {} would stop this run from doing what was asked", if problems == 1 { "problem" } else { "problems" });
+
+        std::process::exit(1);
+    }
+
+    println!("Nothing in the way of this run");
+}
+
+// Nested folders deep enough to go past MAX_PATH
+fn long_path(dir: &Path) -> io::Result<()> {
+    let deep = (0..8).fold(dir.to_owned(), |path, _| path.join("d".repeat(40)));
+
+    fs::create_dir_all(&deep)?;
+    fs::write(deep.join("file"), b"")
+}
+
+// Where the desktop would move files from this folder to, if anywhere
+fn trash(root: &Path) -> Option<PathBuf> {
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from);
+
+    let candidates = if cfg!(windows) {
+        // Every volume has its own recycle bin at its root
+        root.canonicalize().ok()?.ancestors().last().map(|volume| volume.join("$Recycle.Bin")).into_iter().collect()
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join(".Trash")).into_iter().collect()
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local/share")))
+            .map(|data| data.join("Trash"))
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    candidates.into_iter().find(|trash| trash.is_dir())
+}
@@ -0,0 +1,38 @@
+// A stratum of a stratified random sample: how many units it holds,
+// and the values measured for the ones that were sampled
+pub struct Stratum {
+    pub population: usize,
+    pub samples: Vec<f64>
+}
+
+// Estimates the total over every unit of every stratum,
+// returning the estimate and its margin of error at 95% confidence
+#[allow(clippy::cast_precision_loss)]
+pub fn total(strata: &[Stratum]) -> (f64, f64) {
+    let mut total = 0.0;
+    let mut variance = 0.0;
+
+    for stratum in strata {
+        if stratum.samples.is_empty() {
+            continue;
+        }
+
+        let population = stratum.population as f64;
+        let count = stratum.samples.len() as f64;
+        let mean = stratum.samples.iter().sum::<f64>() / count;
+
+        total += population * mean;
+
+        if stratum.samples.len() > 1 {
+            let sample_variance = stratum.samples.iter()
+                .map(|sample| (sample - mean).powi(2))
+                .sum::<f64>() / (count - 1.0);
+            // Finite population correction, sampling a whole stratum leaves no uncertainty
+            let correction = 1.0 - count / population;
+
+            variance += population.powi(2) * correction * sample_variance / count;
+        }
+    }
+
+    (total, 1.96 * variance.sqrt())
+}
@@ -0,0 +1,308 @@
+use crate::deduplicator::Deduplicator;
+use crate::options::{Command, FileOrdering, Keep, Mode, Options, PermissionOrder, TextNormalization};
+use crate::rationale;
+
+// Describes in plain words what a run with these options will do, one step a line, so a mistyped
+// flag shows up before anything happens rather than after
+pub fn print(options: &Options) {
+    let steps = match options.command {
+        None | Some(Command::Packages) => scan_steps(options),
+        Some(Command::VerifyPlan { ref plan }) => vec![
+            format!("re-check every file of the plan {} against the digests it was written with", plan.display()),
+            format!("{} that didn't change since, leaving groups that did alone", action(options).unwrap_or_else(|| String::from("list the duplicates"))),
+        ],
+        Some(_) => vec![String::from("run the subcommand, which only reads files and changes none of them")]
+    };
+
+    println!("This run will:");
+
+    for step in steps {
+        println!("  - {step}");
+    }
+
+    println!();
+}
+
+fn scan_steps(options: &Options) -> Vec<String> {
+    let mut steps = vec![scope(options)];
+
+    if let Some(limit) = scan_limit(options) {
+        steps.push(limit);
+    }
+
+    if let Some(groups) = options.limit_groups {
+        steps.push(format!("stop once {groups} groups of duplicates are found"));
+    }
+
+    if matches!(options.command, Some(Command::Packages)) {
+        steps.push(String::from("compare every file with the files installed by dpkg or rpm, going by their file lists"));
+        steps.push(match (options.delete, options.trash) {
+            (true, true) => String::from("move the files identical to an installed one to the trash, as the system keeps its own copy"),
+            (true, false) => String::from("delete the files identical to an installed one permanently, as the system keeps its own copy"),
+            (false, _) => String::from("only list the files identical to an installed one, without changing any file")
+        });
+
+        return steps;
+    }
+
+    match options.mode {
+        Mode::Hash => {
+            steps.push(matching(options));
+            steps.push(keeping(options));
+        },
+        Mode::Similarity => {
+            steps.push(similarity(options));
+            steps.push(if options.delete && options.find_thumbnails {
+                String::from("delete the smaller copies found, along with their sidecars but never a RAW file, and only list the rest")
+            } else {
+                String::from("only list the groups of images, without changing any file")
+            });
+
+            return steps;
+        },
+        Mode::QuickSignature => {
+            steps.push(format!("look for probable duplicates, going only by their size and the {} digest of their first 4 KiB, without reading the rest", options.hash_algo.name()));
+            steps.push(keeping(options));
+        },
+        Mode::SyncConflicts => {
+            steps.push(String::from("only look for sync conflict copies, like `report (conflicted copy).txt`, with the same contents as the file they were made of"));
+            steps.push(String::from("keep the original file every time"));
+        },
+        Mode::BackupGenerations => {
+            steps.push(String::from("treat the folders with a date in their name as backup generations, comparing each file with the same path in the generation before"));
+            steps.push(String::from("keep each file in the oldest generation it's unchanged in"));
+        }
+    }
+
+    match action(options) {
+        Some(action) => {
+            steps.push(format!("{action}{}", conditions(options)));
+            steps.extend(safeguards(options));
+        },
+        None => steps.push(listing(options))
+    }
+
+    steps
+}
+
+fn scope(options: &Options) -> String {
+    let path = options.path.display();
+    let mut skipped = Vec::new();
+
+    if !options.include_hidden {
+        skipped.push(if options.skip_hidden { "hidden files and folders" } else { "hidden folders" });
+    }
+
+    if !options.include_caches {
+        skipped.push(if options.exclude_caches { "cache folders like .cache and __pycache__" } else { "folders tagged as caches with a CACHEDIR.TAG file" });
+    }
+
+    if !options.scan_bundles {
+        skipped.push("the insides of macOS bundles like .app folders");
+    }
+
+    let scanned = if options.no_recursive {
+        format!("scan the files directly in {path}, but not its subfolders")
+    } else {
+        format!("scan {path} and every folder under it")
+    };
+
+    if skipped.is_empty() {
+        scanned
+    } else {
+        format!("{scanned}, skipping {}", list(&skipped))
+    }
+}
+
+// Like `a, b and c`
+fn list(items: &[&str]) -> String {
+    match items.split_last() {
+        Some((last, [])) => (*last).to_owned(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => String::new()
+    }
+}
+
+fn scan_limit(options: &Options) -> Option<String> {
+    let limits: Vec<String> = vec![
+        options.max_files.map(|files| format!("{files} files")),
+        options.max_bytes.map(|bytes| format!("{} of files", Deduplicator::format_size(bytes, 2))),
+    ].into_iter().flatten().collect();
+
+    (!limits.is_empty()).then(|| format!("stop scanning after {}, leaving the rest of the folder out", limits.join(" or ")))
+}
+
+fn matching(options: &Options) -> String {
+    let mut ignoring = Vec::new();
+
+    if options.ignore_tags {
+        ignoring.push("the tags and art of mp3 and flac files");
+    }
+
+    if options.normalize_pdfs {
+        ignoring.push("the IDs and timestamps of pdf files");
+    }
+
+    if options.normalize_office {
+        ignoring.push("how office documents are compressed");
+    }
+
+    if options.normalize_images {
+        ignoring.push("how images are encoded and rotated");
+    }
+
+    if options.text_normalize.contains(&TextNormalization::Eol) {
+        ignoring.push("the line endings of text files");
+    }
+
+    if options.text_normalize.contains(&TextNormalization::TrailingWs) {
+        ignoring.push("whitespace at the end of lines of text files");
+    }
+
+    if options.text_normalize.contains(&TextNormalization::Bom) {
+        ignoring.push("byte order marks of text files");
+    }
+
+    if options.decompress_before_hash {
+        ignoring.push("whether gz, xz and zst files are compressed");
+    }
+
+    let mut matching = format!("look for files with exactly the same contents, going by their {} digests", options.hash_algo.name());
+
+    if !ignoring.is_empty() {
+        matching.push_str(", ignoring ");
+        matching.push_str(&list(&ignoring));
+    }
+
+    if options.per_user {
+        matching.push_str(", only grouping files owned by the same user");
+    }
+
+    matching
+}
+
+fn similarity(options: &Options) -> String {
+    let mut looking = match options.max_distance {
+        Some(bits) => format!("look for images that look alike, whose hashes differ in at most {bits} bits out of 144"),
+        None if options.clip_model.is_some() => format!("look for images that look alike, at least {}% similar going by the CLIP model", options.similarity_score),
+        None => format!("look for images that look alike, at least {}% similar", options.similarity_score)
+    };
+
+    if options.find_thumbnails {
+        looking.push_str(", and for smaller copies of them like thumbnails");
+    }
+
+    if !options.no_video_frames {
+        looking.push_str(", comparing a frame of videos when ffmpeg is installed");
+    }
+
+    looking
+}
+
+fn keeping(options: &Options) -> String {
+    let (first, last) = match options.order {
+        FileOrdering::Modified => ("the one modified longest ago", "the one modified most recently"),
+        FileOrdering::Created => ("the one created first", "the one created last"),
+        FileOrdering::Name => ("the first one by name", "the last one by name")
+    };
+    let by_order = match options.keep {
+        Keep::First => first,
+        Keep::Last => last
+    };
+
+    let mut preferences = Vec::new();
+
+    if !options.keep_dir_order.is_empty() {
+        let folders: Vec<String> = options.keep_dir_order.iter().map(|folder| folder.display().to_string()).collect();
+
+        preferences.push(format!("a copy in {}", folders.join(", then in ")));
+    }
+
+    match options.keep_permissions {
+        Some(PermissionOrder::Strictest) => preferences.push(String::from("the one with the strictest permissions")),
+        Some(PermissionOrder::Loosest) => preferences.push(String::from("the one with the loosest permissions")),
+        None => {}
+    }
+
+    if options.keep_compressed {
+        preferences.push(String::from("a compressed one"));
+    }
+
+    if let Some(ref command) = options.select_cmd {
+        return format!("in each group of duplicates, keep the one `{command}` picks, leaving groups it fails on alone");
+    }
+
+    preferences.push(String::from(by_order));
+
+    format!("in each group of duplicates, keep {}, unless a .dedup-policy.toml says otherwise", preferences.join(", or else "))
+}
+
+// What's done to the files that aren't kept, if anything is
+fn action(options: &Options) -> Option<String> {
+    if options.hardlink {
+        Some(String::from("replace the rest with hard links to the kept file"))
+    } else if options.reflink {
+        Some(String::from("replace the rest with copy-on-write clones of the kept file"))
+    } else if options.delete && options.trash {
+        Some(String::from("move the rest to the trash, where they can be restored from until it's emptied"))
+    } else if options.delete {
+        Some(String::from("delete the rest permanently"))
+    } else {
+        options.rename_dupes.as_ref().map(|pattern| format!("rename the rest following `{pattern}`"))
+    }
+}
+
+fn conditions(options: &Options) -> String {
+    let mut conditions = Vec::new();
+
+    if options.only_older_copies {
+        conditions.push(String::from("last modified before the kept file"));
+    }
+
+    if let Some(age) = options.only_delete_older_than {
+        conditions.push(format!("last modified more than {} ago", rationale::span(age)));
+    }
+
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(", but only the ones {}", conditions.join(" and "))
+    }
+}
+
+fn safeguards(options: &Options) -> Vec<String> {
+    let mut safeguards = Vec::new();
+
+    if let Some(size) = options.skip_groups_larger_than {
+        safeguards.push(format!("leave groups of more than {size} files alone, only listing them"));
+    }
+
+    let limits: Vec<String> = vec![
+        options.max_delete_files.map(|files| format!("{files} files")),
+        options.max_delete_bytes.map(|bytes| Deduplicator::format_size(bytes, 2)),
+    ].into_iter().flatten().collect();
+
+    if !limits.is_empty() && options.delete {
+        safeguards.push(format!("stop deleting after {}, only listing the remaining groups", limits.join(" or ")));
+    }
+
+    if options.paranoid {
+        safeguards.push(String::from("hash each kept file again right before, leaving its group alone if it changed"));
+    }
+
+    if options.prune_empty_dirs {
+        safeguards.push(String::from("remove the folders left empty once done"));
+    }
+
+    safeguards
+}
+
+// Runs without an action only report, or hand the groups to something else to act on
+fn listing(options: &Options) -> String {
+    match (&options.write_plan, &options.user_scripts) {
+        (Some(plan), _) => format!("write the groups to the plan {} without changing any file, to be acted on later with `verify-plan`", plan.display()),
+        (None, Some(scripts)) => format!("write a script per user deleting their duplicates into {} without changing any file", scripts.display()),
+        (None, None) if matches!(options.mode, Mode::QuickSignature) => String::from("only list the probable duplicates, without changing any file. Pass --write-plan to check them in full and act on them with `verify-plan`"),
+        (None, None) => String::from("only list the duplicates, without changing any file. Pass --delete, --hardlink, --reflink or --rename-dupes to act on them")
+    }
+}
@@ -0,0 +1,190 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+// A key and the path filed under it
+type Record = (Vec<u8>, String);
+
+// Records of a key and a path, sorted by key without holding them all in memory:
+// they're sorted in runs that fit in memory, written to disk and merged back together
+pub struct Sorter {
+    dir: PathBuf,
+    name: &'static str,
+    // Bytes a run may take before it's written out
+    run_bytes: usize,
+    run: Vec<Record>,
+    size: usize,
+    runs: Vec<PathBuf>
+}
+
+impl Sorter {
+    pub fn new(dir: &Path, name: &'static str, run_bytes: usize) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            name,
+            run_bytes,
+            run: Vec::new(),
+            size: 0,
+            runs: Vec::new()
+        }
+    }
+
+    pub fn push(&mut self, key: Vec<u8>, path: String) -> io::Result<()> {
+        self.size += key.len() + path.len() + mem::size_of::<Record>();
+        self.run.push((key, path));
+
+        if self.size >= self.run_bytes {
+            self.write_run()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        if self.run.is_empty() {
+            return Ok(());
+        }
+
+        let path = self.dir.join(format!("deduplicator-{}-{}-{}", std::process::id(), self.name, self.runs.len()));
+        self.runs.push(path.clone());
+
+        self.run.sort_unstable();
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+
+        for (key, path) in self.run.drain(..) {
+            write_field(&mut writer, &key)?;
+            write_field(&mut writer, path.as_bytes())?;
+        }
+
+        writer.flush()?;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    // Every record pushed, in order of their keys
+    pub fn merge(mut self) -> io::Result<Merged> {
+        self.write_run()?;
+
+        let runs = mem::take(&mut self.runs);
+        let mut merged = Merged {
+            readers: Vec::new(),
+            heap: BinaryHeap::new(),
+            runs
+        };
+
+        for (index, run) in merged.runs.iter().enumerate() {
+            let mut reader = BufReader::new(File::open(run)?);
+
+            if let Some(record) = read_record(&mut reader)? {
+                merged.heap.push(Reverse((record, index)));
+            }
+
+            merged.readers.push(reader);
+        }
+
+        Ok(merged)
+    }
+}
+
+impl Drop for Sorter {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = fs::remove_file(run);
+        }
+    }
+}
+
+// The runs of a sorter, read back in order by always taking the smallest record at the head of any run
+pub struct Merged {
+    readers: Vec<BufReader<File>>,
+    // The next record of each run, along with its index
+    heap: BinaryHeap<Reverse<(Record, usize)>>,
+    runs: Vec<PathBuf>
+}
+
+impl Merged {
+    // Consecutive records sharing a key, along with the paths of all of them
+    pub fn groups(self) -> impl Iterator<Item = io::Result<(Vec<u8>, Vec<String>)>> {
+        let mut records = self.peekable();
+
+        std::iter::from_fn(move || {
+            let (key, path) = match records.next()? {
+                Ok(record) => record,
+                Err(err) => return Some(Err(err))
+            };
+            let mut paths = vec![path];
+
+            while let Some(Ok((next, _))) = records.peek() {
+                if *next != key {
+                    break;
+                }
+
+                if let Some(Ok((_, path))) = records.next() {
+                    paths.push(path);
+                }
+            }
+
+            Some(Ok((key, paths)))
+        })
+    }
+}
+
+impl Iterator for Merged {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((record, index)) = self.heap.pop()?;
+
+        match read_record(&mut self.readers[index]) {
+            Ok(Some(next)) => self.heap.push(Reverse((next, index))),
+            Ok(None) => {},
+            Err(err) => return Some(Err(err))
+        }
+
+        Some(Ok(record))
+    }
+}
+
+impl Drop for Merged {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = fs::remove_file(run);
+        }
+    }
+}
+
+fn write_field(writer: &mut impl Write, field: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(field.len()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(field)
+}
+
+fn read_field(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let mut field = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut field)?;
+
+    Ok(field)
+}
+
+// The next record of a run, if there's any left
+fn read_record(reader: &mut impl Read) -> io::Result<Option<Record>> {
+    let key = match read_field(reader) {
+        Ok(key) => key,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err)
+    };
+    let path = String::from_utf8(read_field(reader)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    Ok(Some((key, path)))
+}
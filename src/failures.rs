@@ -0,0 +1,92 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+
+// A file that couldn't be read or acted on, kept for the JSON outputs so automation can tell apart
+// what went wrong without parsing stderr
+#[derive(Debug)]
+pub struct Failure {
+    path: PathBuf,
+    // What was being done with the file, like `metadata`, `hashing` or `deleting`
+    phase: &'static str,
+    code: Option<i32>,
+    class: &'static str,
+    message: String
+}
+
+impl Failure {
+    pub fn new(path: &Path, phase: &'static str, err: &io::Error) -> Self {
+        Self {
+            path: path.to_owned(),
+            phase,
+            code: err.raw_os_error(),
+            class: classify(err),
+            message: err.to_string()
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.to_string_lossy(),
+            "phase": self.phase,
+            "os_error": self.code,
+            "class": self.class,
+            "message": self.message
+        })
+    }
+}
+
+// Failures of a run, shared with the threads reading metadata
+#[derive(Debug, Clone, Default)]
+pub struct Failures(Arc<Mutex<Vec<Failure>>>);
+
+impl Failures {
+    pub fn record(&self, path: &Path, phase: &'static str, err: &io::Error) {
+        self.0.lock()
+            .expect("failures are never poisoned")
+            .push(Failure::new(path, phase, err));
+    }
+
+    // The last failure of a file, for reporting on each file acted on
+    pub fn of(&self, path: &Path) -> Option<Value> {
+        let failures = self.0.lock().expect("failures are never poisoned");
+
+        failures.iter()
+            .rev()
+            .find(|failure| failure.path == path)
+            .map(Failure::to_json)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let failures = self.0.lock().expect("failures are never poisoned");
+
+        failures.iter()
+            .map(Failure::to_json)
+            .collect()
+    }
+}
+
+// Broad reason for a failure, which tells whether it's worth retrying:
+// `vanished` files were moved or deleted during the run, `permission_denied` ones never will be readable,
+// and `transient` ones were busy or locked, by antivirus scanners or network filesystem hiccups
+pub fn classify(err: &io::Error) -> &'static str {
+    // Sharing and lock violations, which Windows reports for files held open elsewhere
+    if cfg!(windows) && matches!(err.raw_os_error(), Some(32 | 33)) {
+        return "transient";
+    }
+
+    match err.kind() {
+        io::ErrorKind::NotFound => "vanished",
+        io::ErrorKind::PermissionDenied => "permission_denied",
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::ExecutableFileBusy => "transient",
+        io::ErrorKind::AlreadyExists => "already_exists",
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => "invalid_data",
+        _ => "other"
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, Read};
+
+use ring::digest::{SHA512, Context};
+
+use crate::deduplicator::hex;
+use crate::source::FileSource;
+
+// Offset of the first byte at which the files differ, or None if they're identical
+// A file that is a prefix of the other differs at the end of the shorter one
+pub fn first_difference(a: &impl FileSource, b: &impl FileSource) -> io::Result<Option<u64>> {
+    let mut a = io::BufReader::new(a.open()?);
+    let mut b = io::BufReader::new(b.open()?);
+
+    let mut buf_a = [0; 8192];
+    let mut buf_b = [0; 8192];
+    let mut offset = 0;
+
+    loop {
+        let count_a = read_full(&mut a, &mut buf_a)?;
+        let count_b = read_full(&mut b, &mut buf_b)?;
+
+        if let Some(pos) = buf_a[..count_a].iter().zip(&buf_b[..count_b]).position(|(x, y)| x != y) {
+            return Ok(Some(offset + pos as u64));
+        }
+
+        if count_a != count_b {
+            return Ok(Some(offset + count_a.min(count_b) as u64));
+        }
+
+        if count_a == 0 {
+            return Ok(None);
+        }
+
+        offset += count_a as u64;
+    }
+}
+
+// Fills the buffer unless the end of the file is reached first, so both files are compared in lockstep
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let count = reader.read(&mut buf[filled..])?;
+        if count == 0 {
+            break;
+        }
+
+        filled += count;
+    }
+
+    Ok(filled)
+}
+
+// A second, unrelated digest for telling colliding files apart
+pub fn sha512_hex(file: &impl FileSource) -> io::Result<String> {
+    let mut reader = io::BufReader::new(file.open()?);
+    let mut ctx = Context::new(&SHA512);
+    let mut buf = [0; 8192];
+
+    loop {
+        let count = reader.read(&mut buf)?;
+        if count == 0 {
+            break;
+        }
+
+        ctx.update(&buf[..count]);
+    }
+
+    Ok(hex(ctx.finish().as_ref()))
+}
+
+pub fn report<F: FileSource>(digest: &[u8], a: &F, b: &F, offset: u64) {
+    eprintln!("Hash collision between different files with SHA-256 {}:", hex(digest));
+
+    for file in [a, b] {
+        let len = file.info().map_or_else(|_| "unknown".to_string(), |info| info.size.to_string());
+        let sha512 = sha512_hex(file).unwrap_or_else(|err| format!("unreadable ({err})"));
+
+        eprintln!("  {}", file.path().display());
+        eprintln!("    {len} bytes, SHA-512 {sha512}");
+    }
+
+    eprintln!("  First difference at byte offset {offset}");
+    eprintln!();
+}
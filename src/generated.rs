@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+
+// Folders build tools and package managers fill with output, which tends to be the same across
+// every copy of a project or every version of a build
+const GENERATED_FOLDERS: &[&str] = &[
+    "build", "dist", "target", "out", "obj", "node_modules", "__pycache__", ".gradle", ".next", ".nuxt", ".tox", "vendor"
+];
+
+// The outermost generated folder a file is in, under the scanned folder
+fn family_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut family = root.to_path_buf();
+
+    // The file name itself doesn't count, only the folders it's in
+    for component in relative.parent()?.components() {
+        family.push(component);
+
+        if GENERATED_FOLDERS.iter().any(|name| component.as_os_str() == *name) {
+            return Some(family);
+        }
+    }
+
+    None
+}
+
+// The generated folders a group of identical files is spread across, sorted, if every one of them is in one
+pub fn family<'a>(root: &Path, paths: impl IntoIterator<Item = &'a Path>) -> Option<Vec<PathBuf>> {
+    let mut family: Vec<PathBuf> = paths.into_iter()
+        .map(|path| family_root(root, path))
+        .collect::<Option<_>>()?;
+
+    family.sort();
+    family.dedup();
+
+    Some(family)
+}
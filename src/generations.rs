@@ -0,0 +1,67 @@
+// The date and time in the name of a backup folder, like `backup-2024-01-01`, `daily.20240101-0300`
+// or `2024_01_01T03:00:00`, as YYYYMMDDhhmmss so generations sort by it. The time is optional
+pub fn timestamp(name: &str) -> Option<u64> {
+    let bytes = name.as_bytes();
+
+    (0..bytes.len()).find_map(|start| parse_at(&bytes[start..]))
+}
+
+fn parse_at(bytes: &[u8]) -> Option<u64> {
+    let mut rest = bytes;
+
+    let year = digits(&mut rest, 4)?;
+    let separator = skip_any(&mut rest, b"-_.");
+    let month = digits(&mut rest, 2)?;
+
+    // Dates use the same separator throughout, or none
+    if skip_any(&mut rest, b"-_.") != separator {
+        return None;
+    }
+
+    let day = digits(&mut rest, 2)?;
+
+    if !(1900..=2999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let date = (year * 100 + month) * 100 + day;
+
+    Some(date * 1_000_000 + time(rest).unwrap_or(0))
+}
+
+fn time(mut rest: &[u8]) -> Option<u64> {
+    skip_any(&mut rest, b"-_T .");
+
+    let hours = digits(&mut rest, 2)?;
+    skip_any(&mut rest, b":-._");
+    let minutes = digits(&mut rest, 2)?;
+    skip_any(&mut rest, b":-._");
+    let seconds = digits(&mut rest, 2).unwrap_or(0);
+
+    (hours < 24 && minutes < 60 && seconds < 60).then(|| (hours * 100 + minutes) * 100 + seconds)
+}
+
+// Takes exactly `count` digits off the front
+fn digits(rest: &mut &[u8], count: usize) -> Option<u64> {
+    let taken = rest.get(..count)?;
+
+    if !taken.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    let value = taken.iter().fold(0, |value, digit| value * 10 + u64::from(digit - b'0'));
+    *rest = &rest[count..];
+
+    Some(value)
+}
+
+// Takes one of the given separators off the front, if there's one
+fn skip_any(rest: &mut &[u8], separators: &[u8]) -> Option<u8> {
+    let first = *rest.first()?;
+
+    separators.contains(&first).then(|| {
+        *rest = &rest[1..];
+
+        first
+    })
+}
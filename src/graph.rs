@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::paths;
+
+// A similarity graph to write with --export-graph, for clustering or drawing in tools like Gephi:
+// a node for every compared image and an edge for every pair similar enough, weighted by similarity
+pub struct Graph<'a> {
+    pub root: &'a Path,
+    pub nodes: Vec<&'a Path>,
+    // Both images, their distance in bits and their similarity from 0 to 1
+    pub edges: Vec<(&'a Path, &'a Path, u32, f32)>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Dot,
+    GraphMl
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match ext.as_str() {
+            "dot" | "gv" => Some(Self::Dot),
+            "graphml" => Some(Self::GraphMl),
+            _ => None
+        }
+    }
+}
+
+impl Graph<'_> {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let contents = match Format::of(path) {
+            Some(Format::Dot) => self.dot(),
+            Some(Format::GraphMl) => self.graphml(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the graph file should end in .dot or .graphml"))
+        };
+
+        fs::write(path, contents)
+    }
+
+    fn label(&self, path: &Path) -> String {
+        paths::relative_display(self.root, path)
+    }
+
+    // Graphviz, which also opens in Gephi and most other graph tools
+    fn dot(&self) -> String {
+        let quote = |label: String| format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("graph similarity {\n");
+
+        for node in &self.nodes {
+            let _ = writeln!(dot, "  {};", quote(self.label(node)));
+        }
+
+        for &(a, b, bits, score) in &self.edges {
+            let _ = writeln!(dot, "  {} -- {} [weight={score}, distance={bits}];", quote(self.label(a)), quote(self.label(b)));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn graphml(&self) -> String {
+        let ids: HashMap<&Path, usize> = self.nodes.iter()
+            .enumerate()
+            .map(|(id, &node)| (node, id))
+            .collect();
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n",
+            "  <key id=\"distance\" for=\"edge\" attr.name=\"distance\" attr.type=\"int\"/>\n",
+            "  <graph id=\"similarity\" edgedefault=\"undirected\">\n"
+        ));
+
+        for (id, node) in self.nodes.iter().enumerate() {
+            let _ = writeln!(xml, "    <node id=\"n{id}\"><data key=\"label\">{}</data></node>", escape_xml(&self.label(node)));
+        }
+
+        for &(a, b, bits, score) in &self.edges {
+            let (Some(a), Some(b)) = (ids.get(a), ids.get(b)) else {
+                continue;
+            };
+
+            let _ = writeln!(
+                xml,
+                "    <edge source=\"n{a}\" target=\"n{b}\"><data key=\"weight\">{score}</data><data key=\"distance\">{bits}</data></edge>"
+            );
+        }
+
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,29 @@
+use walkdir::DirEntry;
+
+// Hidden folders mostly hold app state, like .git and AppData, where "duplicates" such as lock files,
+// configs and packed objects are expected and removing them breaks things. Their contents are skipped
+// unless --include-hidden is passed, while hidden files elsewhere are only skipped with --skip-hidden
+// The scanned folder itself is never considered hidden, pointing the tool at one is explicit enough
+pub fn is_hidden(entry: &DirEntry, files: bool) -> bool {
+    if entry.depth() == 0 || !(files || entry.file_type().is_dir()) {
+        return false;
+    }
+
+    has_hidden_flag(entry)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_flag(entry: &DirEntry) -> bool {
+    entry.file_name()
+        .to_str()
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(windows)]
+fn has_hidden_flag(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+    entry.metadata().is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
@@ -0,0 +1,172 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ring::constant_time;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::deduplicator::hex;
+use crate::serve::Server;
+
+// Same operations as the JSON-RPC server, over plain HTTP with JSON bodies:
+// POST /scan       starts a scan in the background, optionally for `{ "path": ... }`
+// GET  /progress   whether a scan is running, its latest progress, and the last scan's result
+// GET  /groups     duplicate groups found by the last scan
+// POST /act        acts on groups, with the same body as the JSON-RPC `act` params
+// Every request needs the token printed at startup, as any web page open in a browser can reach the port
+pub fn listen(server: Server, addr: &str) {
+    let addr = local_by_default(addr);
+    let Some(token) = new_token() else {
+        eprintln!("Could not generate a token for the HTTP API");
+        return;
+    };
+
+    let http = match tiny_http::Server::http(&addr) {
+        Ok(http) => http,
+        Err(err) => {
+            eprintln!("Could not listen on {addr}:");
+            eprintln!("{err:?}");
+            return;
+        }
+    };
+
+    eprintln!("Listening on http://{addr}");
+    eprintln!("Send `Authorization: Bearer {token}` with every request");
+
+    let server = Arc::new(Mutex::new(server));
+    let status = Arc::new(Mutex::new(json!({ "running": false })));
+
+    for mut request in http.incoming_requests() {
+        let (code, body) = match check(&request, &addr, &token) {
+            Ok(()) => route(&mut request, &server, &status),
+            Err((code, message)) => (code, json!({ "error": message }))
+        };
+        let header = Header::from_bytes("Content-Type", "application/json").expect("header is valid");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(code)
+            .with_header(header);
+
+        if let Err(err) = request.respond(response) {
+            eprintln!("Failure while responding to request:");
+            eprintln!("{err:?}");
+        }
+    }
+}
+
+// Addresses given as just a port, like `8080` or `:8080`, are only reachable from this machine
+fn local_by_default(addr: &str) -> String {
+    let port = addr.strip_prefix(':').unwrap_or(addr);
+
+    if port.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{port}")
+    } else {
+        addr.to_owned()
+    }
+}
+
+fn new_token() -> Option<String> {
+    let mut bytes = [0; 16];
+
+    SystemRandom::new().fill(&mut bytes).ok()?;
+
+    Some(hex(&bytes))
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+// Turns away requests without the token, requests sent by web pages from other origins, and bodies that aren't JSON,
+// which browsers can't send to other origins without asking first
+fn check(request: &Request, addr: &str, token: &str) -> Result<(), (u16, &'static str)> {
+    let authorized = header(request, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time::verify_slices_are_equal(given.trim().as_bytes(), token.as_bytes()).is_ok());
+
+    if !authorized {
+        return Err((401, "Missing or wrong token, send the one printed at startup as `Authorization: Bearer <token>`"));
+    }
+
+    if header(request, "Origin").is_some_and(|origin| origin != format!("http://{addr}")) {
+        return Err((403, "Requests from other origins are not allowed"));
+    }
+
+    let json = header(request, "Content-Type")
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+
+    if *request.method() == Method::Post && !json {
+        return Err((415, "Request bodies have to be sent as application/json"));
+    }
+
+    Ok(())
+}
+
+fn route(request: &mut Request, server: &Arc<Mutex<Server>>, status: &Arc<Mutex<Value>>) -> (u16, Value) {
+    let mut body = String::new();
+
+    if let Err(err) = request.as_reader().read_to_string(&mut body) {
+        return (400, json!({ "error": err.to_string() }));
+    }
+
+    let body = if body.trim().is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(body) => body,
+            Err(err) => return (400, json!({ "error": err.to_string() }))
+        }
+    };
+
+    let running = status.lock().expect("status lock is not poisoned")["running"] == true;
+
+    match (request.method(), request.url()) {
+        (Method::Get, "/progress") => (200, status.lock().expect("status lock is not poisoned").clone()),
+        // Groups and actions would only see partial results while scanning
+        (Method::Post, "/scan" | "/act") | (Method::Get, "/groups") if running => {
+            (409, json!({ "error": "A scan is in progress" }))
+        },
+        (Method::Post, "/scan") => {
+            start_scan(body, server, status);
+
+            (202, json!({ "started": true }))
+        },
+        (Method::Get, "/groups") => (200, server.lock().expect("server lock is not poisoned").report()),
+        (Method::Post, "/act") => {
+            let result = server.lock().expect("server lock is not poisoned").act(&body);
+
+            match result {
+                Ok(result) => (200, result),
+                Err((_, message)) => (400, json!({ "error": message }))
+            }
+        },
+        _ => (404, json!({ "error": "Not found" }))
+    }
+}
+
+fn start_scan(params: Value, server: &Arc<Mutex<Server>>, status: &Arc<Mutex<Value>>) {
+    let server = Arc::clone(server);
+    let status = Arc::clone(status);
+
+    *status.lock().expect("status lock is not poisoned") = json!({ "running": true });
+
+    thread::spawn(move || {
+        let result = server.lock().expect("server lock is not poisoned").scan(&params, |method, params| {
+            if method == "progress" {
+                *status.lock().expect("status lock is not poisoned") = json!({
+                    "running": true,
+                    "progress": params
+                });
+            }
+        });
+
+        *status.lock().expect("status lock is not poisoned") = match result {
+            Ok(result) => json!({ "running": false, "result": result }),
+            Err((_, message)) => json!({ "running": false, "error": message })
+        };
+    });
+}
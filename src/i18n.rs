@@ -0,0 +1,157 @@
+use std::env;
+
+use crate::options::Lang;
+
+// The user-visible lines of the hash mode report, translated by `Lang::text`
+#[derive(Clone, Copy)]
+pub enum Message<'a> {
+    FoundFiles(usize),
+    ProcessedFiles(usize, usize),
+    SkippedPlaceholders(usize),
+    // The group number, then how many files it has
+    DuplicateFiles(usize, usize),
+    // Like `DuplicateFiles`, with --mode quick-signature
+    ProbableFiles(usize, usize),
+    Owner(&'a str),
+    Source(&'a str),
+    Copy(&'a str),
+    Stopped(usize),
+    Summary,
+    DuplicateGroups(usize),
+    DuplicatesFound(usize),
+    Probable,
+    SharedSize(usize),
+    UniqueContents(usize),
+    Unreadable(usize),
+    HashCollisions(usize),
+    SpaceSaved(&'a str),
+    Clones(usize),
+    PeakMemory(&'a str, &'a str),
+    Spilled(usize),
+    PerUser,
+    UserStats(&'a str, usize, &'a str),
+    Done(u128)
+}
+
+impl Lang {
+    // --lang, or else the language of the locale, which is English if it isn't a supported one
+    pub fn detect(lang: Option<Self>) -> Self {
+        lang.unwrap_or_else(|| {
+            let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+                .filter_map(|var| env::var(var).ok())
+                .find(|locale| !locale.is_empty())
+                .unwrap_or_default();
+
+            // `es_ES.UTF-8` and the like
+            match locale.split(['_', '.', '-']).next() {
+                Some("es") => Self::Es,
+                Some("de") => Self::De,
+                _ => Self::En
+            }
+        })
+    }
+
+    pub fn text(self, message: Message) -> String {
+        match self {
+            Self::En => english(message),
+            Self::Es => spanish(message),
+            Self::De => german(message)
+        }
+    }
+}
+
+// Every language supported so far only tells apart one from many
+const fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
+    if count == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+fn english(message: Message) -> String {
+    match message {
+        Message::FoundFiles(count) => format!("Found {count} {}", plural(count, "file", "files")),
+        Message::ProcessedFiles(idx, count) => format!("Processed {idx} files out of {count}"),
+        Message::SkippedPlaceholders(count) => format!("Skipped {count} cloud placeholder {}, pass --hydrate to download and scan them", plural(count, "file", "files")),
+        Message::DuplicateFiles(group, count) => format!("Group {group}, found {count} duplicate files:"),
+        Message::ProbableFiles(group, count) => format!("Group {group}, found {count} probable duplicate files:"),
+        Message::Owner(name) => format!("Owner:  {name}"),
+        Message::Source(path) => format!("Source: {path}"),
+        Message::Copy(path) => format!("Copy:   {path}"),
+        Message::Stopped(count) => format!("Stopped after finding {count} duplicate {}", plural(count, "group", "groups")),
+        Message::Summary => "Summary:".to_owned(),
+        Message::DuplicateGroups(count) => format!("{count} duplicate {}", plural(count, "group", "groups")),
+        Message::DuplicatesFound(count) => format!("{count} {} found", plural(count, "duplicate", "duplicates")),
+        Message::Probable => "These are probable duplicates, going by their size and first 4 KiB only. Check them in full with --write-plan and `verify-plan`".to_owned(),
+        Message::SharedSize(count) => format!("{count} {} shared their size with another", plural(count, "file", "files")),
+        Message::UniqueContents(count) => format!("{count} of those had unique contents"),
+        Message::Unreadable(count) => format!("{count} of those could not be read"),
+        Message::HashCollisions(count) => format!("{count} hash {}", plural(count, "collision", "collisions")),
+        Message::SpaceSaved(size) => format!("{size} space saved after deletion of duplicates"),
+        Message::Clones(count) => format!("{count} {} already clones of the kept file, left alone", plural(count, "duplicate was", "duplicates were")),
+        Message::PeakMemory(sizes, hashes) => format!("{sizes} of memory at most for file sizes, {hashes} for hashes"),
+        Message::Spilled(count) => format!("{count} {} spilled to disk", plural(count, "file", "files")),
+        Message::PerUser => "Per user:".to_owned(),
+        Message::UserStats(name, groups, size) => format!("{name}: {groups} duplicate {}, {size} space saved", plural(groups, "group", "groups")),
+        Message::Done(ms) => format!("Done in {ms}ms!")
+    }
+}
+
+fn spanish(message: Message) -> String {
+    match message {
+        Message::FoundFiles(count) => format!("{} {count} {}", plural(count, "Se encontró", "Se encontraron"), plural(count, "archivo", "archivos")),
+        Message::ProcessedFiles(idx, count) => format!("Procesados {idx} de {count} archivos"),
+        Message::SkippedPlaceholders(count) => format!("Se omitieron {count} {} de la nube sin descargar, usa --hydrate para descargarlos y analizarlos", plural(count, "archivo", "archivos")),
+        Message::DuplicateFiles(group, count) => format!("Grupo {group}, se encontraron {count} archivos duplicados:"),
+        Message::ProbableFiles(group, count) => format!("Grupo {group}, se encontraron {count} archivos probablemente duplicados:"),
+        Message::Owner(name) => format!("Dueño:    {name}"),
+        Message::Source(path) => format!("Se queda: {path}"),
+        Message::Copy(path) => format!("Copia:    {path}"),
+        Message::Stopped(count) => format!("Detenido tras encontrar {count} {} de duplicados", plural(count, "grupo", "grupos")),
+        Message::Summary => "Resumen:".to_owned(),
+        Message::DuplicateGroups(count) => format!("{count} {} de duplicados", plural(count, "grupo", "grupos")),
+        Message::DuplicatesFound(count) => format!("{count} {}", plural(count, "duplicado encontrado", "duplicados encontrados")),
+        Message::Probable => "Son duplicados probables, según su tamaño y sus primeros 4 KiB nada más. Compruébalos enteros con --write-plan y `verify-plan`".to_owned(),
+        Message::SharedSize(count) => format!("{count} {} su tamaño con otro", plural(count, "archivo compartía", "archivos compartían")),
+        Message::UniqueContents(count) => format!("{count} de ellos {} contenido único", plural(count, "tenía", "tenían")),
+        Message::Unreadable(count) => format!("{count} de ellos no se {} leer", plural(count, "pudo", "pudieron")),
+        Message::HashCollisions(count) => format!("{count} {} de hash", plural(count, "colisión", "colisiones")),
+        Message::SpaceSaved(size) => format!("{size} liberados al borrar los duplicados"),
+        Message::Clones(count) => format!("{count} {} clones del archivo que se queda, no se tocan", plural(count, "duplicado ya era", "duplicados ya eran")),
+        Message::PeakMemory(sizes, hashes) => format!("{sizes} de memoria como máximo para los tamaños, {hashes} para los hashes"),
+        Message::Spilled(count) => format!("{count} {} a disco", plural(count, "archivo volcado", "archivos volcados")),
+        Message::PerUser => "Por usuario:".to_owned(),
+        Message::UserStats(name, groups, size) => format!("{name}: {groups} {} de duplicados, {size} liberados", plural(groups, "grupo", "grupos")),
+        Message::Done(ms) => format!("¡Terminado en {ms}ms!")
+    }
+}
+
+fn german(message: Message) -> String {
+    match message {
+        Message::FoundFiles(count) => format!("{count} {} gefunden", plural(count, "Datei", "Dateien")),
+        Message::ProcessedFiles(idx, count) => format!("{idx} von {count} Dateien verarbeitet"),
+        Message::SkippedPlaceholders(count) => format!("{count} {} übersprungen, mit --hydrate werden sie heruntergeladen und geprüft", plural(count, "Cloud-Platzhalterdatei", "Cloud-Platzhalterdateien")),
+        Message::DuplicateFiles(group, count) => format!("Gruppe {group}, {count} doppelte Dateien gefunden:"),
+        Message::ProbableFiles(group, count) => format!("Gruppe {group}, {count} wahrscheinlich doppelte Dateien gefunden:"),
+        Message::Owner(name) => format!("Besitzer: {name}"),
+        Message::Source(path) => format!("Behalten: {path}"),
+        Message::Copy(path) => format!("Kopie:    {path}"),
+        Message::Stopped(count) => format!("Abgebrochen nach {count} {}", plural(count, "Duplikatgruppe", "Duplikatgruppen")),
+        Message::Summary => "Zusammenfassung:".to_owned(),
+        Message::DuplicateGroups(count) => format!("{count} {}", plural(count, "Duplikatgruppe", "Duplikatgruppen")),
+        Message::DuplicatesFound(count) => format!("{count} {} gefunden", plural(count, "Duplikat", "Duplikate")),
+        Message::Probable => "Das sind wahrscheinliche Duplikate, nur nach Größe und den ersten 4 KiB. Mit --write-plan und `verify-plan` werden sie vollständig geprüft".to_owned(),
+        Message::SharedSize(count) => format!("{count} {} ihre Größe mit einer anderen", plural(count, "Datei teilte", "Dateien teilten")),
+        Message::UniqueContents(count) => format!("{count} davon {} eindeutigen Inhalt", plural(count, "hatte", "hatten")),
+        Message::Unreadable(count) => format!("{count} davon {} nicht gelesen werden", plural(count, "konnte", "konnten")),
+        Message::HashCollisions(count) => format!("{count} {}", plural(count, "Hash-Kollision", "Hash-Kollisionen")),
+        Message::SpaceSaved(size) => format!("{size} werden durch das Löschen der Duplikate frei"),
+        Message::Clones(count) => format!("{count} {} bereits Klone der behaltenen Datei und bleiben unberührt", plural(count, "Duplikat war", "Duplikate waren")),
+        Message::PeakMemory(sizes, hashes) => format!("Höchstens {sizes} Speicher für Dateigrößen, {hashes} für Hashes"),
+        Message::Spilled(count) => format!("{count} {} auf die Festplatte ausgelagert", plural(count, "Datei", "Dateien")),
+        Message::PerUser => "Pro Benutzer:".to_owned(),
+        Message::UserStats(name, groups, size) => format!("{name}: {groups} {}, {size} frei", plural(groups, "Duplikatgruppe", "Duplikatgruppen")),
+        Message::Done(ms) => format!("Fertig in {ms}ms!")
+    }
+}
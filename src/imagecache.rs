@@ -0,0 +1,162 @@
+use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image_hasher::ImageHash;
+use serde_json::{json, Map, Value};
+
+use crate::options::ImageClass;
+use crate::paths;
+use crate::source::FileInfo;
+
+// Bumped whenever the way images are hashed changes, which makes older caches start over
+const VERSION: u64 = 1;
+
+// What decoding an image yields, kept so it needn't be decoded again while it stays the same
+#[derive(Clone)]
+pub struct CachedImage {
+    pub hash: ImageHash,
+    pub width: u32,
+    pub height: u32,
+    pub taken: Option<i64>,
+    pub class: ImageClass
+}
+
+struct Entry {
+    size: u64,
+    modified: Duration,
+    image: CachedImage
+}
+
+// Image hashes of previous runs for --image-cache, by absolute path, which are only
+// reused while the file keeps the size and modification time it had when hashed
+pub struct ImageCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    changed: AtomicBool
+}
+
+impl ImageCache {
+    // A missing or unreadable cache is the same as an empty one, and gets written anew
+    pub fn load(path: &Path) -> Self {
+        let entries = match fs::read(path) {
+            Ok(contents) => match serde_json::from_slice::<Value>(&contents) {
+                Ok(cache) if cache["version"].as_u64() == Some(VERSION) => cache["images"].as_object()
+                    .map(|images| images.iter()
+                        .filter_map(|(path, entry)| Some((PathBuf::from(path), parse_entry(entry)?)))
+                        .collect())
+                    .unwrap_or_default(),
+                Ok(_) => HashMap::new(),
+                Err(err) => {
+                    eprintln!("Failure while parsing the image cache, starting over: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+
+                    HashMap::new()
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                eprintln!("Failure while reading the image cache, starting over: {}", path.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: path.to_owned(),
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(false)
+        }
+    }
+
+    pub fn get(&self, path: &Path, info: &FileInfo) -> Option<CachedImage> {
+        let modified = since_epoch(info.modified?)?;
+
+        self.entries.lock()
+            .expect("image cache is not poisoned")
+            .get(&paths::absolute(path))
+            .filter(|entry| entry.size == info.size && entry.modified == modified)
+            .map(|entry| entry.image.clone())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn insert(&self, path: &Path, info: &FileInfo, image: CachedImage) {
+        let Some(modified) = info.modified.and_then(since_epoch) else {
+            return;
+        };
+
+        self.entries.lock()
+            .expect("image cache is not poisoned")
+            .insert(paths::absolute(path), Entry { size: info.size, modified, image });
+
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    // Written to a temporary file first, so an interrupted run never leaves a truncated cache behind
+    pub fn save(&self) -> io::Result<()> {
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let images: Map<String, Value> = self.entries.lock()
+            .expect("image cache is not poisoned")
+            .iter()
+            .map(|(path, entry)| (path.to_string_lossy().into_owned(), json!({
+                "size": entry.size,
+                "modified": [entry.modified.as_secs(), entry.modified.subsec_nanos()],
+                "hash": entry.image.hash.to_base64(),
+                "width": entry.image.width,
+                "height": entry.image.height,
+                "taken": entry.image.taken,
+                "screenshot": entry.image.class == ImageClass::Screenshots
+            })))
+            .collect();
+
+        let cache = json!({
+            "version": VERSION,
+            "images": images
+        });
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+
+        fs::write(&temp, serde_json::to_vec(&cache)?)?;
+        fs::rename(&temp, &self.path)
+    }
+}
+
+fn since_epoch(time: SystemTime) -> Option<Duration> {
+    time.duration_since(UNIX_EPOCH).ok()
+}
+
+fn parse_entry(entry: &Value) -> Option<Entry> {
+    let secs = entry["modified"][0].as_u64()?;
+    let nanos = u32::try_from(entry["modified"][1].as_u64()?).ok()?;
+
+    Some(Entry {
+        size: entry["size"].as_u64()?,
+        modified: Duration::new(secs, nanos),
+        image: CachedImage {
+            hash: ImageHash::from_base64(entry["hash"].as_str()?).ok()?,
+            width: u32::try_from(entry["width"].as_u64()?).ok()?,
+            height: u32::try_from(entry["height"].as_u64()?).ok()?,
+            taken: entry["taken"].as_i64(),
+            class: if entry["screenshot"].as_bool()? {
+                ImageClass::Screenshots
+            } else {
+                ImageClass::Photos
+            }
+        }
+    })
+}
@@ -0,0 +1,225 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use image::DynamicImage;
+use ring::digest::{SHA256, Digest, Context};
+
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp"];
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+// Screen resolutions of common monitors, laptops and phones, smallest side first
+const SCREEN_RESOLUTIONS: [(u32, u32); 27] = [
+    (720, 1280), (800, 1280), (768, 1366), (900, 1440), (864, 1536), (900, 1600), (1050, 1680),
+    (1080, 1920), (1200, 1920), (1080, 2560), (1440, 2560), (1600, 2560), (1800, 2880), (1440, 3440),
+    (2160, 3840), (2880, 5120), (640, 1136), (750, 1334), (828, 1792), (1125, 2436), (1170, 2532),
+    (1179, 2556), (1242, 2688), (1284, 2778), (1290, 2796), (1080, 2400), (1440, 3200)
+];
+
+// Dimensions read from the header alone, smallest first so that rotated copies share a bucket
+pub fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let (width, height) = image::image_dimensions(path).ok()?;
+
+    Some((width.min(height), width.max(height)))
+}
+
+// Hashes the decoded pixels after applying the EXIF orientation, so re-encoded metadata, rotation
+// flags and container details don't matter, but any change to the pixels themselves still does
+// Lossy re-encodes change the pixels, so those still hash differently, unlike in similarity mode
+pub fn digest(path: &Path) -> io::Result<Digest> {
+    let data = fs::read(path)?;
+    let image = image::load_from_memory(&data)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let image = orient(image, orientation(&data));
+    let pixels = image.to_rgba8();
+
+    let mut ctx = Context::new(&SHA256);
+    ctx.update(&pixels.width().to_le_bytes());
+    ctx.update(&pixels.height().to_le_bytes());
+    ctx.update(pixels.as_raw());
+
+    Ok(ctx.finish())
+}
+
+// Screenshots are saved as PNG at the resolution of the screen, and unlike photos carry no EXIF data
+pub fn is_screenshot(data: &[u8], width: u32, height: u32) -> bool {
+    let dimensions = (width.min(height), width.max(height));
+
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+        && !png_has_exif(data)
+        && SCREEN_RESOLUTIONS.contains(&dimensions)
+}
+
+// Whether an eXIf chunk comes before the image data, where the PNG spec requires it to be
+fn png_has_exif(data: &[u8]) -> bool {
+    let mut offset = 8;
+
+    while let Some(header) = data.get(offset..offset + 8) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+
+        match &header[4..] {
+            b"eXIf" => return true,
+            b"IDAT" | b"IEND" => return false,
+            _ => {}
+        }
+
+        // Length, type and CRC around the chunk data
+        let Some(next) = usize::try_from(len).ok().and_then(|len| offset.checked_add(len + 12)) else {
+            return false;
+        };
+
+        offset = next;
+    }
+
+    false
+}
+
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image
+    }
+}
+
+// EXIF orientation of a JPEG or TIFF file, 1 (upright) when missing
+fn orientation(data: &[u8]) -> u16 {
+    exif(data).and_then(|tiff| {
+        let entry = tiff.entry(tiff.first_ifd()?, 0x0112)?;
+
+        tiff.u16_at(entry + 8)
+    })
+    .unwrap_or(1)
+}
+
+// When a JPEG or TIFF photo was taken according to its EXIF data, in seconds since the epoch
+// Cameras record their local time without a zone, which is fine for comparing photos from the same one
+pub fn taken_at(data: &[u8]) -> Option<i64> {
+    let tiff = exif(data)?;
+    let first = tiff.first_ifd()?;
+
+    // DateTimeOriginal, in the EXIF sub-IFD, or else DateTime, which editors may have updated since
+    let original = tiff.entry(first, 0x8769)
+        .and_then(|pointer| tiff.u32_at(pointer + 8))
+        .and_then(|ifd| tiff.entry(usize::try_from(ifd).ok()?, 0x9003));
+    let entry = original.or_else(|| tiff.entry(first, 0x0132))?;
+
+    let offset = usize::try_from(tiff.u32_at(entry + 8)?).ok()?;
+    let text = std::str::from_utf8(tiff.data.get(offset..offset + 19)?).ok()?;
+
+    parse_exif_time(text)
+}
+
+// `YYYY:MM:DD HH:MM:SS`
+fn parse_exif_time(text: &str) -> Option<i64> {
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar, from Howard Hinnant's date algorithms
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The TIFF structure holding the EXIF data of a TIFF file, or of a JPEG inside its APP1 segment
+fn exif(data: &[u8]) -> Option<Tiff<'_>> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Tiff::parse(data);
+    }
+
+    jpeg_exif(data).and_then(Tiff::parse)
+}
+
+// Finds the TIFF structure inside the APP1 segment of a JPEG
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+
+    let mut offset = 2;
+
+    while offset + 4 <= data.len() && data[offset] == 0xff {
+        let marker = data[offset + 1];
+        let len = usize::from(u16::from_be_bytes([data[offset + 2], data[offset + 3]]));
+        let segment = data.get(offset + 4..offset + 2 + len)?;
+
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+
+        // Start of scan, no more metadata segments after this
+        if marker == 0xda {
+            return None;
+        }
+
+        offset += 2 + len;
+    }
+
+    None
+}
+
+// Just enough of TIFF to find tags, where every offset is from the start of the structure
+struct Tiff<'a> {
+    data: &'a [u8],
+    little: bool
+}
+
+impl<'a> Tiff<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let little = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None
+        };
+
+        Some(Self { data, little })
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let bytes = [*self.data.get(at)?, *self.data.get(at + 1)?];
+
+        Some(if self.little { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let bytes = [*self.data.get(at)?, *self.data.get(at + 1)?, *self.data.get(at + 2)?, *self.data.get(at + 3)?];
+
+        Some(if self.little { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        usize::try_from(self.u32_at(4)?).ok()
+    }
+
+    // Offset of the 12-byte entry for the tag in the IFD at the offset, whose value or value offset is 8 bytes in
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let entries = usize::from(self.u16_at(ifd)?);
+
+        (0..entries)
+            .map(|n| ifd + 2 + n * 12)
+            .find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+}
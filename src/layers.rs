@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use itertools::Itertools;
+use ruzstd::StreamingDecoder;
+use serde_json::Value;
+use tar::Archive;
+
+use crate::deduplicator::{self, Deduplicator};
+use crate::options::Options;
+use crate::progress::Progress;
+
+// A regular file inside a layer, identified by its contents
+struct LayerFile {
+    layer: usize,
+    path: String,
+    size: u64
+}
+
+// An image name along with the paths to its layers, bottom first
+type Manifest = (String, Vec<String>);
+
+// A layer, shared by every image built on top of it
+struct Layer {
+    id: String,
+    images: Vec<String>
+}
+
+// Where the layers of an image are read from
+#[derive(Clone)]
+enum Source {
+    // An image folder, as laid out by `docker save` or an OCI image layout, with layers as files
+    Folder(PathBuf),
+    // A `docker save` tarball, with layers as members
+    Tarball(PathBuf)
+}
+
+// Reports files duplicated across the layers of OCI or Docker images, and across the images themselves
+// Layers shared between images are stored once by registries, so each is only scanned once
+pub fn run(options: &Options, images: &[PathBuf]) {
+    let mut layers: Vec<Layer> = Vec::new();
+    let mut sources: Vec<(Source, String)> = Vec::new();
+
+    for image in images {
+        match read_image(image) {
+            Ok((source, manifests)) => {
+                for (name, layer_paths) in manifests {
+                    for layer_path in layer_paths {
+                        let id = layer_id(&layer_path);
+
+                        if let Some(layer) = layers.iter_mut().find(|layer| layer.id == id) {
+                            layer.images.push(name.clone());
+                        } else {
+                            layers.push(Layer { id, images: vec![name.clone()] });
+                            sources.push((source.clone(), layer_path));
+                        }
+                    }
+                }
+            },
+            Err(err) => {
+                eprintln!("Failure while reading the image: {}", image.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+            }
+        }
+    }
+
+    let algorithm = options.hash_algo.algorithm();
+    let mut files: HashMap<(u64, Vec<u8>), Vec<LayerFile>> = HashMap::new();
+    let mut count = 0;
+    let mut progress = Progress::start();
+
+    for (index, (source, layer_path)) in sources.iter().enumerate() {
+        progress.update(index, format_args!("Scanning layer {} out of {}", index + 1, sources.len()));
+
+        let result = with_layer(source, layer_path, |reader| {
+            let mut archive = Archive::new(decompressed(reader)?);
+
+            for entry in archive.entries()? {
+                let entry = entry?;
+
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+
+                let path = entry.path()?.to_string_lossy().into_owned();
+                let size = entry.size();
+
+                // Whiteouts mark deletions from lower layers, and empty files all match each other
+                if size == 0 || path.rsplit('/').next().is_some_and(|name| name.starts_with(".wh.")) {
+                    continue;
+                }
+
+                let digest = deduplicator::reader_digest(algorithm, entry)?;
+
+                count += 1;
+                files.entry((size, digest.as_ref().to_owned()))
+                    .or_default()
+                    .push(LayerFile { layer: index, path, size });
+            }
+
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            eprintln!("Failure while scanning the layer: {layer_path}");
+            eprintln!("{err:?}");
+            eprintln!();
+        }
+    }
+
+    progress.finish();
+    eprintln!();
+
+    report(options, &layers, files, count);
+}
+
+fn report(options: &Options, layers: &[Layer], files: HashMap<(u64, Vec<u8>), Vec<LayerFile>>, count: usize) {
+    // Copies within the same layer are left to hash mode, these are the ones registries store again
+    let groups: Vec<_> = files.into_values()
+        .filter(|copies| copies.iter().map(|copy| copy.layer).unique().count() > 1)
+        .sorted_by_key(|copies| std::cmp::Reverse(wasted(copies)))
+        .take(options.limit_groups.unwrap_or(usize::MAX))
+        .collect();
+
+    let mut total_wasted = 0;
+
+    for copies in &groups {
+        total_wasted += wasted(copies);
+
+        if !options.quiet {
+            println!("Found {} copies of {} bytes:", copies.len(), copies[0].size);
+
+            for copy in copies {
+                let layer = &layers[copy.layer];
+
+                println!("  {} in layer {} of {}", copy.path, short_id(&layer.id), layer.images.iter().unique().join(", "));
+            }
+
+            println!();
+        }
+    }
+
+    if options.no_summary {
+        return;
+    }
+
+    let images = layers.iter()
+        .flat_map(|layer| layer.images.iter())
+        .unique()
+        .count();
+
+    println!("Summary:");
+    println!("{images} images, {} distinct layers", layers.len());
+    println!("{count} files scanned");
+    println!("{} files stored in more than one layer", groups.len());
+    println!("{} stored more than once", Deduplicator::format_size(total_wasted, 2));
+}
+
+// Bytes taken by every copy but one
+fn wasted(copies: &[LayerFile]) -> u64 {
+    copies[0].size * (copies.len() as u64 - 1)
+}
+
+// The images in a folder or tarball
+fn read_image(path: &Path) -> io::Result<(Source, Vec<Manifest>)> {
+    if path.is_dir() {
+        let manifests = if path.join("manifest.json").is_file() {
+            docker_manifests(&json_file(&path.join("manifest.json"))?, path)
+        } else {
+            oci_manifests(path)?
+        };
+
+        return Ok((Source::Folder(path.to_owned()), manifests));
+    }
+
+    let source = Source::Tarball(path.to_owned());
+    let mut manifest = None;
+
+    // `docker save` writes the manifest last, so it takes a pass over the tarball to find it
+    for entry in Archive::new(BufReader::new(File::open(path)?)).entries()? {
+        let mut entry = entry?;
+
+        if member_name(&entry.path()?) == "manifest.json" {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+
+            manifest = Some(serde_json::from_str(&contents)?);
+            break;
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no manifest.json in the tarball"))?;
+
+    Ok((source, docker_manifests(&manifest, path)))
+}
+
+// manifest.json lists every image with its tags and the paths to its layers
+fn docker_manifests(manifest: &Value, path: &Path) -> Vec<Manifest> {
+    manifest.as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(|image| {
+            let name = image["RepoTags"][0].as_str()
+                .map_or_else(|| path.to_string_lossy().into_owned(), ToOwned::to_owned);
+            let layers = image["Layers"].as_array()
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .filter_map(Value::as_str)
+                .map(ToOwned::to_owned)
+                .collect();
+
+            (name, layers)
+        })
+        .collect()
+}
+
+// index.json points at manifests by digest, which may be indexes themselves for multi-platform images
+fn oci_manifests(path: &Path) -> io::Result<Vec<Manifest>> {
+    let index = json_file(&path.join("index.json"))?;
+    let mut images = Vec::new();
+
+    for descriptor in index["manifests"].as_array().map_or(&[][..], Vec::as_slice) {
+        let name = descriptor["annotations"]["org.opencontainers.image.ref.name"].as_str()
+            .map_or_else(|| path.to_string_lossy().into_owned(), ToOwned::to_owned);
+
+        let mut pending = vec![descriptor.clone()];
+
+        while let Some(descriptor) = pending.pop() {
+            let Some(blob) = descriptor["digest"].as_str().map(blob_path) else {
+                continue;
+            };
+            let manifest = json_file(&path.join(&blob))?;
+
+            if let Some(nested) = manifest["manifests"].as_array() {
+                pending.extend(nested.iter().cloned());
+                continue;
+            }
+
+            let layers = manifest["layers"].as_array()
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .filter_map(|layer| layer["digest"].as_str())
+                .map(blob_path)
+                .collect();
+
+            images.push((name.clone(), layers));
+        }
+    }
+
+    Ok(images)
+}
+
+// `sha256:abcd...` to `blobs/sha256/abcd...`
+fn blob_path(digest: &str) -> String {
+    format!("blobs/{}", digest.replacen(':', "/", 1))
+}
+
+// Layers are named after their digest in OCI layouts, or their ID in older `docker save` tarballs
+fn layer_id(layer_path: &str) -> String {
+    layer_path.trim_end_matches("/layer.tar")
+        .trim_start_matches("blobs/")
+        .replacen('/', ":", 1)
+}
+
+fn short_id(id: &str) -> &str {
+    let hex = id.rsplit(':').next().unwrap_or(id);
+
+    &hex[..hex.len().min(12)]
+}
+
+// Tarballs made with `tar -C folder .` prefix every member with `./`
+fn member_name(path: &Path) -> String {
+    path.to_string_lossy().trim_start_matches("./").to_owned()
+}
+
+fn json_file(path: &Path) -> io::Result<Value> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+// Hands a reader over the raw layer to the callback, wherever the layer is stored
+fn with_layer(source: &Source, layer_path: &str, read: impl FnOnce(Box<dyn Read + '_>) -> io::Result<()>) -> io::Result<()> {
+    match source {
+        Source::Folder(folder) => read(Box::new(BufReader::new(File::open(folder.join(layer_path))?))),
+        Source::Tarball(tarball) => {
+            let mut archive = Archive::new(BufReader::new(File::open(tarball)?));
+
+            for entry in archive.entries()? {
+                let entry = entry?;
+
+                if member_name(&entry.path()?) == layer_path.trim_start_matches("./") {
+                    return read(Box::new(entry));
+                }
+            }
+
+            Err(io::Error::new(io::ErrorKind::NotFound, "layer missing from the tarball"))
+        }
+    }
+}
+
+// Layers may be stored as plain, gzip or zstd compressed tars, told apart by their magic bytes
+fn decompressed<'a>(reader: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let magic = io::BufRead::fill_buf(&mut reader)?;
+    let gzip = magic.starts_with(&[0x1f, 0x8b]);
+    let zstd = magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]);
+
+    if gzip {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else if zstd {
+        let decoder = StreamingDecoder::new(reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
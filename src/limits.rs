@@ -0,0 +1,54 @@
+use walkdir::DirEntry;
+
+use crate::options::Options;
+
+// Cuts the walk short once --max-files or --max-bytes are exceeded,
+// for quick sampling runs over enormous trees
+pub struct ScanLimit {
+    max_files: Option<usize>,
+    max_bytes: Option<u64>,
+    files: usize,
+    bytes: u64,
+    reached: bool
+}
+
+impl ScanLimit {
+    pub const fn new(options: &Options) -> Self {
+        Self {
+            max_files: options.max_files,
+            max_bytes: options.max_bytes,
+            files: 0,
+            bytes: 0,
+            reached: false
+        }
+    }
+
+    // Whether the entry still fits within the limits
+    // Folders don't count towards them
+    pub fn admit(&mut self, entry: &DirEntry) -> bool {
+        if self.reached {
+            return false;
+        }
+
+        if !entry.file_type().is_file() {
+            return true;
+        }
+
+        self.files += 1;
+
+        if self.max_bytes.is_some() {
+            self.bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+
+        let over_files = self.max_files.is_some_and(|max| self.files > max);
+        let over_bytes = self.max_bytes.is_some_and(|max| self.bytes > max);
+
+        if over_files || over_bytes {
+            self.reached = true;
+
+            eprintln!("Scan limit reached, stopping after {} files", self.files - 1);
+        }
+
+        !self.reached
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs::{self, FileTimes};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LinkKind {
+    Hard,
+    // Copy-on-write clone, which shares the data blocks but is otherwise a file of its own
+    Reflink
+}
+
+// Replaces a duplicate with a link to the kept file
+// The link is made under a temporary name and renamed over the duplicate, so a failure never loses it
+// Reflinks keep the timestamps and permissions of the duplicate they replace, hard links share the kept file's
+pub fn replace(source: &Path, dup: &Path, kind: LinkKind) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dup)?;
+    let temp = temp_path(dup);
+
+    let linked = match kind {
+        LinkKind::Hard => fs::hard_link(source, &temp),
+        LinkKind::Reflink => reflink(source, &temp).and_then(|()| {
+            fs::set_permissions(&temp, metadata.permissions())?;
+
+            set_times(&temp, metadata.accessed()?, metadata.modified()?)
+        })
+    };
+
+    if let Err(err) = linked.and_then(|()| fs::rename(&temp, dup)) {
+        let _ = fs::remove_file(&temp);
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+    path.with_file_name(format!(".{name}.dedup-link"))
+}
+
+pub fn set_times(path: &Path, accessed: SystemTime, modified: SystemTime) -> io::Result<()> {
+    let file = fs::File::options().write(true).open(path)?;
+
+    file.set_times(FileTimes::new().set_accessed(accessed).set_modified(modified))
+}
+
+pub fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let accessed = fs::metadata(path)?.accessed()?;
+
+    set_times(path, accessed, modified)
+}
+
+#[cfg(target_os = "linux")]
+pub fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // Same as cp --reflink, supported on Btrfs, XFS and bcachefs among others
+    const FICLONE: libc::c_ulong = 0x4004_9409;
+
+    let src = fs::File::open(source)?;
+    let dst = fs::File::options().write(true).create_new(true).open(target)?;
+
+    // The request is an unsigned long on glibc but an int on musl
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    if unsafe { libc::ioctl(dst.as_raw_fd(), FICLONE as _, src.as_raw_fd()) } == -1 {
+        let err = io::Error::last_os_error();
+        let _ = fs::remove_file(target);
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(source.as_os_str().as_bytes())?;
+    let dst = CString::new(target.as_os_str().as_bytes())?;
+
+    // APFS clones, the same as Finder makes when duplicating a file
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    let src = fs::File::open(source)?;
+    let dst = fs::File::options().write(true).create_new(true).open(target)?;
+
+    if let Err(err) = block_clone(&src, &dst, target) {
+        drop(dst);
+        let _ = fs::remove_file(target);
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+// Block cloning, supported on ReFS and Dev Drives, which shares clusters between files much like reflinks
+#[cfg(windows)]
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn block_clone(src: &fs::File, dst: &fs::File, target: &Path) -> io::Result<()> {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::AsRawHandle;
+
+    const FSCTL_DUPLICATE_EXTENTS_TO_FILE: u32 = 0x0009_8344;
+    // Each request must clone less than 4GB, and a whole number of clusters
+    const MAX_CHUNK: u64 = 1 << 31;
+    const MAX_PATH: usize = 260;
+
+    #[repr(C)]
+    struct DuplicateExtentsData {
+        file_handle: *mut c_void,
+        source_file_offset: i64,
+        target_file_offset: i64,
+        byte_count: i64
+    }
+
+    extern "system" {
+        fn DeviceIoControl(device: *mut c_void, code: u32, input: *const c_void, input_size: u32, output: *mut c_void, output_size: u32, returned: *mut u32, overlapped: *mut c_void) -> i32;
+        fn GetVolumePathNameW(file_name: *const u16, volume_path: *mut u16, len: u32) -> i32;
+        fn GetDiskFreeSpaceW(root: *const u16, sectors_per_cluster: *mut u32, bytes_per_sector: *mut u32, free_clusters: *mut u32, total_clusters: *mut u32) -> i32;
+    }
+
+    let wide: Vec<u16> = target.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut volume = [0_u16; MAX_PATH];
+    let (mut sectors_per_cluster, mut bytes_per_sector, mut free, mut total) = (0, 0, 0, 0);
+
+    if unsafe { GetVolumePathNameW(wide.as_ptr(), volume.as_mut_ptr(), MAX_PATH as u32) } == 0
+        || unsafe { GetDiskFreeSpaceW(volume.as_ptr(), &mut sectors_per_cluster, &mut bytes_per_sector, &mut free, &mut total) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let cluster = u64::from(sectors_per_cluster) * u64::from(bytes_per_sector);
+    let len = src.metadata()?.len();
+
+    // The clone has to fit in the target already, but may run past its end up to the next cluster
+    dst.set_len(len)?;
+
+    let mut offset = 0;
+
+    while offset < len {
+        let count = (len - offset).next_multiple_of(cluster).min(MAX_CHUNK);
+        let request = DuplicateExtentsData {
+            file_handle: src.as_raw_handle().cast(),
+            source_file_offset: offset as i64,
+            target_file_offset: offset as i64,
+            byte_count: count as i64
+        };
+        let mut returned = 0;
+
+        let cloned = unsafe {
+            DeviceIoControl(
+                dst.as_raw_handle().cast(),
+                FSCTL_DUPLICATE_EXTENTS_TO_FILE,
+                std::ptr::addr_of!(request).cast(),
+                std::mem::size_of::<DuplicateExtentsData>() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut()
+            )
+        };
+
+        if cloned == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        offset += count;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn reflink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are not supported on this platform"))
+}
+
+// Identifies the data of an APFS file, which clones share until either is written to, so
+// files with the same ID take up the space of one. Only given for files that may share blocks
+#[cfg(target_os = "macos")]
+pub fn clone_id(path: &Path) -> Option<u64> {
+    use std::convert::TryInto;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Set on files that have been cloned, or are clones themselves
+    const EF_MAY_SHARE_BLOCKS: u64 = 0x1;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+
+    // Extended common attributes go in the fork attributes with FSOPT_ATTR_CMN_EXTENDED
+    let mut request = libc::attrlist {
+        bitmapcount: libc::ATTR_BIT_MAP_COUNT,
+        reserved: 0,
+        commonattr: 0,
+        volattr: 0,
+        dirattr: 0,
+        fileattr: 0,
+        forkattr: libc::ATTR_CMNEXT_CLONEID | libc::ATTR_CMNEXT_EXT_FLAGS
+    };
+    // The length of the returned attributes, then each in the order of their bits
+    let mut buf = [0_u8; 4 + 8 + 8];
+
+    let result = unsafe {
+        libc::getattrlist(
+            path.as_ptr(),
+            std::ptr::addr_of_mut!(request).cast(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            libc::FSOPT_ATTR_CMN_EXTENDED | libc::FSOPT_NOFOLLOW
+        )
+    };
+
+    if result == -1 {
+        return None;
+    }
+
+    let clone_id = u64::from_ne_bytes(buf[4..12].try_into().ok()?);
+    let flags = u64::from_ne_bytes(buf[12..20].try_into().ok()?);
+
+    (flags & EF_MAY_SHARE_BLOCKS != 0).then_some(clone_id)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub const fn clone_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+// Whether both paths are hard links to the same file already, so linking one to the other frees nothing
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false
+    }
+}
+
+#[cfg(not(unix))]
+pub const fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
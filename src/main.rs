@@ -2,27 +2,190 @@
 #![deny(clippy::nursery)]
 #![deny(clippy::pedantic)]
 
+mod artifacts;
+mod audio;
+mod bench;
+mod bundles;
+mod caches;
+mod checksums;
+mod clip;
+mod config;
+mod compressed;
+mod conflicts;
+mod diff;
+mod dirpolicy;
+mod dirtimes;
+mod doctor;
+mod estimate;
+mod explain;
+mod external;
+mod failures;
+mod forensic;
+mod generated;
+mod generations;
+mod graph;
+mod hidden;
+#[cfg(feature = "http")]
+mod http;
+mod i18n;
+mod imagecache;
+mod images;
+mod layers;
+mod limits;
+mod links;
+mod md5;
+mod office;
 mod options;
+mod owners;
+mod packages;
+mod paths;
+mod pdf;
+mod plan;
+mod policy;
+mod progress;
+mod random;
+mod rationale;
+mod readahead;
+mod reparse;
+mod roots;
+mod sample;
+mod selftest;
+mod serve;
+mod session;
+mod sidecars;
+mod source;
+mod space;
+mod spill;
+mod stats;
+mod storage;
+mod streams;
+mod subjects;
+mod text;
+mod throttle;
+mod trash;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod video;
+mod vss;
+mod watchdog;
 mod deduplicator;
 mod similarity;
 
-use clap::Parser;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use clap::{CommandFactory, ErrorKind, Parser};
 use deduplicator::Deduplicator;
 
-use options::{Options, Mode};
+use config::Config;
+use options::{Options, Mode, Command};
+use serve::Server;
 use similarity::Similarity;
 
 fn main() {
     ctrlc::set_handler(|| {
-        eprint!("{}", ansi_escapes::CursorShow);
+        if io::stderr().is_terminal() {
+            eprint!("{}", ansi_escapes::CursorShow);
+        }
+
         std::process::exit(0);
     })
     .expect("Error setting Ctrl-C handler");
 
     let options = Options::parse();
 
+    let scans = !matches!(options.command, Some(Command::VerifyPlan { .. } | Command::Verify { .. } | Command::Report { .. } | Command::Bench { .. } | Command::Layers { .. } | Command::Serve(_) | Command::SelfTest));
+
+    if scans && options.path.as_os_str().is_empty() {
+        Options::command()
+            .error(ErrorKind::MissingRequiredArgument, "The --path <PATH> argument, or DEDUP_PATH, is required to scan")
+            .exit();
+    }
+
+    // Before any confirmation, so the question is asked knowing what it's about
+    if options.explain {
+        explain::print(&options);
+    }
+
+    check_actions(&options);
+
+    match options.command {
+        Some(Command::Estimate { samples, seed }) => {
+            let deduplicator = Deduplicator::new(options);
+
+            deduplicator.estimate(samples, seed);
+
+            return;
+        },
+        Some(Command::VerifyPlan { ref plan }) => {
+            let plan = plan.clone();
+
+            plan::verify(options, &plan);
+
+            return;
+        },
+        Some(Command::Verify { ref manifest }) => {
+            let manifest = manifest.clone();
+
+            checksums::verify(options, &manifest);
+
+            return;
+        },
+        Some(Command::Report { ref session }) => {
+            session::report(&options, session);
+
+            return;
+        },
+        Some(Command::Doctor) => {
+            doctor::run(&options);
+
+            return;
+        },
+        Some(Command::Query { ref file, ref hash }) => {
+            if let Some(hash) = hash.clone() {
+                let deduplicator = Deduplicator::new(options);
+
+                deduplicator.find_copies(&hash);
+            } else if let Some(file) = file.clone() {
+                let similarity = Similarity::new(options);
+
+                similarity.query(&file);
+            }
+
+            return;
+        },
+        Some(Command::Packages) => {
+            packages::run(options);
+
+            return;
+        },
+        Some(Command::Bench { size, ref buffers }) => {
+            bench::run(&options, size, buffers);
+
+            return;
+        },
+        Some(Command::Layers { ref images }) => {
+            layers::run(&options, images);
+
+            return;
+        },
+        Some(Command::Serve(ref serve)) => {
+            let serve = serve.clone();
+            let server = Server::new(options, serve);
+
+            server.execute();
+
+            return;
+        },
+        Some(Command::SelfTest) => {
+            selftest::run();
+
+            return;
+        },
+        None => {}
+    }
+
     match options.mode {
-        Mode::Hash => {
+        Mode::Hash | Mode::QuickSignature => {
             let deduplicator = Deduplicator::new(options);
 
             deduplicator.execute();
@@ -31,6 +194,118 @@ fn main() {
             let similarity = Similarity::new(options);
 
             similarity.execute();
+        },
+        Mode::SyncConflicts => {
+            let deduplicator = Deduplicator::new(options);
+
+            deduplicator.clean_conflicts();
+        },
+        Mode::BackupGenerations => {
+            let deduplicator = Deduplicator::new(options);
+
+            deduplicator.link_generations();
         }
     }
 }
+
+// Refuses actions that could lose files that are no duplicates, unless told otherwise
+fn check_actions(options: &Options) {
+    // Acting on every duplicate under a mistyped path like `/` can't be taken back
+    let acts = options.delete || options.rename_dupes.is_some() || options.hardlink || options.reflink;
+
+    // Plans are acted on in the folder they were written for
+    let root = match options.command {
+        None | Some(Command::Packages) => Some(("--path", options.path.clone())),
+        Some(Command::VerifyPlan { ref plan }) => plan::root(plan).map(|root| ("the plan's folder", root)),
+        Some(_) => None
+    };
+
+    if let (true, Some((name, root))) = (acts, root) {
+        if let Some(reason) = roots::sensitive(&root) {
+            eprintln!("Warning: {name} {} is {reason}", root.display());
+            eprintln!("Warning: duplicates anywhere under it will be deleted, renamed or linked, including ones programs rely on");
+            eprintln!();
+
+            if !options.force_root {
+                Options::command()
+                    .error(ErrorKind::MissingRequiredArgument, "Pass --force-root to act on duplicates in such a folder anyway")
+                    .exit();
+            }
+        }
+    }
+
+    // A file deleted from a newer generation would be missing from that backup, so generations are only ever linked
+    if matches!(options.mode, Mode::BackupGenerations) && (options.delete || options.rename_dupes.is_some()) {
+        Options::command()
+            .error(ErrorKind::ArgumentConflict, "--mode backup-generations only links unchanged files, pass --hardlink or --reflink instead of --delete or --rename-dupes")
+            .exit();
+    }
+
+    // Files sharing their first bytes may still differ further in, so they're only acted on once checked in full
+    if matches!(options.mode, Mode::QuickSignature) && acts {
+        Options::command()
+            .error(ErrorKind::ArgumentConflict, "--mode quick-signature only finds probable duplicates, pass --write-plan and act on them with `verify-plan`, which checks them in full first")
+            .exit();
+    }
+
+    if matches!(options.mode, Mode::QuickSignature) && (options.export_checksums.is_some() || options.session.is_some()) {
+        Options::command()
+            .error(ErrorKind::ArgumentConflict, "--mode quick-signature only hashes the start of files, so it can't be used with --export-checksums or --session")
+            .exit();
+    }
+
+    let config = Config::load();
+
+    if !acts || !config.confirms_actions() {
+        return;
+    }
+
+    let target = match options.command {
+        None | Some(Command::Packages) => format!("the duplicates found under {}", options.path.display()),
+        Some(Command::VerifyPlan { ref plan }) => format!("the duplicates of the plan {} that didn't change", plan.display()),
+        _ => return
+    };
+
+    if options.yes && config.allows_yes() {
+        return;
+    }
+
+    if !io::stdin().is_terminal() {
+        let message = if options.yes {
+            "The config file turns --yes off, so acting on duplicates has to be confirmed at a terminal"
+        } else {
+            "Pass --yes to delete, rename or link duplicates without a terminal to confirm it at"
+        };
+
+        Options::command()
+            .error(ErrorKind::MissingRequiredArgument, message)
+            .exit();
+    }
+
+    let verb = if options.delete {
+        "Delete"
+    } else if options.rename_dupes.is_some() {
+        "Rename"
+    } else {
+        "Link"
+    };
+
+    if !ask(&format!("{verb} {target}?")) {
+        eprintln!("Stopped before acting on any duplicate");
+        std::process::exit(1);
+    }
+}
+
+// Asks a yes or no question at the terminal, taking anything but yes as no
+fn ask(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
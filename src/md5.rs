@@ -0,0 +1,111 @@
+use std::io::{self, Read};
+
+// MD5, only for comparing against the checksums dpkg keeps of packaged files, never for finding duplicates
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21
+];
+
+// The integer part of abs(sin(i + 1)) * 2^32
+const CONSTANTS: [u32; 64] = [
+    0xd76a_a478, 0xe8c7_b756, 0x2420_70db, 0xc1bd_ceee,
+    0xf57c_0faf, 0x4787_c62a, 0xa830_4613, 0xfd46_9501,
+    0x6980_98d8, 0x8b44_f7af, 0xffff_5bb1, 0x895c_d7be,
+    0x6b90_1122, 0xfd98_7193, 0xa679_438e, 0x49b4_0821,
+    0xf61e_2562, 0xc040_b340, 0x265e_5a51, 0xe9b6_c7aa,
+    0xd62f_105d, 0x0244_1453, 0xd8a1_e681, 0xe7d3_fbc8,
+    0x21e1_cde6, 0xc337_07d6, 0xf4d5_0d87, 0x455a_14ed,
+    0xa9e3_e905, 0xfcef_a3f8, 0x676f_02d9, 0x8d2a_4c8a,
+    0xfffa_3942, 0x8771_f681, 0x6d9d_6122, 0xfde5_380c,
+    0xa4be_ea44, 0x4bde_cfa9, 0xf6bb_4b60, 0xbebf_bc70,
+    0x289b_7ec6, 0xeaa1_27fa, 0xd4ef_3085, 0x0488_1d05,
+    0xd9d4_d039, 0xe6db_99e5, 0x1fa2_7cf8, 0xc4ac_5665,
+    0xf429_2244, 0x432a_ff97, 0xab94_23a7, 0xfc93_a039,
+    0x655b_59c3, 0x8f0c_cc92, 0xffef_f47d, 0x8584_5dd1,
+    0x6fa8_7e4f, 0xfe2c_e6e0, 0xa301_4314, 0x4e08_11a1,
+    0xf753_7e82, 0xbd3a_f235, 0x2ad7_d2bb, 0xeb86_d391
+];
+
+const BLOCK: usize = 64;
+
+pub fn digest(mut reader: impl Read) -> io::Result<[u8; 16]> {
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    let mut buffer = vec![0; BLOCK * 1024];
+    let mut pending = 0;
+    let mut length: u64 = 0;
+
+    loop {
+        let read = match reader.read(&mut buffer[pending..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+
+        length += read as u64;
+        pending += read;
+
+        let full = pending - pending % BLOCK;
+
+        for block in buffer[..full].chunks_exact(BLOCK) {
+            compress(&mut state, block);
+        }
+
+        buffer.copy_within(full..pending, 0);
+        pending -= full;
+    }
+
+    // A 1 bit, zeros up to 8 bytes short of a block, then the length in bits
+    let mut tail = buffer[..pending].to_vec();
+    tail.push(0x80);
+
+    while tail.len() % BLOCK != BLOCK - 8 {
+        tail.push(0);
+    }
+
+    tail.extend_from_slice(&length.wrapping_mul(8).to_le_bytes());
+
+    for block in tail.chunks_exact(BLOCK) {
+        compress(&mut state, block);
+    }
+
+    let mut digest = [0; 16];
+
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+
+    Ok(digest)
+}
+
+fn compress(state: &mut [u32; 4], block: &[u8]) {
+    let words: Vec<u32> = block.chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    let [mut a, mut b, mut c, mut d] = *state;
+
+    for i in 0..64 {
+        let (mixed, word) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16)
+        };
+
+        let rotated = a.wrapping_add(mixed)
+            .wrapping_add(CONSTANTS[i])
+            .wrapping_add(words[word])
+            .rotate_left(SHIFTS[i]);
+
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+
+    for (word, added) in state.iter_mut().zip([a, b, c, d]) {
+        *word = word.wrapping_add(added);
+    }
+}
@@ -0,0 +1,56 @@
+use std::io::{self, Read};
+use std::fs;
+use std::path::Path;
+
+use ring::digest::{Algorithm, Context, Digest};
+use zip::ZipArchive;
+
+// OOXML and OpenDocument formats, all of which are zip containers
+const OFFICE_EXTENSIONS: [&str; 9] = ["docx", "docm", "xlsx", "xlsm", "pptx", "pptm", "odt", "ods", "odp"];
+
+pub fn is_office(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            OFFICE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+// Hashes the names and uncompressed contents of every member, in name order,
+// so that the compression level, member order and zip timestamps don't matter
+pub fn digest(path: &Path, algorithm: &'static Algorithm) -> io::Result<Digest> {
+    let file = fs::File::open(path)?;
+    let mut archive = ZipArchive::new(io::BufReader::new(file))?;
+
+    let mut names: Vec<String> = archive.file_names()
+        .map(ToOwned::to_owned)
+        .collect();
+    names.sort();
+
+    let mut ctx = Context::new(algorithm);
+    let mut buf = [0; 1024];
+
+    for name in names {
+        let mut member = archive.by_name(&name)?;
+
+        if member.is_dir() {
+            continue;
+        }
+
+        // Length-prefix the name so member boundaries can't be confused for one another
+        ctx.update(&(name.len() as u64).to_le_bytes());
+        ctx.update(name.as_bytes());
+        ctx.update(&member.size().to_le_bytes());
+
+        loop {
+            let count = member.read(&mut buf)?;
+            if count == 0 {
+                break;
+            }
+
+            ctx.update(&buf[..count]);
+        }
+    }
+
+    Ok(ctx.finish())
+}
@@ -1,49 +1,452 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::{Parser, ValueEnum};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use ring::digest::{Algorithm, SHA256, SHA512};
 
-#[derive(Parser, Debug)]
+use crate::graph::Format;
+
+#[derive(Parser, Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 #[clap(name = "deduplicator", about = "Deduplicates files in a folder")]
+// For options that only apply to linking duplicates, either way
+#[clap(group(ArgGroup::new("link").args(&["hardlink", "reflink"])))]
 pub struct Options {
-    #[clap(long, help = "Path towards the folder to scan")]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    // Required for scanning, which main checks for, as `verify-plan`, `bench`, `layers` and `serve` can do without it
+    #[clap(long, env = "DEDUP_PATH", default_value = "", hide_default_value = true, help = "Path towards the folder to scan")]
     pub path: PathBuf,
 
-    #[clap(long, value_enum, default_value = "first", help = "What file to keep; `first` or `last`")]
+    #[clap(long, env = "DEDUP_KEEP", value_enum, default_value = "first", help = "What file to keep; `first` or `last`")]
     pub keep: Keep,
 
     // // TODO: Make an enum
     // #[clap(long, default_value = "modified", help = "How to order files; `modified`, `created`, `name`")]
     // pub order: String,
 
-    #[clap(long, value_enum, default_value = "modified", help = "How to order files; `modified`, `created`, `name`")]
+    #[clap(long, env = "DEDUP_ORDER", value_enum, default_value = "modified", help = "How to order files; `modified`, `created`, `name`")]
     pub order: FileOrdering,
 
-    #[clap(long, help = "Whether to delete the duplicate files")]
+    #[clap(long, env = "DEDUP_KEEP_DIR_ORDER", value_delimiter = ',', help = "Comma-separated folders, relative to the path, in order of preference for the file to keep. Takes priority over --keep")]
+    pub keep_dir_order: Vec<PathBuf>,
+
+    #[clap(long, env = "DEDUP_KEEP_PERMISSIONS", value_enum, help = "Keep the copy with the `strictest` or `loosest` permissions, such as one that isn't world-writable. Takes priority over --keep, but not --keep-dir-order")]
+    pub keep_permissions: Option<PermissionOrder>,
+
+    #[clap(long, env = "DEDUP_SELECT_CMD", help = "Shell command deciding which file of each group to keep, given the group as JSON on stdin and printing the path to keep. Groups it fails on are left alone. Takes priority over every other rule")]
+    pub select_cmd: Option<String>,
+
+    #[clap(long, env = "DEDUP_LANG", value_enum, help = "Language of the report; `en`, `es` or `de`. Defaults to the language of the locale, or English if it isn't one of these. Used in hash mode")]
+    pub lang: Option<Lang>,
+
+    #[clap(long, env = "DEDUP_VSS", help = "Whether to hash files from a shadow copy of the volume taken at the start, so files held open by other programs are read consistently and without sharing violations. Duplicates are still acted on in the live volume. Windows only, run from an elevated prompt. Used in hash mode")]
+    pub vss: bool,
+
+    #[clap(long, env = "DEDUP_PARANOID", help = "Whether to hash each kept file again right before acting on its duplicates, leaving the group alone if it changed since it was hashed. Used in hash mode")]
+    pub paranoid: bool,
+
+    #[clap(long, env = "DEDUP_ONLY_OLDER_COPIES", help = "Whether to only act on duplicates last modified strictly before the kept file, leaving ones as new or newer alone, like when cleaning up sync conflicts. Used in hash mode")]
+    pub only_older_copies: bool,
+
+    #[clap(long, env = "DEDUP_ONLY_DELETE_OLDER_THAN", value_name = "AGE", value_parser = parse_duration, help = "Only act on duplicates last modified longer ago than this, e.g. `12h`, `30d` or `2w`, leaving recent copies alone, like in a folder files are still coming into. Used in hash mode")]
+    pub only_delete_older_than: Option<Duration>,
+
+    #[clap(long, env = "DEDUP_DELETE", help = "Whether to delete the duplicate files")]
     pub delete: bool,
 
-    #[clap(long, help = "Whether to shut the fuck up")]
+    #[clap(long, env = "DEDUP_YES", help = "Whether to delete, rename or link duplicates without asking first, which is needed to act on them without a terminal to ask at, unless the config file turns that off")]
+    pub yes: bool,
+
+    #[clap(long, env = "DEDUP_EXPLAIN", help = "Whether to first describe in plain words what the given options will do, like what gets scanned, which copy is kept and what happens to the rest, before going ahead")]
+    pub explain: bool,
+
+    #[clap(long, env = "DEDUP_FORCE_ROOT", help = "Whether to delete, rename or link duplicates even when --path is the root of a filesystem or drive, a home folder or a system folder")]
+    pub force_root: bool,
+
+    #[clap(long, env = "DEDUP_TRASH", requires = "delete", help = "Whether to move deleted files to the trash, where they can be restored from, instead of removing them for good. They still take up space until it's emptied. Linux and macOS only")]
+    pub trash: bool,
+
+    #[clap(long, env = "DEDUP_EMPTY_TRASH_AFTER", value_name = "DAYS", requires = "trash", help = "Say when the files moved to the trash can be emptied from it, this many days from now, in the summary")]
+    pub empty_trash_after: Option<u64>,
+
+    #[clap(long, env = "DEDUP_PRESERVE_DIR_TIMES", requires = "delete", help = "Whether to put back the modification times of the folders files are deleted from, for photo managers and backup tools that look for changed folders by them")]
+    pub preserve_dir_times: bool,
+
+    #[clap(long, env = "DEDUP_PRUNE_EMPTY_DIRS", requires = "delete", help = "Whether to remove the folders left empty by deleting duplicates once done, except where a .dedup-policy.toml protects")]
+    pub prune_empty_dirs: bool,
+
+    #[clap(long, env = "DEDUP_DELETE_RATE", requires = "delete", value_name = "FILES_PER_SEC", help = "Delete at most this many files a second, for network file systems that struggle with bursts of deletions")]
+    pub delete_rate: Option<f64>,
+
+    #[clap(long, env = "DEDUP_DELETE_BATCH", requires = "delete", value_name = "N", help = "Delete files in batches of this many, starting at most one batch a second, or as --delete-rate allows")]
+    pub delete_batch: Option<usize>,
+
+    #[clap(long, env = "DEDUP_MAX_DELETE_FILES", value_name = "N", requires = "delete", help = "Stop deleting once this many duplicates were deleted, only listing the remaining groups, to limit what a misconfigured run can take out. Also applies to `verify-plan`, `packages` and --find-thumbnails")]
+    pub max_delete_files: Option<usize>,
+
+    #[clap(long, env = "DEDUP_MAX_DELETE_BYTES", value_parser = parse_size, requires = "delete", help = "Stop deleting once duplicates of this many bytes were deleted, e.g. `10G`, only listing the remaining groups. Also applies to `verify-plan`, `packages` and --find-thumbnails")]
+    pub max_delete_bytes: Option<u64>,
+
+    #[clap(long, env = "DEDUP_RENAME_DUPES", conflicts_with = "delete", help = "Rename the duplicate files instead of deleting them, e.g. `{name}.dup{n}{ext}`. Also supports `{hash}`")]
+    pub rename_dupes: Option<String>,
+
+    #[clap(long, env = "DEDUP_HARDLINK", conflicts_with_all = &["delete", "rename-dupes"], help = "Whether to replace the duplicate files with hard links to the kept one")]
+    pub hardlink: bool,
+
+    #[clap(long, env = "DEDUP_REFLINK", visible_alias = "block-clone", conflicts_with_all = &["delete", "rename-dupes", "hardlink"], help = "Whether to replace the duplicate files with copy-on-write clones of the kept one, on Btrfs, XFS or APFS, or with block clones on ReFS and Dev Drives on Windows. Clones keep their own timestamps")]
+    pub reflink: bool,
+
+    #[clap(long, env = "DEDUP_KEEP_NEWEST_MTIME", requires = "link", help = "Whether to give the kept file the newest modification time in its group when linking the duplicates to it")]
+    pub keep_newest_mtime: bool,
+
+    #[clap(long, env = "DEDUP_SKIP_GROUPS_LARGER_THAN", value_name = "N", help = "Leave groups of more than N identical files alone, as they're more likely generated data than copies, still listing them to be looked at by hand. Used in hash mode")]
+    pub skip_groups_larger_than: Option<usize>,
+
+    #[clap(long, env = "DEDUP_WRITE_PLAN", conflicts_with_all = &["delete", "rename-dupes", "hardlink", "reflink"], help = "Write the duplicate groups to a JSON plan instead of acting on them, to be checked and executed later with `verify-plan`. Used in hash mode")]
+    pub write_plan: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_PER_USER", help = "Whether to only group together files owned by the same user, reporting each group under its owner. Unix only. Used in hash mode")]
+    pub per_user: bool,
+
+    #[clap(long, env = "DEDUP_USER_SCRIPTS", conflicts_with_all = &["delete", "rename-dupes", "hardlink", "reflink", "write-plan"], help = "Write a script per user deleting their duplicates into this folder instead of acting on them, owned by each user when run as root. Implies --per-user. Used in hash mode")]
+    pub user_scripts: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_PROGRESS_FD", value_name = "FD", help = "Write progress to this open file descriptor as a JSON line at most once a second, with the `phase`, files and bytes done out of their totals and an `eta_ms`, for wrappers to show progress with. Unix only. Used in hash mode")]
+    pub progress_fd: Option<i32>,
+
+    #[clap(long, env = "DEDUP_PROGRESS_FILE", conflicts_with = "progress-fd", help = "Write progress to this file instead, like a named pipe, the same as --progress-fd. Used in hash mode")]
+    pub progress_file: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_QUIET", help = "Whether to shut the fuck up")]
     pub quiet: bool,
 
-    #[clap(long, default_value_t = num_cpus::get(), help = "How many threads to split file reading into")]
-    pub threads: usize,
+    #[clap(long, env = "DEDUP_IO_THREADS", alias = "threads", help = "How many threads to split file reading into. Defaults to 2 on spinning disks, more on network shares and one per core elsewhere")]
+    pub io_threads: Option<usize>,
+
+    #[clap(long, env = "DEDUP_CPU_THREADS", default_value_t = num_cpus::get(), help = "How many threads to split image decoding into. Used in similarity mode")]
+    pub cpu_threads: usize,
+
+    #[clap(long, env = "DEDUP_READ_BUFFER", value_parser = parse_size, help = "How many bytes to read from a file at a time while hashing, e.g. `64K` or `1M`. Defaults to 1M on spinning disks and network shares and 64K elsewhere, see `bench`. Used in hash mode")]
+    pub read_buffer: Option<u64>,
 
-    #[clap(long, help = "Whether to not search subfolders recursively")]
+    #[clap(long, env = "DEDUP_HASH_ALGO", value_enum, default_value = "sha256", help = "Which hash to compare file contents with; `sha256`, or `sha512` which is faster on most 64-bit CPUs without SHA instructions, see `bench`. Used in hash mode")]
+    pub hash_algo: HashAlgo,
+
+    #[clap(long, env = "DEDUP_PREFETCH", help = "Whether to start reading the next file of a group into the cache while hashing the current one, which helps with cold caches on spinning disks and network shares. Used in hash mode")]
+    pub prefetch: bool,
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    #[clap(long, env = "DEDUP_IO_URING", help = "Whether to hash files through io_uring, with reads for many files in flight at once. Used in hash mode")]
+    pub io_uring: bool,
+
+    #[clap(long, env = "DEDUP_NO_RECURSIVE", help = "Whether to not search subfolders recursively")]
     pub no_recursive: bool,
 
-    #[clap(long, help = "Whether to show the summary at the end")]
+    #[clap(long, env = "DEDUP_MAX_FILES", help = "Stop scanning after this many files")]
+    pub max_files: Option<usize>,
+
+    #[clap(long, env = "DEDUP_MAX_MEMORY", value_parser = parse_size, help = "How much memory the files bucketed by size may take, e.g. `512M`, past which they're written out to a temporary file and read back once the scan is done, keeping only the ones that may have duplicates. Used in hash mode")]
+    pub max_memory: Option<u64>,
+
+    #[clap(long, env = "DEDUP_SPILL_DIR", conflicts_with = "case-collisions", help = "Folder to keep files and their digests in instead of memory, as sorted runs merged back together to find duplicates, for folders with tens of millions of files. Runs take up to --max-memory, or 64M, in memory while sorted. Groups come out in no particular order, so --hash-order and --sort-output don't apply. Used in hash mode")]
+    pub spill_dir: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_EXPORT_CHECKSUMS", value_enum, conflicts_with = "spill-dir", help = "Write a checksum manifest of every scanned file once done, reusing the digests hashed to find duplicates where it can; `sha256sum` or `md5sum` lines, which `sha256sum -c` and `md5sum -c` check, or `bsd` tagged lines with --hash-algo. Files deleted or renamed along the way are left out. Used in hash mode")]
+    pub export_checksums: Option<ChecksumFormat>,
+
+    #[clap(long, env = "DEDUP_CHECKSUMS_FILE", requires = "export-checksums", help = "Where to write the manifest of --export-checksums, instead of SHA256SUMS, MD5SUMS or CHECKSUMS in the path. Files in it are relative to the path either way. Used in hash mode")]
+    pub checksums_file: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_SESSION", conflicts_with = "spill-dir", help = "Record every scanned file and its digest in this session file, under the name of the scanned volume, so `report --session` can find copies across volumes scanned one at a time, like removable drives. Every file is hashed, not only the ones sharing a size with another. Used in hash mode")]
+    pub session: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_SESSION_VOLUME", requires = "session", help = "Name to record the scanned folder under in the session, replacing whatever was recorded under it before, instead of the folder's name, which is the label of removable drives mounted under /media or /Volumes")]
+    pub session_volume: Option<String>,
+
+    #[clap(long, env = "DEDUP_MAX_BYTES", value_parser = parse_size, help = "Stop scanning after this many bytes of files, e.g. `500M` or `2T`")]
+    pub max_bytes: Option<u64>,
+
+    #[clap(long, env = "DEDUP_HYDRATE", help = "Whether to read cloud placeholder files, downloading their contents from the provider")]
+    pub hydrate: bool,
+
+    #[clap(long, env = "DEDUP_LIMIT_GROUPS", help = "Stop once this many duplicate groups have been found")]
+    pub limit_groups: Option<usize>,
+
+    #[clap(long, env = "DEDUP_SAMPLE_GROUPS", value_name = "N", help = "Only list a random sample of this many duplicate groups once done, to spot-check a large run, still acting on every group. Used in hash mode")]
+    pub sample_groups: Option<usize>,
+
+    #[clap(long, env = "DEDUP_SEED", help = "Seed for picking the --sample-groups sample, to pick the same groups as a previous run")]
+    pub seed: Option<u64>,
+
+    #[clap(long, env = "DEDUP_SCAN_BUNDLES", help = "Whether to scan inside macOS bundles and packages like .app and .framework folders")]
+    pub scan_bundles: bool,
+
+    #[clap(long, env = "DEDUP_INCLUDE_CACHES", help = "Whether to scan folders tagged as caches with a CACHEDIR.TAG file, which are skipped otherwise")]
+    pub include_caches: bool,
+
+    #[clap(long, env = "DEDUP_EXCLUDE_CACHES", conflicts_with = "include-caches", help = "Whether to also skip common cache folders that aren't tagged, like .cache and __pycache__")]
+    pub exclude_caches: bool,
+
+    #[clap(long, env = "DEDUP_INCLUDE_HIDDEN", help = "Whether to scan inside hidden folders, like dotfolders on Unix and folders with the hidden attribute on Windows, which are skipped otherwise")]
+    pub include_hidden: bool,
+
+    #[clap(long, env = "DEDUP_SKIP_HIDDEN", conflicts_with = "include-hidden", help = "Whether to also skip hidden files outside of hidden folders")]
+    pub skip_hidden: bool,
+
+    #[clap(long, env = "DEDUP_NO_SUMMARY", help = "Whether to skip the summary at the end")]
     pub no_summary: bool,
 
-    #[clap(long, help = "Whether to not ignore errors (e.g. retrieving and reading files)")]
+    #[clap(long, env = "DEDUP_SUMMARY_ONLY", conflicts_with = "no-summary", help = "Whether to only print the summary, without listing each group or showing progress. Used in hash mode")]
+    pub summary_only: bool,
+
+    #[clap(long, env = "DEDUP_SUMMARY_FORMAT", value_enum, default_value = "text", help = "How to print the summary; `text`, or `json` as a single line. Used in hash mode")]
+    pub summary_format: SummaryFormat,
+
+    #[clap(long, env = "DEDUP_STALL_WARNING", default_value_t = 60, help = "Warn when no file finishes hashing for this many seconds, naming the files still being read, like ones on a hung network mount. 0 to never warn. Used in hash mode")]
+    pub stall_warning: u64,
+
+    #[clap(long, env = "DEDUP_RETRIES", default_value_t = 0, help = "How many times to retry hashing files that failed with errors that may go away, like locks held by antivirus scanners or network filesystem hiccups. They are retried once every other file is hashed, waiting 1s before the first retry and twice as long before each one after. Used in hash mode")]
+    pub retries: u32,
+
+    #[clap(long, env = "DEDUP_NO_IGNORE_ERRORS", help = "Whether to not ignore errors (e.g. retrieving and reading files)")]
     pub no_ignore_errors: bool,
 
-    #[clap(long, value_enum, help = "How to sort the duplicate groups; `modified`, `created`, `name`")]
-    pub sort_output: Option<FileOrdering>,
+    #[clap(long, env = "DEDUP_SORT_OUTPUT", value_enum, help = "How to sort the duplicate groups; `modified`, `created`, `name`, or `size` for the most reclaimable space first")]
+    pub sort_output: Option<OutputOrdering>,
+
+    #[clap(long, env = "DEDUP_HASH_ORDER", value_enum, default_value = "walk-order", help = "In what order to hash same-size files, output follows it unless --sort-output is passed")]
+    pub hash_order: HashOrder,
 
-    #[clap(long, value_enum, default_value = "hash", help = "Criteria for file duplicate finding; `hash` or `similarity`")]
+    #[clap(long, env = "DEDUP_MODE", value_enum, default_value = "hash", help = "Criteria for file duplicate finding; `hash`, `similarity`, `sync-conflicts` to only match sync conflict copies against the file they were made of, `backup-generations` to match files against the same path in the dated backup folders before them, or `quick-signature` to only compare the size and first 4 KiB of files, a much faster triage pass finding probable duplicates to check in full with `--write-plan` and `verify-plan`")]
     pub mode: Mode,
 
-    #[clap(long, default_value = "95", help = "Required similarity for reporting duplicate images. Used in similarity mode. 0-100, 100 indicating exact match")]
-    pub similarity_score: u32
+    #[clap(long, env = "DEDUP_SIMILARITY_SCORE", visible_alias = "auto-threshold", default_value = "95", help = "Required similarity for reporting duplicate images. Used in similarity mode. 0-100, 100 indicating exact match")]
+    pub similarity_score: u32,
+
+    #[clap(long, env = "DEDUP_MAX_DISTANCE", conflicts_with = "similarity-score", help = "Largest number of bits the hashes of similar images may differ in, out of 144, instead of --similarity-score. Used in similarity mode")]
+    pub max_distance: Option<u32>,
+
+    #[clap(long, env = "DEDUP_CLIP_MODEL", value_name = "FILE", conflicts_with = "max-distance", help = "Compare images by the embeddings of this CLIP image encoder in ONNX format instead of by their hashes, which also catches cropped, filtered and edited versions of a photo. --similarity-score is then the cosine similarity of the embeddings, in percent. Needs a build with the `clip` feature. Used in similarity mode")]
+    pub clip_model: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_REVIEW_THRESHOLD", help = "Also list groups of images at least this similar, but below --similarity-score, apart from the rest to be reviewed by hand. Used in similarity mode")]
+    pub review_threshold: Option<u32>,
+
+    #[clap(long, env = "DEDUP_REPORT_NEAR_MISSES", value_name = "N", help = "List the N closest pairs of images that weren't similar enough, with their scores, to help pick --similarity-score. Used in similarity mode")]
+    pub report_near_misses: Option<usize>,
+
+    #[clap(long, env = "DEDUP_MIN_PIXELS", help = "Leave out images with fewer pixels than this, like icons and thumbnails. Used in similarity mode")]
+    pub min_pixels: Option<u64>,
+
+    #[clap(long, env = "DEDUP_MIN_DIMENSIONS", value_parser = parse_dimensions, value_name = "WIDTHxHEIGHT", help = "Leave out images narrower or shorter than this, e.g. `64x64`. Used in similarity mode")]
+    pub min_dimensions: Option<(u32, u32)>,
+
+    #[clap(long, env = "DEDUP_FIND_THUMBNAILS", help = "Whether to list images that look the same as one at least twice as large, like thumbnails, and delete only those with --delete. Used in similarity mode")]
+    pub find_thumbnails: bool,
+
+    #[clap(long, env = "DEDUP_RESULTS", help = "Write the similarity groups to this JSON file, each with an ID derived from its members that stays the same across runs. Used in similarity mode")]
+    pub results: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_EXPORT_GRAPH", value_name = "FILE", value_parser = parse_graph_path, help = "Write every compared image and every similar pair, weighted by similarity, to this Graphviz `.dot` or `.graphml` file, for clustering or drawing in tools like Gephi. Used in similarity mode")]
+    pub export_graph: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_IGNORE_GROUPS", help = "File of group IDs from --results to leave out of the report, one per line, for groups reviewed and found not to be duplicates. The ID of a two-file group only keeps that pair apart. Used in similarity mode")]
+    pub ignore_groups: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_CLASS", value_enum, help = "Only compare images of this class; `screenshots`, which are PNGs at a standard screen resolution without EXIF data, or `photos` for every other image. Used in similarity mode")]
+    pub class: Option<ImageClass>,
+
+    #[clap(long, env = "DEDUP_IMAGE_CACHE", help = "Keep the hashes of decoded images in this file, so later runs only decode new or changed images. Used in similarity mode")]
+    pub image_cache: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_IGNORE_BURSTS", help = "Whether to leave out groups of photo bursts, shot within a couple seconds of each other or numbered in sequence, instead of marking them as bursts. Used in similarity mode")]
+    pub ignore_bursts: bool,
+
+    #[clap(long, env = "DEDUP_ANNOTATE_SUBJECTS", help = "Whether to note for each group if its images have the same number of subjects in the same places, going by cheap heuristics rather than face detection, to tell re-exports of one photo from shots of the same scene. Used in similarity mode")]
+    pub annotate_subjects: bool,
+
+    #[clap(long, env = "DEDUP_NO_VIDEO_FRAMES", help = "Whether to skip videos and animations the image decoder can't read, rather than comparing a frame taken out of them with ffmpeg, if it's installed. Used in similarity mode")]
+    pub no_video_frames: bool,
+
+    #[clap(long, env = "DEDUP_IGNORE_TAGS", help = "Whether to hash only the audio stream of mp3, mp2, aac and flac files, ignoring tags and embedded art. Used in hash mode")]
+    pub ignore_tags: bool,
+
+    #[clap(long, env = "DEDUP_NORMALIZE_PDFS", help = "Whether to ignore document IDs, timestamps and producer metadata when hashing pdf files. Used in hash mode")]
+    pub normalize_pdfs: bool,
+
+    #[clap(long, env = "DEDUP_NORMALIZE_OFFICE", help = "Whether to hash the uncompressed contents of office documents (docx, xlsx, pptx, odt...) instead of their raw bytes. Used in hash mode")]
+    pub normalize_office: bool,
+
+    #[clap(long, env = "DEDUP_NORMALIZE_IMAGES", help = "Whether to hash the decoded pixels of images, upright according to their EXIF orientation, instead of their raw bytes. Used in hash mode")]
+    pub normalize_images: bool,
+
+    #[clap(long, env = "DEDUP_TEXT_NORMALIZE", value_enum, value_delimiter = ',', help = "Comma-separated differences to ignore when hashing text files, like code and documents, so copies synced across OSes match; `eol` for CRLF against LF line endings, `trailing-ws` for whitespace at the end of lines and blank lines at the end, `bom` for a UTF-8 byte order mark. They are read twice, once to bucket them by size. Used in hash mode")]
+    pub text_normalize: Vec<TextNormalization>,
+
+    #[clap(long, env = "DEDUP_DECOMPRESS_BEFORE_HASH", help = "Whether to hash the decompressed contents of gz, xz and zst files, so they match uncompressed copies. They are decompressed twice, once to bucket them by size. Used in hash mode")]
+    pub decompress_before_hash: bool,
+
+    #[clap(long, env = "DEDUP_KEEP_COMPRESSED", help = "Whether to keep a compressed copy over uncompressed ones, when --decompress-before-hash groups them together. --keep-dir-order still takes priority")]
+    pub keep_compressed: bool,
+
+    #[clap(long, env = "DEDUP_ALTERNATE_STREAMS", help = "Whether to hash NTFS alternate data streams along with file contents, so files only match when their streams do too. Windows only. Used in hash mode")]
+    pub alternate_streams: bool,
+
+    #[clap(long, env = "DEDUP_FORENSIC", help = "Whether to byte-compare files with the same hash, reporting the details of any hash collision instead of treating them as duplicates. Used in hash mode")]
+    pub forensic: bool,
+
+    #[clap(long, env = "DEDUP_COLLAPSE_GENERATED", help = "Whether to list groups whose files are all in generated folders like `build`, `dist`, `target` or `node_modules` as one line per set of folders at the end, instead of one by one, so they don't bury the rest. They're still acted on. Used in hash mode")]
+    pub collapse_generated: bool,
+
+    #[clap(long, env = "DEDUP_SHOW_DIFF", help = "Whether to print a short diff of text files that have the same size as another but different contents, to confirm they really differ. Used in hash mode")]
+    pub show_diff: bool,
+
+    #[clap(long, env = "DEDUP_VERBOSE", help = "Whether to explain why each duplicate was picked over the kept file, e.g. `modified 42 days before the kept file`. Plans always include it. Used in hash mode")]
+    pub verbose: bool,
+
+    #[clap(long, env = "DEDUP_DETERMINISTIC", help = "Whether to list folders, files and groups in the same order on every run over an unchanged folder, leaving timings out of the summary, so the reports of two runs can be diffed. Used in hash mode")]
+    pub deterministic: bool,
+
+    #[clap(long, env = "DEDUP_CASE_COLLISIONS", help = "Whether to report files in the same folder whose names only differ in case")]
+    pub case_collisions: bool
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[clap(about = "Estimates the duplicated space by hashing a random sample of same-size groups")]
+    Estimate {
+        #[clap(long, env = "DEDUP_SAMPLES", default_value = "1000", help = "How many same-size groups to hash")]
+        samples: usize,
+
+        #[clap(long, env = "DEDUP_SEED", help = "Seed for picking the sample, to reproduce a previous estimate")]
+        seed: Option<u64>
+    },
+
+    #[clap(about = "Re-checks the files of a plan written with --write-plan, then deletes, renames or links the duplicates that didn't change since")]
+    VerifyPlan {
+        #[clap(help = "The plan file to verify")]
+        plan: PathBuf
+    },
+
+    #[clap(about = "Re-hashes the files of a checksum manifest, like one written with --export-checksums, reporting the ones modified or missing since and the files in --path, or else the manifest's folder, that it doesn't list")]
+    Verify {
+        #[clap(long, env = "DEDUP_MANIFEST", help = "The `sha256sum`, `md5sum`, `sha512sum` or BSD tagged manifest to check against")]
+        manifest: PathBuf
+    },
+
+    #[clap(about = "Lists the files stored on more than one of the volumes recorded in a session with --session, without any of them mounted")]
+    Report {
+        #[clap(long, env = "DEDUP_SESSION", help = "The session file to report on")]
+        session: PathBuf
+    },
+
+    #[clap(about = "Tries out what the requested actions rely on in --path, like hard links, reflinks and write access, and reports what to do about anything missing")]
+    Doctor,
+
+    #[clap(about = "Lists the images in --path that look like the given one, most similar first, as in similarity mode, or the exact copies of a file")]
+    Query {
+        #[clap(long, env = "DEDUP_FILE", required_unless_present = "hash", conflicts_with = "hash", help = "The image to look for")]
+        file: Option<PathBuf>,
+
+        #[clap(long, env = "DEDUP_HASH", value_name = "FILE_OR_DIGEST", help = "Look for exact copies of this file, or files with this hex digest, instead")]
+        hash: Option<String>
+    },
+
+    #[clap(about = "Reports files in --path identical to files installed by dpkg or rpm, going by their file lists, which --delete removes as the system keeps its own copy")]
+    Packages,
+
+    #[clap(about = "Measures hashing throughput with every hash algorithm and read buffer size, on files sampled from --path or else a synthetic file")]
+    Bench {
+        #[clap(long, env = "DEDUP_SIZE", value_parser = parse_size, default_value = "256M", help = "How many bytes to hash with each combination")]
+        size: u64,
+
+        #[clap(long, env = "DEDUP_BUFFERS", value_delimiter = ',', value_parser = parse_size, default_value = "4K,64K,1M,8M", help = "Comma-separated read buffer sizes to try")]
+        buffers: Vec<u64>
+    },
+
+    #[clap(about = "Reports files stored more than once across the layers of OCI or Docker images, given as image folders or `docker save` tarballs")]
+    Layers {
+        #[clap(required = true, help = "The image folders or tarballs to scan")]
+        images: Vec<PathBuf>
+    },
+
+    #[clap(about = "Serves scan, report and act requests as line-delimited JSON-RPC over stdin/stdout")]
+    Serve(ServeOptions),
+
+    #[clap(about = "Runs selection rules and actions against folders made for them under the temporary folder, checking what's left against what's expected")]
+    SelfTest
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ServeOptions {
+    #[clap(long, env = "DEDUP_SOCKET", help = "Listen on this unix socket instead of stdin/stdout")]
+    pub socket: Option<PathBuf>,
+
+    #[clap(long, env = "DEDUP_THUMBNAIL_SIZE", default_value = "128", help = "Size of the thumbnails sent along similarity groups, in pixels")]
+    pub thumbnail_size: u32,
+
+    #[cfg(feature = "http")]
+    #[clap(long, env = "DEDUP_LISTEN", conflicts_with = "socket", help = "Serve an HTTP API on this address instead, e.g. `127.0.0.1:8080`, or on 127.0.0.1 given only a port. Requests need the token printed at startup")]
+    pub listen: Option<String>
+}
+
+// Parses a byte count with an optional binary unit suffix: k, m, g, t (case-insensitive, `b` optional)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches('b');
+    let (number, shift) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 10),
+        Some('m') => (&digits[..digits.len() - 1], 20),
+        Some('g') => (&digits[..digits.len() - 1], 30),
+        Some('t') => (&digits[..digits.len() - 1], 40),
+        _ => (digits, 0)
+    };
+
+    number.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| format!("`{value}` is not a valid size"))
+}
+
+// Takes an --export-graph path, whose extension picks the format it's written in
+fn parse_graph_path(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+
+    match Format::of(&path) {
+        Some(_) => Ok(path),
+        None => Err(format!("`{value}` should end in .dot or .graphml"))
+    }
+}
+
+// Parses a number of seconds, minutes, hours, days or weeks with its unit suffix, like `30d` (case-insensitive)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let (number, unit) = match lower.chars().last() {
+        Some('s') => (&lower[..lower.len() - 1], 1),
+        Some('m') => (&lower[..lower.len() - 1], 60),
+        Some('h') => (&lower[..lower.len() - 1], 60 * 60),
+        Some('d') => (&lower[..lower.len() - 1], 24 * 60 * 60),
+        Some('w') => (&lower[..lower.len() - 1], 7 * 24 * 60 * 60),
+        _ => return Err(format!("`{value}` needs a unit, like `30d`; s, m, h, d and w are understood"))
+    };
+
+    number.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("`{value}` is not a valid duration"))
+}
+
+// Parses a width and height separated by an `x`, like `640x480`
+fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    value.to_ascii_lowercase()
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .ok_or_else(|| format!("`{value}` is not a valid size, expected WIDTHxHEIGHT"))
 }
 
 #[derive(ValueEnum, Debug, Clone)]
@@ -53,14 +456,104 @@ pub enum FileOrdering {
     Name
 }
 
+// Like `FileOrdering`, but groups can also be sorted by the space they take up
+#[derive(ValueEnum, Debug, Clone)]
+pub enum OutputOrdering {
+    Modified,
+    Created,
+    Name,
+    Size
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum SummaryFormat {
+    Text,
+    Json
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum HashAlgo {
+    Sha256,
+    Sha512
+}
+
+impl HashAlgo {
+    pub fn algorithm(self) -> &'static Algorithm {
+        match self {
+            Self::Sha256 => &SHA256,
+            Self::Sha512 => &SHA512
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512"
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ChecksumFormat {
+    Sha256sum,
+    Md5sum,
+    Bsd
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextNormalization {
+    Eol,
+    TrailingWs,
+    Bom
+}
+
+impl TextNormalization {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Eol => "eol",
+            Self::TrailingWs => "trailing-ws",
+            Self::Bom => "bom"
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum HashOrder {
+    LargestFirst,
+    SmallestFirst,
+    WalkOrder
+}
+
 #[derive(ValueEnum, Debug, Clone)]
 pub enum Keep {
     First,
     Last
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum PermissionOrder {
+    Strictest,
+    Loosest
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageClass {
+    Screenshots,
+    Photos
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Lang {
+    En,
+    Es,
+    De
+}
+
 #[derive(ValueEnum, Debug, Clone)]
 pub enum Mode {
     Hash,
-    Similarity
+    Similarity,
+    SyncConflicts,
+    BackupGenerations,
+    QuickSignature
 }
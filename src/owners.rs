@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+// The numeric owner of a file, which only unix filesystems expose
+#[cfg(unix)]
+pub fn owner(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::symlink_metadata(path).ok().map(|metadata| metadata.uid())
+}
+
+#[cfg(not(unix))]
+pub const fn owner(_path: &Path) -> Option<u32> {
+    None
+}
+
+// User names by uid, read from /etc/passwd
+// Users from other sources (LDAP and such) just show up by their uid
+pub struct Users {
+    names: HashMap<u32, String>
+}
+
+impl Users {
+    pub fn load() -> Self {
+        let names = fs::read_to_string("/etc/passwd")
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let uid = fields.nth(1)?.parse().ok()?;
+
+                Some((uid, name.to_owned()))
+            })
+            .collect();
+
+        Self { names }
+    }
+
+    pub fn name(&self, uid: u32) -> String {
+        self.names.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+    }
+}
+
+// Duplicates to delete, each along with the file that's kept in its place
+pub type Removals = Vec<(PathBuf, PathBuf)>;
+
+// Writes a `<user>.sh` script per user deleting their duplicates, handed over to them so they
+// can review and run it themselves, instead of a privileged scan deleting files on their behalf
+pub fn write_scripts(dir: &Path, users: &Users, removals: &BTreeMap<u32, Removals>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (&uid, files) in removals {
+        let name = users.name(uid);
+        let path = dir.join(format!("{name}.sh"));
+        let mut script = format!("#!/bin/sh\n# Duplicate files owned by {name}, review before running\n\n");
+
+        for (dup, source) in files {
+            let _ = writeln!(script, "# Copy of {}\nrm -- {}", quote(&paths::absolute(source)), quote(&paths::absolute(dup)));
+        }
+
+        fs::write(&path, script)?;
+        hand_over(&path, uid)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn hand_over(path: &Path, uid: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+
+    // Only root can give files away, anyone else is writing scripts for themselves
+    match std::os::unix::fs::chown(path, Some(uid), None) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result
+    }
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+const fn hand_over(_path: &Path, _uid: u32) -> io::Result<()> {
+    Ok(())
+}
+
+// Single-quotes a path for sh, where the only thing to escape is the single quote itself
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ring::digest::{SHA1_FOR_LEGACY_USE_ONLY, SHA256, SHA512};
+
+use crate::deduplicator::{self, Deduplicator};
+use crate::links;
+use crate::md5;
+use crate::options::Options;
+use crate::paths;
+
+const DPKG_INFO: &str = "/var/lib/dpkg/info";
+
+// The checksums package managers keep, per https://github.com/rpm-software-management/rpm/blob/master/include/rpm/rpmpgp.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Checksum {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512
+}
+
+impl Checksum {
+    fn of_rpm(algo: &str) -> Option<Self> {
+        match algo {
+            // Packages from before the algorithm was recorded all used MD5
+            "1" | "(none)" => Some(Self::Md5),
+            "2" => Some(Self::Sha1),
+            "8" => Some(Self::Sha256),
+            "10" => Some(Self::Sha512),
+            _ => None
+        }
+    }
+
+    fn hash(self, path: &Path) -> io::Result<String> {
+        let reader = io::BufReader::new(fs::File::open(path)?);
+
+        let digest = match self {
+            Self::Md5 => md5::digest(reader)?.to_vec(),
+            Self::Sha1 => deduplicator::reader_digest(&SHA1_FOR_LEGACY_USE_ONLY, reader)?.as_ref().to_vec(),
+            Self::Sha256 => deduplicator::reader_digest(&SHA256, reader)?.as_ref().to_vec(),
+            Self::Sha512 => deduplicator::reader_digest(&SHA512, reader)?.as_ref().to_vec()
+        };
+
+        Ok(deduplicator::hex(&digest))
+    }
+}
+
+// A file installed by a package, with the checksum of its contents as shipped
+struct Packaged {
+    path: PathBuf,
+    package: String,
+    checksum: Checksum,
+    digest: String
+}
+
+// Reports the files under the root that are byte-identical to files installed by dpkg or rpm, as the checksums
+// in their file lists say they were shipped, deleting them with --delete since the system keeps its own copy
+pub fn run(options: Options) {
+    let mut packaged: HashMap<u64, Vec<Packaged>> = HashMap::new();
+
+    for (manager, read) in [("dpkg", read_dpkg as fn(&mut HashMap<u64, Vec<Packaged>>) -> io::Result<usize>), ("rpm", read_rpm)] {
+        match read(&mut packaged) {
+            Ok(0) => {},
+            Ok(count) => println!("Read {count} packaged files from {manager}"),
+            Err(err) => {
+                eprintln!("Failure while reading the {manager} file lists");
+                eprintln!("{err:?}");
+                eprintln!();
+            }
+        }
+    }
+
+    if packaged.is_empty() {
+        println!("Found no dpkg or rpm file lists to compare against");
+        return;
+    }
+
+    let delete = options.delete;
+    let deduplicator = Deduplicator::new(options);
+    let mut matches = Vec::new();
+    let mut bytes = 0;
+
+    for entry in deduplicator.list_entries().filter(|entry| entry.file_type().is_file()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let Some(candidates) = packaged.get(&metadata.len()) else {
+            continue;
+        };
+
+        let path = paths::absolute(entry.path());
+        let mut digests: HashMap<Checksum, Option<String>> = HashMap::new();
+
+        deduplicator.remember(entry.path());
+
+        // Packaged files themselves turn up when scanning system folders, under other paths too
+        // where /lib links to /usr/lib
+        let found = candidates.iter()
+            .filter(|candidate| candidate.path != path && !links::same_file(&candidate.path, entry.path()))
+            .find(|candidate| {
+                digests.entry(candidate.checksum)
+                    .or_insert_with(|| candidate.checksum.hash(entry.path()).ok())
+                    .as_ref()
+                    .is_some_and(|digest| *digest == candidate.digest)
+            });
+
+        if let Some(found) = found {
+            bytes += metadata.len();
+            matches.push((entry, found));
+        }
+    }
+
+    println!();
+    println!("{} files are identical to packaged files, taking up {}:", matches.len(), Deduplicator::format_size(bytes, 2));
+
+    for (entry, found) in &matches {
+        println!("{} is {} from {}", paths::display(entry.path()), found.path.display(), found.package);
+    }
+
+    if delete {
+        for (n, (entry, found)) in matches.iter().enumerate() {
+            deduplicator.delete_unchanged(n + 1, &found.path, &[entry.path()]);
+        }
+    }
+}
+
+// Every package has an `<name>[:<arch>].md5sums` file with lines of `<md5>  <path without the leading slash>`
+// which carry no sizes, so the installed files are looked at for those
+fn read_dpkg(packaged: &mut HashMap<u64, Vec<Packaged>>) -> io::Result<usize> {
+    let entries = match fs::read_dir(DPKG_INFO) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err)
+    };
+
+    let mut count = 0;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.extension().is_none_or(|ext| ext != "md5sums") {
+            continue;
+        }
+
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let package = name.split(':').next().unwrap_or_default().to_string();
+
+        for line in fs::read_to_string(&path)?.lines() {
+            let Some((digest, file)) = line.split_once("  ") else {
+                continue;
+            };
+            let file = Path::new("/").join(file);
+            let Ok(metadata) = fs::metadata(&file) else {
+                continue;
+            };
+
+            packaged.entry(metadata.len()).or_default().push(Packaged {
+                path: file,
+                package: package.clone(),
+                checksum: Checksum::Md5,
+                digest: digest.to_ascii_lowercase()
+            });
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+// One line per file of every installed package, `<algorithm> <size> <digest> <package> <path>`
+// Folders and symlinks have no digest, and are left out
+fn read_rpm(packaged: &mut HashMap<u64, Vec<Packaged>>) -> io::Result<usize> {
+    let output = match Command::new("rpm").args(["-qa", "--qf", "[%{FILEDIGESTALGO} %{FILESIZES} %{FILEDIGESTS} %{NAME} %{FILENAMES}\\n]"]).output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err)
+    };
+
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+
+    let mut count = 0;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.splitn(5, ' ');
+        let (Some(algo), Some(size), Some(digest), Some(package), Some(file)) = (fields.next(), fields.next(), fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let (Some(checksum), Ok(size)) = (Checksum::of_rpm(algo), size.parse()) else {
+            continue;
+        };
+
+        if digest.is_empty() {
+            continue;
+        }
+
+        packaged.entry(size).or_default().push(Packaged {
+            path: PathBuf::from(file),
+            package: package.to_string(),
+            checksum,
+            digest: digest.to_ascii_lowercase()
+        });
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use unicode_normalization::UnicodeNormalization;
+
+// macOS stores names decomposed (NFD) while most everything else composes them (NFC),
+// so the "same" name synced between the two can be made up of different code points
+pub fn normalize(name: &OsStr) -> String {
+    name.to_string_lossy().nfc().collect()
+}
+
+pub fn display(path: &Path) -> String {
+    normalize(path.as_os_str())
+}
+
+// Path relative to the scanned root, for output
+pub fn relative_display(root: &Path, path: &Path) -> String {
+    display(path.strip_prefix(root).unwrap_or(path))
+}
+
+// Resolves relative paths against the working directory, for files meant to be used from anywhere
+pub fn absolute(path: &Path) -> PathBuf {
+    env::current_dir().map_or_else(|_| path.to_owned(), |cwd| cwd.join(path))
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+// Keys whose values change every time a PDF is re-saved or re-downloaded,
+// without affecting the content of the document
+const VOLATILE_KEYS: [&[u8]; 6] = [
+    b"/ID",
+    b"/CreationDate",
+    b"/ModDate",
+    b"/Producer",
+    b"/Creator",
+    b"/Metadata"
+];
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+// Strips document IDs, timestamps, producer info, XMP packets and cross-reference tables,
+// the latter of which hold byte offsets that shift whenever any of the former change length
+// This is not a full PDF parser, and re-encoded content streams will still hash differently
+pub fn normalize(data: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        if let Some(end) = skip_volatile(data, i) {
+            i = end;
+            continue;
+        }
+
+        normalized.push(data[i]);
+        i += 1;
+    }
+
+    normalized
+}
+
+fn skip_volatile(data: &[u8], i: usize) -> Option<usize> {
+    let rest = &data[i..];
+
+    match data[i] {
+        b'/' => {
+            let key = VOLATILE_KEYS.iter().find(|key| {
+                rest.starts_with(key) && rest.get(key.len()).is_none_or(|&byte| is_delimiter(byte))
+            })?;
+
+            Some(value_end(data, i + key.len()))
+        },
+        b'<' if rest.starts_with(b"<?xpacket begin") => {
+            let end = find(data, i, b"<?xpacket end")?;
+
+            Some(find(data, end, b"?>").map_or(data.len(), |close| close + 2))
+        },
+        b'x' if rest.starts_with(b"xref") && i > 0 && data[i - 1].is_ascii_whitespace() => {
+            let end = find(data, i, b"trailer")?;
+
+            // Only a table of offsets and generations ending in a trailer goes, anything else is hashed as it is
+            data[i + b"xref".len()..end].iter()
+                .all(|&byte| byte.is_ascii_digit() || byte.is_ascii_whitespace() || matches!(byte, b'n' | b'f'))
+                .then_some(end)
+        },
+        b's' if rest.starts_with(b"startxref") => {
+            let mut end = i + b"startxref".len();
+
+            while end < data.len() && (data[end].is_ascii_whitespace() || data[end].is_ascii_digit()) {
+                end += 1;
+            }
+
+            Some(end)
+        },
+        _ => None
+    }
+}
+
+// Finds the end of the PDF object starting at (or after whitespace following) `start`
+fn value_end(data: &[u8], start: usize) -> usize {
+    let mut i = start;
+
+    while i < data.len() && data[i].is_ascii_whitespace() {
+        i += 1;
+    }
+
+    if i >= data.len() {
+        return i;
+    }
+
+    match data[i] {
+        b'(' => literal_string_end(data, i),
+        b'<' if data.get(i + 1) != Some(&b'<') => find(data, i, b">").map_or(data.len(), |end| end + 1),
+        b'[' => {
+            let mut depth = 0;
+
+            while i < data.len() {
+                match data[i] {
+                    b'[' => depth += 1,
+                    b']' => {
+                        depth -= 1;
+
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    },
+                    b'(' => {
+                        i = literal_string_end(data, i);
+                        continue;
+                    },
+                    _ => {}
+                }
+
+                i += 1;
+            }
+
+            i
+        },
+        _ => {
+            // Indirect references (`12 0 R`) and plain tokens
+            let mut end = i + 1;
+
+            while end < data.len() && !is_delimiter(data[end]) {
+                end += 1;
+            }
+
+            end
+        }
+    }
+}
+
+fn literal_string_end(data: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+
+    while i < data.len() {
+        match data[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return i + 1;
+                }
+            },
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    data.len()
+}
+
+const fn is_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace() || matches!(byte, b'/' | b'[' | b']' | b'(' | b')' | b'<' | b'>')
+}
+
+fn find(data: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    data[from..].windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| from + pos)
+}
@@ -0,0 +1,260 @@
+use std::convert::TryFrom;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::paths;
+use crate::deduplicator::{hex, Deduplicator};
+use crate::options::{HashAlgo, Mode, Options, TextNormalization};
+
+const VERSION: u64 = 1;
+
+// Each duplicate carries the reason it goes instead of the kept file, given in the same order
+pub fn group_record(group: usize, digest: &[u8], source: &Path, duplicates: &[&Path], reasons: &[String]) -> Value {
+    let duplicates: Vec<_> = duplicates.iter()
+        .zip(reasons)
+        .map(|(dup, reason)| {
+            let mut record = file_record(dup);
+            record["reason"] = json!(reason);
+            record
+        })
+        .collect();
+
+    json!({
+        "group": group,
+        "digest": hex(digest),
+        "source": file_record(source),
+        "duplicates": duplicates
+    })
+}
+
+// Paths are stored absolute, so the plan can be verified from any working directory
+fn file_record(path: &Path) -> Value {
+    let absolute = paths::absolute(path);
+    let metadata = fs::metadata(path).ok();
+
+    json!({
+        "path": absolute.to_string_lossy(),
+        "size": metadata.as_ref().map(Metadata::len),
+        "modified": metadata.as_ref().and_then(modified_ns)
+    })
+}
+
+fn modified_ns(metadata: &Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+pub fn write(path: &Path, options: &Options, groups: &[Value]) -> io::Result<()> {
+    // Stored canonical, so verifying it from another folder still tells if it was written for `/` or the home folder
+    let root = options.path.canonicalize().unwrap_or_else(|_| paths::absolute(&options.path));
+
+    let plan = json!({
+        "version": VERSION,
+        "root": root.to_string_lossy(),
+        "options": {
+            "ignore_tags": options.ignore_tags,
+            "normalize_pdfs": options.normalize_pdfs,
+            "normalize_office": options.normalize_office,
+            "normalize_images": options.normalize_images,
+            "alternate_streams": options.alternate_streams,
+            "decompress_before_hash": options.decompress_before_hash,
+            "text_normalize": options.text_normalize.iter().map(|normalization| normalization.name()).collect::<Vec<_>>(),
+            "hash_algo": options.hash_algo.name(),
+            "quick_signature": matches!(options.mode, Mode::QuickSignature)
+        },
+        "groups": groups
+    });
+
+    fs::write(path, serde_json::to_string_pretty(&plan)?)
+}
+
+// Re-checks every file of a plan against its recorded size, modification time and digest,
+// then acts on the unchanged duplicates of every group whose kept file is unchanged too
+pub fn verify(mut options: Options, path: &Path) {
+    let plan = match read(path) {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("Failure while reading the plan: {}", path.to_string_lossy());
+            eprintln!("{err}");
+            return;
+        }
+    };
+
+    if plan["version"].as_u64() != Some(VERSION) {
+        eprintln!("Unsupported plan version in {}", path.to_string_lossy());
+        return;
+    }
+
+    // Files have to be hashed the same way they were when the plan was written
+    let digest_options = &plan["options"];
+    hash_like(&mut options, digest_options);
+
+    // Plans of probable duplicates are checked by their signatures, then hashed in full before acting on them
+    let quick = digest_options["quick_signature"].as_bool().unwrap_or(false);
+    let full = quick.then(|| Deduplicator::new(Options { mode: Mode::Hash, ..options.clone() }));
+
+    if quick {
+        options.mode = Mode::QuickSignature;
+    }
+
+    let link = options.hardlink || options.reflink;
+    let delete = options.delete;
+    let pattern = options.rename_dupes.clone();
+    let deduplicator = Deduplicator::new(options);
+
+    let groups = plan["groups"].as_array().map_or(&[][..], Vec::as_slice);
+    let mut verified_count = 0;
+    let mut stale_count = 0;
+    let mut skipped_groups = 0;
+    let mut differing_count = 0;
+
+    for (n, group) in groups.iter().enumerate() {
+        let expected = group["digest"].as_str().unwrap_or_default();
+
+        let (source, digest) = match check(&deduplicator, &group["source"], expected) {
+            Ok(checked) => checked,
+            // Plans from before groups were numbered only name them by their kept file
+            Err((source, reason)) => {
+                match group["group"].as_u64() {
+                    Some(number) => println!("Skipping group {number} of {source}, the kept file {reason}"),
+                    None => println!("Skipping the group of {source}, the kept file {reason}")
+                }
+
+                skipped_groups += 1;
+                continue;
+            }
+        };
+
+        let mut verified = Vec::new();
+
+        for dup in group["duplicates"].as_array().map_or(&[][..], Vec::as_slice) {
+            match check(&deduplicator, dup, expected) {
+                Ok((path, _)) => verified.push(path),
+                Err((path, reason)) => {
+                    println!("Skipping {path}, it {reason}");
+                    stale_count += 1;
+                }
+            }
+        }
+
+        let (digest, verified) = match full {
+            Some(ref full) => {
+                let Some((full_digest, same, differing)) = check_in_full(full, &source, verified) else {
+                    println!("Skipping the group of {}, the kept file could not be read", source.display());
+                    skipped_groups += 1;
+                    continue;
+                };
+
+                differing_count += differing;
+
+                (full_digest, same)
+            },
+            None => (digest, verified)
+        };
+
+        verified_count += verified.len();
+
+        let verified: Vec<&Path> = verified.iter()
+            .map(PathBuf::as_path)
+            .collect();
+
+        // Plans of probable duplicates are acted on by what hashed them in full, which --paranoid checks the kept file with
+        let acting = full.as_ref().unwrap_or(&deduplicator);
+        let number = group["group"].as_u64().and_then(|number| usize::try_from(number).ok()).unwrap_or(n + 1);
+
+        acting.act(number, &digest, &source, &verified);
+    }
+
+    full.as_ref().unwrap_or(&deduplicator).prune_empty_dirs();
+
+    println!();
+    println!("{verified_count} duplicates verified");
+    println!("{stale_count} duplicates changed since the plan was written");
+    println!("{skipped_groups} groups skipped as their kept file changed");
+
+    if quick {
+        println!("{differing_count} probable duplicates turned out to differ from their kept file");
+    }
+
+    if !link && !delete && pattern.is_none() {
+        println!();
+        println!("Pass --delete, --rename-dupes, --hardlink or --reflink to act on the verified duplicates");
+    }
+}
+
+// Hashes the kept file and its probable duplicates in full, returning the kept file's digest, the duplicates
+// that really are and how many weren't, or nothing if the kept file can't be read
+fn check_in_full(full: &Deduplicator, source: &Path, verified: Vec<PathBuf>) -> Option<(Vec<u8>, Vec<PathBuf>, usize)> {
+    let source_digest = full.digest(source).ok()?;
+
+    let (same, differing): (Vec<PathBuf>, Vec<PathBuf>) = verified.into_iter()
+        .partition(|path| full.digest(path).is_ok_and(|digest| digest == source_digest));
+
+    for path in &differing {
+        println!("Skipping {}, it differs from the kept file past its first bytes", path.display());
+    }
+
+    Some((source_digest, same, differing.len()))
+}
+
+// The folder a plan was written for, if it can be read
+pub fn root(path: &Path) -> Option<PathBuf> {
+    read(path).ok()?["root"].as_str().map(PathBuf::from)
+}
+
+fn read(path: &Path) -> Result<Value, String> {
+    let data = fs::read(path).map_err(|err| err.to_string())?;
+
+    serde_json::from_slice(&data).map_err(|err| err.to_string())
+}
+
+// Sets the options files are hashed with to the ones the plan was written with
+fn hash_like(options: &mut Options, digest_options: &Value) {
+    options.ignore_tags = digest_options["ignore_tags"].as_bool().unwrap_or(false);
+    options.normalize_pdfs = digest_options["normalize_pdfs"].as_bool().unwrap_or(false);
+    options.normalize_office = digest_options["normalize_office"].as_bool().unwrap_or(false);
+    options.normalize_images = digest_options["normalize_images"].as_bool().unwrap_or(false);
+    options.alternate_streams = digest_options["alternate_streams"].as_bool().unwrap_or(false);
+    options.decompress_before_hash = digest_options["decompress_before_hash"].as_bool().unwrap_or(false);
+    options.text_normalize = digest_options["text_normalize"].as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| TextNormalization::from_str(name.as_str()?, true).ok())
+        .collect();
+    // Plans from before --hash-algo existed were all hashed with SHA-256
+    options.hash_algo = digest_options["hash_algo"].as_str()
+        .and_then(|name| HashAlgo::from_str(name, true).ok())
+        .unwrap_or(HashAlgo::Sha256);
+}
+
+// Returns the file's path and digest if it still matches its record, or else its path and what changed
+fn check(deduplicator: &Deduplicator, record: &Value, expected: &str) -> Result<(PathBuf, Vec<u8>), (String, &'static str)> {
+    let Some(path) = record["path"].as_str() else {
+        return Err(("a file".to_string(), "is missing from the plan"));
+    };
+
+    let path = PathBuf::from(path);
+    let Ok(metadata) = fs::metadata(&path) else {
+        return Err((path.display().to_string(), "no longer exists"));
+    };
+
+    if record["size"].as_u64() != Some(metadata.len()) {
+        return Err((path.display().to_string(), "changed size"));
+    }
+
+    if record["modified"].as_u64().is_some_and(|modified| modified_ns(&metadata) != Some(modified)) {
+        return Err((path.display().to_string(), "was modified"));
+    }
+
+    match deduplicator.digest(&path) {
+        Ok(digest) if hex(&digest) == expected => Ok((path, digest)),
+        Ok(_) => Err((path.display().to_string(), "changed contents")),
+        Err(_) => Err((path.display().to_string(), "could not be read"))
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use serde_json::{json, Value};
+
+use crate::deduplicator::hex;
+use crate::paths;
+use crate::source::FileSource;
+
+// Asks the --select-cmd command which file of a group to keep, handing it the group as JSON on stdin:
+// `{"digest": "...", "default": "<path>", "files": [{"path": "...", "size": 123, "modified": 1700000000}]}`
+// where `default` is the file the built-in rules would keep, and reading the path to keep from its stdout
+// Returns the index of the file to keep
+pub fn select(command: &str, digest: &[u8], files: &[&impl FileSource], default: &Path) -> io::Result<usize> {
+    let records: Vec<_> = files.iter()
+        .map(|file| {
+            let info = file.info().ok();
+
+            json!({
+                "path": file.path().to_string_lossy(),
+                "size": info.map(|info| info.size),
+                "modified": info.and_then(|info| info.modified)
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|since_epoch| since_epoch.as_secs())
+            })
+        })
+        .collect();
+
+    let group = json!({
+        "digest": hex(digest),
+        "default": default.to_string_lossy(),
+        "files": records
+    });
+
+    let chosen = run(command, &group)?;
+
+    files.iter()
+        .position(|file| file.path() == chosen || paths::absolute(file.path()) == paths::absolute(&chosen))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("`{}` is not in the group", chosen.display())))
+}
+
+fn run(command: &str, group: &Value) -> io::Result<PathBuf> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Dropped right after writing, so the command sees the end of its input
+    let written = child.stdin.take()
+        .expect("stdin is piped")
+        .write_all(group.to_string().as_bytes());
+
+    // Commands are free to decide without reading the whole group
+    match written {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+        _ => {}
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!("the command exited with {}", output.status)));
+    }
+
+    let chosen = String::from_utf8_lossy(&output.stdout);
+
+    Ok(PathBuf::from(chosen.trim_end_matches(['\r', '\n'])))
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
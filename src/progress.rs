@@ -0,0 +1,183 @@
+use std::fmt::Arguments;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::options::Options;
+
+// When stderr isn't a terminal, a line is printed this often, or every so many steps, whichever comes first
+const LINE_INTERVAL: Duration = Duration::from_secs(5);
+const LINE_STEPS: usize = 10_000;
+// Records for --progress-fd and --progress-file are written this often while a phase runs, and at its end
+const RECORD_INTERVAL: Duration = Duration::from_secs(1);
+
+// Progress on stderr, rewritten in place on a terminal, and printed as plain lines every so often
+// otherwise, so logs of cron runs don't fill up with carriage returns and escape codes
+pub struct Progress {
+    tty: bool,
+    last_line: Instant,
+    last_step: usize
+}
+
+impl Progress {
+    pub fn start() -> Self {
+        let tty = io::stderr().is_terminal();
+
+        if tty {
+            eprint!("{}", ansi_escapes::CursorHide);
+        }
+
+        Self {
+            tty,
+            last_line: Instant::now(),
+            last_step: 0
+        }
+    }
+
+    pub fn update(&mut self, step: usize, message: Arguments) {
+        if self.tty {
+            eprint!("\r{message}");
+        } else if self.last_line.elapsed() >= LINE_INTERVAL || step >= self.last_step + LINE_STEPS {
+            eprintln!("{message}");
+
+            self.last_line = Instant::now();
+            self.last_step = step;
+        }
+    }
+
+    pub fn finish(self) {
+        if self.tty {
+            eprintln!("{}", ansi_escapes::CursorShow);
+        }
+    }
+}
+
+// How far along a phase is, with totals when they're known ahead of time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Step {
+    pub files_done: usize,
+    pub files_total: Option<usize>,
+    pub bytes_done: u64,
+    pub bytes_total: Option<u64>
+}
+
+impl Step {
+    // Time left going by the rate so far, in bytes when their total is known and files otherwise
+    #[allow(clippy::cast_precision_loss)]
+    fn eta(self, elapsed: Duration) -> Option<Duration> {
+        let (done, total) = match (self.bytes_total, self.files_total) {
+            (Some(total), _) if self.bytes_done > 0 => (self.bytes_done as f64, total as f64),
+            (_, Some(total)) if self.files_done > 0 => (self.files_done as f64, total as f64),
+            _ => return None
+        };
+
+        Some(elapsed.mul_f64((total - done).max(0.0) / done))
+    }
+}
+
+struct LogState {
+    out: Box<dyn Write + Send>,
+    phase: &'static str,
+    phase_start: Instant,
+    last_record: Option<Instant>
+}
+
+// Progress as JSON lines on the file descriptor or file given with --progress-fd or --progress-file, apart from
+// the output for people, so wrappers can show progress of their own. Shared with the threads doing the work
+#[derive(Clone)]
+pub struct ProgressLog(Arc<Mutex<Option<LogState>>>);
+
+impl ProgressLog {
+    pub fn open(options: &Options) -> Option<Self> {
+        let (out, name) = match (options.progress_fd, &options.progress_file) {
+            (Some(fd), _) => (from_fd(fd), format!("descriptor {fd}")),
+            (None, Some(path)) => (File::create(path), path.to_string_lossy().into_owned()),
+            (None, None) => return None
+        };
+
+        match out {
+            Ok(out) => Some(Self(Arc::new(Mutex::new(Some(LogState {
+                out: Box::new(out),
+                phase: "",
+                phase_start: Instant::now(),
+                last_record: None
+            }))))),
+            Err(err) => {
+                eprintln!("Failure while opening the progress output, leaving it out: {name}");
+                eprintln!("{err:?}");
+                eprintln!();
+
+                None
+            }
+        }
+    }
+
+    // Writes a record if none was written for a while, or this is the first of a new phase
+    pub fn update(&self, phase: &'static str, step: Step) {
+        self.record(phase, step, false);
+    }
+
+    // Writes a record no matter how recently the last one was, at the end of a phase
+    pub fn finish(&self, phase: &'static str, step: Step) {
+        self.record(phase, step, true);
+    }
+
+    fn record(&self, phase: &'static str, step: Step, force: bool) {
+        let mut guard = self.0.lock().expect("the progress log is never poisoned");
+        let Some(ref mut state) = *guard else {
+            return;
+        };
+
+        if state.phase != phase {
+            state.phase = phase;
+            state.phase_start = Instant::now();
+            state.last_record = None;
+        }
+
+        if !force && state.last_record.is_some_and(|last| last.elapsed() < RECORD_INTERVAL) {
+            return;
+        }
+
+        let elapsed = state.phase_start.elapsed();
+        let record = json!({
+            "phase": phase,
+            "files_done": step.files_done,
+            "files_total": step.files_total,
+            "bytes_done": step.bytes_done,
+            "bytes_total": step.bytes_total,
+            "elapsed_ms": elapsed.as_millis(),
+            "eta_ms": step.eta(elapsed).map(|eta| eta.as_millis())
+        });
+
+        state.last_record = Some(Instant::now());
+
+        // Wrappers that stopped reading shouldn't be written to again, but the run carries on
+        if let Err(err) = writeln!(state.out, "{record}").and_then(|()| state.out.flush()) {
+            eprintln!("Failure while writing progress, leaving it out from now on");
+            eprintln!("{err:?}");
+            eprintln!();
+
+            *guard = None;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn from_fd(fd: i32) -> io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+
+    // Borrowing a descriptor that isn't open would write wherever it's opened next
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn from_fd(_fd: i32) -> io::Result<File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--progress-fd is only supported on Unix, use --progress-file instead"))
+}
@@ -0,0 +1,54 @@
+use std::time::{Duration, SystemTime};
+
+// Which rule picked the kept file of a group, so each duplicate can be told apart from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    // A .dedup-policy.toml prefers or protects the kept file's folder
+    Policy,
+    // --keep-dir-order
+    DirOrder,
+    // --keep-permissions
+    Permissions,
+    // --keep-compressed
+    Compressed,
+    // --select-cmd
+    Command,
+    // The kept file is the original of sync conflict copies
+    Conflict,
+    // The kept file is in the oldest of the backup generations the file is unchanged in
+    Generation,
+    // Nothing else applied, so --order and --keep did
+    Order
+}
+
+// How much earlier or later a duplicate's timestamp is than the kept file's, e.g.
+// "modified 42 days before the kept file"
+pub fn time_difference(what: &str, kept: Option<SystemTime>, time: Option<SystemTime>) -> String {
+    let (Some(kept), Some(time)) = (kept, time) else {
+        return format!("{what} time unknown, listed after the kept file");
+    };
+
+    match time.duration_since(kept) {
+        Ok(after) if after.as_secs() == 0 => format!("{what} at the same time as the kept file"),
+        Ok(after) => format!("{what} {} after the kept file", span(after)),
+        Err(before) => format!("{what} {} before the kept file", span(before.duration()))
+    }
+}
+
+// Rounded down to the largest unit, as the exact difference doesn't matter
+pub fn span(duration: Duration) -> String {
+    const UNITS: [(u64, &str); 4] = [(86400, "day"), (3600, "hour"), (60, "minute"), (1, "second")];
+
+    let secs = duration.as_secs();
+    let (size, unit) = UNITS.iter()
+        .copied()
+        .find(|&(size, _)| secs >= size)
+        .unwrap_or((1, "second"));
+    let count = secs / size;
+
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
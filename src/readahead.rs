@@ -0,0 +1,61 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Opens a file to be read from start to end, telling the OS so it reads further ahead than usual
+#[cfg(windows)]
+pub fn open_sequential(path: &Path) -> io::Result<fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
+    fs::File::options()
+        .read(true)
+        .custom_flags(FILE_FLAG_SEQUENTIAL_SCAN)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+pub fn open_sequential(path: &Path) -> io::Result<fs::File> {
+    let file = fs::File::open(path)?;
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // Only a hint, reading works the same if it's ignored
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+    }
+
+    Ok(file)
+}
+
+// Starts reading a file into the page cache in the background, ahead of it being hashed,
+// so the disk or network is kept busy while the previous file is hashed
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn prefetch(path: &Path) {
+    use std::os::unix::io::AsRawFd;
+
+    if let Ok(file) = fs::File::open(path) {
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_WILLNEED) };
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn prefetch(path: &Path) {
+    use std::convert::TryFrom;
+    use std::os::unix::io::AsRawFd;
+
+    if let Ok(file) = fs::File::open(path) {
+        let len = file.metadata().map_or(0, |metadata| metadata.len());
+        let advisory = libc::radvisory {
+            ra_offset: 0,
+            ra_count: libc::c_int::try_from(len).unwrap_or(libc::c_int::MAX)
+        };
+
+        unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &advisory) };
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos")))]
+pub const fn prefetch(_path: &Path) {}
@@ -0,0 +1,44 @@
+use std::fs::Metadata;
+
+use walkdir::DirEntry;
+
+// Symlinks, and on Windows junctions too, as std reports every name-surrogate reparse point as a symlink
+// These are never followed by the walk, and their targets get scanned on their own if they're in the tree,
+// so hashing them would only report a link as a duplicate of what it points to
+pub fn is_link(entry: &DirEntry) -> bool {
+    entry.path_is_symlink()
+}
+
+// Cloud files (OneDrive, Dropbox, iCloud for Windows) whose contents aren't on disk,
+// opening them for reading makes the provider download them in full
+#[cfg(windows)]
+pub fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+    metadata.file_attributes() & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+}
+
+// FUSE-backed cloud drives report dehydrated files as taking up no blocks at their full logical size
+// Small files are let through, as some filesystems store those inline with no blocks of their own
+#[cfg(unix)]
+pub fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.is_file() && metadata.blocks() == 0 && metadata.len() > 4096
+}
+
+#[cfg(not(any(windows, unix)))]
+pub const fn is_placeholder(_metadata: &Metadata) -> bool {
+    false
+}
+
+// Evicted iCloud Drive files are replaced by a hidden `.name.icloud` stub holding only metadata
+pub fn is_icloud_stub(entry: &DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+
+    name.starts_with('.') && name.ends_with(".icloud")
+}
@@ -0,0 +1,66 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Folders holding the system or every user's files, where acting on every duplicate found is most likely a mistyped path
+#[cfg(unix)]
+const SYSTEM_FOLDERS: &[&str] = &[
+    "/bin", "/boot", "/etc", "/home", "/lib", "/lib64", "/opt", "/private", "/root", "/sbin", "/usr", "/var",
+    "/Applications", "/Library", "/System", "/Users"
+];
+
+#[cfg(windows)]
+const SYSTEM_VARS: &[&str] = &["SystemRoot", "ProgramFiles", "ProgramFiles(x86)", "ProgramData"];
+
+// What makes a folder too broad to act on every duplicate in without --force-root, if it is
+pub fn sensitive(path: &Path) -> Option<&'static str> {
+    let path = path.canonicalize().ok()?;
+
+    if path.parent().is_none() {
+        return Some(if cfg!(windows) { "the root of a drive" } else { "the root of the filesystem" });
+    }
+
+    if home().is_some_and(|home| home == path) {
+        return Some("your home folder");
+    }
+
+    if system_folders().contains(&path) {
+        return Some("a system folder");
+    }
+
+    None
+}
+
+fn home() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+
+    PathBuf::from(env::var_os(var)?).canonicalize().ok()
+}
+
+#[cfg(unix)]
+fn system_folders() -> Vec<PathBuf> {
+    SYSTEM_FOLDERS.iter()
+        .filter_map(|folder| Path::new(folder).canonicalize().ok())
+        .collect()
+}
+
+#[cfg(windows)]
+fn system_folders() -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = SYSTEM_VARS.iter()
+        .filter_map(env::var_os)
+        .map(PathBuf::from)
+        .collect();
+
+    // The folder holding every user's profile
+    if let Some(parent) = home().as_deref().and_then(Path::parent) {
+        folders.push(parent.to_owned());
+    }
+
+    folders.into_iter()
+        .filter_map(|folder| folder.canonicalize().ok())
+        .collect()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn system_folders() -> Vec<PathBuf> {
+    Vec::new()
+}
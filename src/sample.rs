@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use crate::random::Random;
+
+// Groups shown with --sample-groups, picked by a key drawn from the seed and each group's digest rather than
+// from when it's found, so the same seed picks the same groups whatever order buckets are hashed in
+pub struct Sample<T> {
+    size: usize,
+    seed: u64,
+    // Groups offered so far
+    seen: usize,
+    // The groups with the lowest keys so far, with their number to tell apart groups of the same digest
+    picked: BTreeMap<(u64, usize), T>
+}
+
+impl<T> Sample<T> {
+    pub fn new(size: usize, seed: Option<u64>) -> Self {
+        Self {
+            size,
+            seed: seed.unwrap_or_else(Random::seed_from_time),
+            seen: 0,
+            picked: BTreeMap::new()
+        }
+    }
+
+    pub fn offer(&mut self, digest: &[u8], group: usize, item: T) {
+        self.seen += 1;
+
+        let mixed = digest.iter().fold(self.seed, |mixed, &byte| mixed.rotate_left(8) ^ u64::from(byte));
+        let key = Random::new(mixed).next_u64();
+
+        self.picked.insert((key, group), item);
+
+        if self.picked.len() > self.size {
+            self.picked.pop_last();
+        }
+    }
+
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub const fn seen(&self) -> usize {
+        self.seen
+    }
+
+    // The picked groups, in the order they were found
+    pub fn picked(&self) -> Vec<&T> {
+        let mut picked: Vec<_> = self.picked.iter().collect();
+
+        picked.sort_by_key(|((_, group), _)| *group);
+
+        picked.into_iter()
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
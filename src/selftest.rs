@@ -0,0 +1,282 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+use walkdir::WalkDir;
+
+use crate::links;
+
+// A file of a case's folder, relative to it, with its contents and how many days ago it was last modified
+type Fixture = (&'static str, &'static str, u64);
+
+// A scenario run against a folder made for it, with what's expected to be left of the folder afterwards,
+// folders ending in a slash, and for cases that write a plan, the kept file and duplicates of each group in it
+struct Case {
+    name: &'static str,
+    files: &'static [Fixture],
+    args: &'static [&'static str],
+    left: &'static [&'static str],
+    plan: &'static [(&'static str, &'static [&'static str])]
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "keeps the oldest copy by default",
+        files: &[("a", "one", 10), ("b", "one", 5), ("c", "one", 1), ("u", "two", 1)],
+        args: &["--delete"],
+        left: &["a", "u"],
+        plan: &[]
+    },
+    Case {
+        name: "--keep last keeps the newest copy",
+        files: &[("a", "one", 10), ("b", "one", 5), ("c", "one", 1)],
+        args: &["--delete", "--keep", "last"],
+        left: &["c"],
+        plan: &[]
+    },
+    Case {
+        name: "--keep-dir-order keeps the copy in the preferred folder",
+        files: &[("old/x", "one", 10), ("keep/x", "one", 1)],
+        args: &["--delete", "--keep-dir-order", "keep"],
+        left: &["keep/", "keep/x", "old/"],
+        plan: &[]
+    },
+    Case {
+        name: "files of the same size with different contents are left alone",
+        files: &[("a", "aaaa", 10), ("b", "bbbb", 1)],
+        args: &["--delete"],
+        left: &["a", "b"],
+        plan: &[]
+    },
+    Case {
+        name: "a protecting .dedup-policy.toml keeps its copy",
+        files: &[("p/.dedup-policy.toml", "protect = true\n", 1), ("p/x", "one", 1), ("q/x", "one", 10)],
+        args: &["--delete"],
+        left: &["p/", "p/.dedup-policy.toml", "p/x", "q/"],
+        plan: &[]
+    },
+    Case {
+        name: "--only-older-copies leaves newer copies alone",
+        files: &[("a", "one", 10), ("b", "one", 1)],
+        args: &["--delete", "--only-older-copies"],
+        left: &["a", "b"],
+        plan: &[]
+    },
+    Case {
+        name: "--only-delete-older-than leaves recent copies alone",
+        files: &[("a", "one", 10), ("b", "one", 5), ("c", "one", 1)],
+        args: &["--delete", "--only-delete-older-than", "3d"],
+        left: &["a", "c"],
+        plan: &[]
+    },
+    Case {
+        name: "--skip-groups-larger-than leaves large groups alone",
+        files: &[("a", "one", 10), ("b", "one", 5), ("c", "one", 1)],
+        args: &["--delete", "--skip-groups-larger-than", "2"],
+        left: &["a", "b", "c"],
+        plan: &[]
+    },
+    Case {
+        name: "--rename-dupes renames the duplicates",
+        files: &[("a.txt", "one", 10), ("b.txt", "one", 1)],
+        args: &["--rename-dupes", "{name}.dup{n}{ext}"],
+        left: &["a.txt", "b.dup1.txt"],
+        plan: &[]
+    },
+    Case {
+        name: "--prune-empty-dirs removes folders emptied by deletions",
+        files: &[("a", "one", 10), ("d/e/b", "one", 1), ("f/c", "two", 1)],
+        args: &["--delete", "--prune-empty-dirs"],
+        left: &["a", "f/", "f/c"],
+        plan: &[]
+    },
+    Case {
+        name: "--write-plan lists the groups without acting on them",
+        files: &[("a", "one", 10), ("b", "one", 5), ("c", "one", 1), ("u", "two", 1)],
+        args: &["--write-plan"],
+        left: &["a", "b", "c", "u"],
+        plan: &[("a", &["b", "c"])]
+    }
+];
+
+// Folder a case runs in, made under the system's temporary folder and removed once done
+struct Scratch {
+    dir: PathBuf
+}
+
+impl Scratch {
+    fn create(index: usize, files: &[Fixture]) -> io::Result<Self> {
+        let dir = env::temp_dir().join(format!("deduplicator-self-test-{}-{index}", process::id()));
+        let scratch = Self { dir };
+
+        fs::create_dir(&scratch.dir)?;
+
+        for &(path, contents, days) in files {
+            let path = scratch.dir.join(path);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(&path, contents)?;
+            links::set_modified(&path, SystemTime::now() - Duration::from_secs(days * 86400))?;
+        }
+
+        Ok(scratch)
+    }
+
+    // Plans go next to the folder, so they're not part of what's left in it
+    fn plan(&self) -> PathBuf {
+        self.dir.with_extension("plan.json")
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn left(&self) -> Vec<String> {
+        let mut left: Vec<String> = WalkDir::new(&self.dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let relative = self.relative(entry.path());
+
+                if entry.file_type().is_dir() { format!("{relative}/") } else { relative }
+            })
+            .collect();
+
+        left.sort();
+        left
+    }
+
+    // Groups of a written plan, with their duplicates sorted, as the order they're found in isn't part of the outcome
+    fn planned(&self) -> Result<Vec<(String, Vec<String>)>, String> {
+        let data = fs::read(self.plan()).map_err(|err| format!("the plan couldn't be read ({err})"))?;
+        let plan: Value = serde_json::from_slice(&data).map_err(|err| format!("the plan couldn't be parsed ({err})"))?;
+        let path = |record: &Value| self.relative(Path::new(record["path"].as_str().unwrap_or_default()));
+
+        let mut groups: Vec<(String, Vec<String>)> = plan["groups"].as_array()
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|group| {
+                let mut duplicates: Vec<String> = group["duplicates"].as_array()
+                    .map_or(&[][..], Vec::as_slice)
+                    .iter()
+                    .map(path)
+                    .collect();
+
+                duplicates.sort();
+
+                (path(&group["source"]), duplicates)
+            })
+            .collect();
+
+        groups.sort();
+
+        Ok(groups)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+        let _ = fs::remove_file(self.plan());
+    }
+}
+
+// Runs every case with this same executable, checking what's left of its folder and what its plan holds
+// against what's expected, so selection rules and actions can be checked on any platform it's built for.
+// Exits with an error if any case fails
+pub fn run() {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!("Failure while finding the deduplicator executable");
+            eprintln!("{err:?}");
+            eprintln!();
+
+            process::exit(1);
+        }
+    };
+
+    let mut failed = 0;
+
+    for (index, case) in CASES.iter().enumerate() {
+        match run_case(&exe, index, case) {
+            Ok(()) => println!("ok      {}", case.name),
+            Err(problem) => {
+                println!("FAILED  {}", case.name);
+                println!("        {problem}");
+
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("{} of {} cases passed", CASES.len() - failed, CASES.len());
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_case(exe: &Path, index: usize, case: &Case) -> Result<(), String> {
+    let scratch = Scratch::create(index, case.files).map_err(|err| format!("the folder couldn't be made ({err})"))?;
+    let mut command = Command::new(exe);
+
+    // There's no terminal to confirm the actions at
+    command.arg("--path").arg(&scratch.dir).arg("--yes");
+
+    for arg in case.args {
+        command.arg(arg);
+
+        if *arg == "--write-plan" {
+            command.arg(scratch.plan());
+        }
+    }
+
+    // Options set for the user's own runs would change the outcome
+    for (name, _) in env::vars_os() {
+        if name.to_string_lossy().starts_with("DEDUP_") {
+            command.env_remove(name);
+        }
+    }
+
+    let output = command.output().map_err(|err| format!("the deduplicator couldn't be run ({err})"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        return Err(format!("the run failed with {}: {}", output.status, stderr.trim()));
+    }
+
+    let left = scratch.left();
+
+    if left != case.left {
+        return Err(format!("expected {:?} to be left, found {left:?}", case.left));
+    }
+
+    if !case.plan.is_empty() {
+        let planned = scratch.planned()?;
+        let expected: Vec<(String, Vec<String>)> = case.plan.iter()
+            .map(|(source, duplicates)| ((*source).to_owned(), duplicates.iter().map(|dup| (*dup).to_owned()).collect()))
+            .collect();
+
+        if planned != expected {
+            return Err(format!("expected the plan to hold {expected:?}, found {planned:?}"));
+        }
+    }
+
+    Ok(())
+}
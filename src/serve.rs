@@ -0,0 +1,455 @@
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Cursor, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use image::ImageOutputFormat;
+use itertools::Itertools;
+use serde_json::{json, Value};
+use walkdir::DirEntry;
+
+use crate::deduplicator::{self, Deduplicator};
+use crate::options::{Mode, Options, ServeOptions};
+use crate::roots;
+use crate::similarity::Similarity;
+use crate::stats::Stats;
+
+// Progress notifications are throttled, as million-file scans would otherwise flood the client
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+struct Group {
+    digest: Vec<u8>,
+    source: DirEntry,
+    duplicates: Vec<DirEntry>,
+    // Why each duplicate goes instead of the source, in the same order
+    reasons: Vec<String>
+}
+
+// Line-delimited JSON-RPC 2.0 over stdin/stdout or a unix socket, for front-ends to drive scans with
+// Methods: `scan` (optional `path`), `report`, `act` (`action`, optional `groups` and `pattern`), `shutdown`
+// While scanning, `progress` and `group` notifications are streamed to the client
+// In similarity mode, groups carry the dimensions and a base64 PNG thumbnail of each image instead,
+// so front-ends can render review screens without reading every image themselves
+pub struct Server {
+    options: Options,
+    serve: ServeOptions,
+    groups: Vec<Group>,
+    // What found the groups, which acts on them too so that they're checked like a scan run's would be,
+    // along with the folder scanned
+    deduplicator: Option<(PathBuf, Deduplicator)>,
+    similar: Vec<Value>,
+    shutdown: bool
+}
+
+impl Server {
+    pub const fn new(options: Options, serve: ServeOptions) -> Self {
+        Self {
+            options,
+            serve,
+            groups: Vec::new(),
+            deduplicator: None,
+            similar: Vec::new(),
+            shutdown: false
+        }
+    }
+
+    pub fn execute(mut self) {
+        #[cfg(feature = "http")]
+        if let Some(addr) = self.serve.listen.clone() {
+            crate::http::listen(self, &addr);
+
+            return;
+        }
+
+        let result = match self.serve.socket.clone() {
+            None => self.run(io::stdin().lock(), io::stdout().lock()),
+            Some(socket) => self.listen(&socket)
+        };
+
+        if let Err(err) = result {
+            eprintln!("Server stopped with error:");
+            eprintln!("{err:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    fn listen(&mut self, socket: &std::path::Path) -> io::Result<()> {
+        use std::os::unix::net::UnixListener;
+
+        let listener = UnixListener::bind(socket)?;
+
+        eprintln!("Listening on {}", socket.display());
+
+        // Clients are served one at a time, scan results are shared between them
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = io::BufReader::new(stream.try_clone()?);
+
+            self.run(reader, stream)?;
+
+            if self.shutdown {
+                break;
+            }
+        }
+
+        fs::remove_file(socket)
+    }
+
+    #[cfg(not(unix))]
+    fn listen(&mut self, _socket: &std::path::Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not supported on this platform"))
+    }
+
+    fn run(&mut self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Value>(&line) {
+                Err(err) => Some(error_response(&Value::Null, PARSE_ERROR, &err.to_string())),
+                Ok(request) => self.handle(&request, &mut writer)
+            };
+
+            if let Some(response) = response {
+                write_message(&mut writer, &response)?;
+            }
+
+            if self.shutdown {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the response, or nothing for notifications (requests without an id)
+    fn handle(&mut self, request: &Value, writer: &mut impl Write) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match request.get("method").and_then(Value::as_str) {
+            Some("scan") => self.scan(&params, |method, params| {
+                let _ = write_message(writer, &json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": params
+                }));
+            }),
+            Some("report") => Ok(self.report()),
+            Some("act") => self.act(&params),
+            Some("shutdown") => {
+                self.shutdown = true;
+
+                Ok(Value::Null)
+            },
+            Some(method) => Err((METHOD_NOT_FOUND, format!("Unknown method `{method}`"))),
+            None => Err((METHOD_NOT_FOUND, "Missing method".to_string()))
+        };
+
+        let id = id?;
+
+        Some(match result {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            }),
+            Err((code, message)) => error_response(&id, code, &message)
+        })
+    }
+
+    // Scans the configured path, or the `path` param, sending `progress` and `group` events as it goes
+    pub fn scan(&mut self, params: &Value, mut notify: impl FnMut(&str, Value)) -> Result<Value, (i64, String)> {
+        let mut options = self.options.clone();
+
+        if let Some(path) = params.get("path").and_then(Value::as_str) {
+            options.path = PathBuf::from(path);
+        }
+
+        if !options.path.is_dir() {
+            return Err((INVALID_PARAMS, format!("{} is not a folder", options.path.display())));
+        }
+
+        if matches!(options.mode, Mode::Similarity) {
+            return Ok(self.scan_similarity(options, notify));
+        }
+
+        let root = options.path.clone();
+        let mut deduplicator = Deduplicator::new(options);
+        let entries: Vec<_> = deduplicator.list_entries().collect();
+        let count = entries.len();
+
+        let mut last_progress = None;
+        let placeholders = deduplicator.scan(entries, |done, total| {
+            progress(&mut notify, &mut last_progress, "metadata", done, total);
+        });
+
+        let buckets = deduplicator.take_buckets();
+
+        self.groups.clear();
+
+        let mut stats = Stats::default();
+
+        for (done, files) in buckets.iter().enumerate() {
+            if self.options.limit_groups.is_some_and(|limit| self.groups.len() >= limit) {
+                break;
+            }
+
+            progress(&mut notify, &mut last_progress, "hashing", done, buckets.len());
+
+            let (dupes_vec, bucket_stats) = deduplicator.get_true_dupes(files);
+
+            stats += bucket_stats;
+
+            for (digest, dupes) in dupes_vec {
+                if self.options.limit_groups.is_some_and(|limit| self.groups.len() >= limit) {
+                    break;
+                }
+
+                let cloned: Vec<_> = dupes.into_iter().cloned().collect();
+                let Some((source, mut duplicates, reason)) = deduplicator.pick(&digest, &cloned) else {
+                    continue;
+                };
+
+                stats.clones += Deduplicator::drop_clones(source, &mut duplicates);
+
+                if duplicates.is_empty() {
+                    continue;
+                }
+
+                stats.duplicate_groups += 1;
+                stats.duplicate_files += duplicates.len() + 1;
+                stats.reclaimable_bytes += duplicates.iter()
+                    .filter_map(|dup| dup.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .sum::<u64>();
+
+                let group = Group {
+                    digest,
+                    source: source.clone(),
+                    reasons: duplicates.iter()
+                        .map(|dup| deduplicator.rationale(reason, source, dup))
+                        .collect(),
+                    duplicates: duplicates.into_iter().cloned().collect()
+                };
+
+                notify("group", group_record(self.groups.len(), &group));
+
+                self.groups.push(group);
+            }
+        }
+
+        let result = json!({
+            "files": count,
+            "placeholders": placeholders,
+            "groups": self.groups.len(),
+            "stats": stats.to_json(),
+            "errors": deduplicator.failures().to_json()
+        });
+
+        self.deduplicator = Some((root, deduplicator));
+
+        Ok(result)
+    }
+
+    fn scan_similarity(&mut self, options: Options, mut notify: impl FnMut(&str, Value)) -> Value {
+        let mut similarity = Similarity::new(options);
+        let entries: Vec<_> = similarity.list_entries().collect();
+        let count = entries.len();
+
+        let mut last_progress = None;
+        let placeholders = similarity.hash_images(entries, |done, total| {
+            progress(&mut notify, &mut last_progress, "decoding", done, total);
+        });
+
+        let dimensions = similarity.dimensions();
+
+        self.similar.clear();
+
+        for group in similarity.find_groups().into_iter().take(self.options.limit_groups.unwrap_or(usize::MAX)) {
+            let members: Vec<_> = group.set.iter()
+                .sorted()
+                .map(|path| {
+                    let (width, height) = dimensions.get(path).copied().unwrap_or_default();
+
+                    json!({
+                        "path": path.to_string_lossy(),
+                        "width": width,
+                        "height": height,
+                        "thumbnail": thumbnail(path, self.serve.thumbnail_size)
+                    })
+                })
+                .collect();
+
+            let record = json!({
+                "id": self.similar.len(),
+                "score": group.similarity_score,
+                "burst": group.burst,
+                "same_subjects": group.same_subjects(),
+                "members": members
+            });
+
+            notify("group", record.clone());
+
+            self.similar.push(record);
+        }
+
+        json!({
+            "files": count,
+            "placeholders": placeholders,
+            "groups": self.similar.len()
+        })
+    }
+
+    pub fn report(&self) -> Value {
+        if matches!(self.options.mode, Mode::Similarity) {
+            return json!({ "groups": self.similar });
+        }
+
+        let groups: Vec<_> = self.groups.iter()
+            .enumerate()
+            .map(|(id, group)| group_record(id, group))
+            .collect();
+
+        json!({ "groups": groups })
+    }
+
+    // Acts on the duplicates of the given group ids, or every group if none are given, skipping the ones
+    // that changed since they were scanned and going through the trash, throttle and budgets asked for
+    pub fn act(&mut self, params: &Value) -> Result<Value, (i64, String)> {
+        let action = params.get("action").and_then(Value::as_str);
+        let pattern = params.get("pattern").and_then(Value::as_str);
+
+        match (action, pattern) {
+            (Some("delete"), _) | (Some("rename"), Some(_)) => {},
+            (Some("rename"), None) => return Err((INVALID_PARAMS, "Renaming requires a `pattern`".to_string())),
+            _ => return Err((INVALID_PARAMS, "`action` must be `delete` or `rename`".to_string()))
+        }
+
+        let ids: Vec<usize> = match params.get("groups") {
+            None | Some(Value::Null) => (0..self.groups.len()).collect(),
+            Some(Value::Array(ids)) => ids.iter()
+                .map(|id| id.as_u64().and_then(|id| usize::try_from(id).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| (INVALID_PARAMS, "`groups` must be an array of group ids".to_string()))?,
+            Some(_) => return Err((INVALID_PARAMS, "`groups` must be an array of group ids".to_string()))
+        };
+
+        if let Some(id) = ids.iter().find(|&&id| id >= self.groups.len()) {
+            return Err((INVALID_PARAMS, format!("Unknown group {id}")));
+        }
+
+        let Some((ref root, ref mut deduplicator)) = self.deduplicator else {
+            return Err((INVALID_PARAMS, "Nothing was scanned yet".to_string()));
+        };
+
+        if let (Some(reason), false) = (roots::sensitive(root), self.options.force_root) {
+            return Err((INVALID_PARAMS, format!("{} is {reason}, pass --force-root to act on duplicates in it anyway", root.display())));
+        }
+
+        let pattern = pattern.filter(|_| action == Some("rename"));
+
+        deduplicator.set_action(pattern.is_none(), pattern.map(String::from));
+
+        let mut results = Vec::new();
+
+        for id in ids {
+            let group = &self.groups[id];
+            let duplicates: Vec<&Path> = group.duplicates.iter().map(DirEntry::path).collect();
+            let acted = deduplicator.act(id, &group.digest, group.source.path(), &duplicates);
+
+            for dup in duplicates {
+                let position = acted.iter().position(|&acted| acted == dup);
+
+                results.push(match (position, deduplicator.failures().of(dup)) {
+                    (None, _) => json!({
+                        "group": id,
+                        "path": dup.to_string_lossy(),
+                        "ok": false,
+                        "error": "Left alone, it or the kept file changed since it was scanned, or a safeguard kept it"
+                    }),
+                    (Some(_), Some(failure)) => json!({
+                        "group": id,
+                        "path": dup.to_string_lossy(),
+                        "ok": false,
+                        "error": failure["message"],
+                        "failure": failure
+                    }),
+                    (Some(n), None) => json!({
+                        "group": id,
+                        "path": dup.to_string_lossy(),
+                        "target": pattern.map(|pattern| Deduplicator::rename_target(pattern, &group.digest, n, dup).to_string_lossy().into_owned()),
+                        "ok": true
+                    })
+                });
+            }
+        }
+
+        Ok(json!({ "results": results }))
+    }
+}
+
+fn group_record(id: usize, group: &Group) -> Value {
+    let duplicates: Vec<_> = group.duplicates.iter()
+        .map(|dup| dup.path().to_string_lossy())
+        .collect();
+
+    json!({
+        "id": id,
+        "digest": deduplicator::hex(&group.digest),
+        "size": group.source.metadata().map_or(0, |metadata| metadata.len()),
+        "source": group.source.path().to_string_lossy(),
+        "duplicates": duplicates,
+        "reasons": group.reasons
+    })
+}
+
+// Data URI of a PNG thumbnail fitting within size x size, if the image can be decoded
+fn thumbnail(path: &std::path::Path, size: u32) -> Option<String> {
+    let image = image::open(path).ok()?;
+    let mut png = Vec::new();
+
+    image.thumbnail(size, size)
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .ok()?;
+
+    Some(format!("data:image/png;base64,{}", base64::encode(&png)))
+}
+
+fn progress(notify: &mut impl FnMut(&str, Value), last: &mut Option<Instant>, phase: &str, done: usize, total: usize) {
+    if last.is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL) && done + 1 < total {
+        return;
+    }
+
+    *last = Some(Instant::now());
+
+    notify("progress", json!({
+        "phase": phase,
+        "done": done + 1,
+        "total": total
+    }));
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    writeln!(writer, "{message}")?;
+    writer.flush()
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use serde_json::{json, Map, Value};
+
+use crate::deduplicator::{hex, Deduplicator};
+use crate::options::Options;
+
+const VERSION: u64 = 1;
+
+// A file recorded in a session, under the volume it was scanned on
+struct Recorded<'a> {
+    volume: &'a str,
+    path: &'a str,
+    size: u64
+}
+
+// What the scanned folder is recorded under, by default its name, which removable drives are mounted under
+// on Linux and macOS, like /media/me/BACKUP1
+pub fn volume_name(options: &Options) -> String {
+    options.session_volume.clone().unwrap_or_else(|| {
+        options.path.file_name()
+            .map_or_else(|| options.path.to_string_lossy(), |name| name.to_string_lossy())
+            .into_owned()
+    })
+}
+
+fn read(path: &Path) -> io::Result<Value> {
+    let data = fs::read(path)?;
+
+    serde_json::from_slice(&data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+// Records the files of the scanned folder in the session, along with their sizes and digests, replacing whatever
+// was recorded under the same volume before. Files are relative to the scanned folder
+pub fn record(path: &Path, options: &Options, files: &[(PathBuf, u64, Vec<u8>)]) -> io::Result<()> {
+    let mut session = match read(path) {
+        Ok(session) => session,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => json!({ "version": VERSION, "volumes": {} }),
+        Err(err) => return Err(err)
+    };
+
+    let files: Vec<Value> = files.iter()
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .map(|(file, size, digest)| {
+            let relative = file.strip_prefix(&options.path).unwrap_or(file);
+
+            json!([relative.to_string_lossy(), size, hex(digest)])
+        })
+        .collect();
+
+    let Some(volumes) = session["volumes"].as_object_mut() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the session has no volumes"));
+    };
+
+    volumes.insert(volume_name(options), json!({
+        "root": options.path.to_string_lossy(),
+        "hash_algo": options.hash_algo.name(),
+        "files": files
+    }));
+
+    // Written next to it first, so a failure halfway never loses the volumes recorded before
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".tmp");
+
+    fs::write(&temp, serde_json::to_string(&session)?)?;
+    fs::rename(&temp, path)
+}
+
+// Lists the files stored on more than one of the session's volumes, none of which need to be mounted
+pub fn report(options: &Options, path: &Path) {
+    let session = match read(path) {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("Failure while reading the session: {}", path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            return;
+        }
+    };
+
+    let empty = Map::new();
+    let volumes = session["volumes"].as_object().unwrap_or(&empty);
+    let mut files: HashMap<(&str, &str), Vec<Recorded>> = HashMap::new();
+    let mut count = 0;
+
+    for (volume, recorded) in volumes {
+        let hash_algo = recorded["hash_algo"].as_str().unwrap_or_default();
+
+        for file in recorded["files"].as_array().into_iter().flatten() {
+            let (Some(path), Some(size), Some(digest)) = (file[0].as_str(), file[1].as_u64(), file[2].as_str()) else {
+                continue;
+            };
+
+            count += 1;
+            files.entry((hash_algo, digest))
+                .or_default()
+                .push(Recorded { volume, path, size });
+        }
+    }
+
+    if volumes.values().map(|recorded| recorded["hash_algo"].as_str()).unique().count() > 1 {
+        eprintln!("Volumes were scanned with different --hash-algo values, so files are only matched among volumes scanned with the same one");
+        eprintln!();
+    }
+
+    // Copies within the same volume are left to hash mode, these are the ones kept on several
+    let groups: Vec<_> = files.into_values()
+        .filter(|copies| copies.iter().map(|copy| copy.volume).unique().count() > 1)
+        .sorted_by_key(|copies| std::cmp::Reverse(wasted(copies)))
+        .take(options.limit_groups.unwrap_or(usize::MAX))
+        .collect();
+
+    let mut total_wasted = 0;
+
+    for copies in &groups {
+        total_wasted += wasted(copies);
+
+        if !options.quiet {
+            println!("Found {} copies of {} bytes:", copies.len(), copies[0].size);
+
+            for copy in copies.iter().sorted_by_key(|copy| (copy.volume, copy.path)) {
+                println!("  {} on {}", copy.path, copy.volume);
+            }
+
+            println!();
+        }
+    }
+
+    if options.no_summary {
+        return;
+    }
+
+    println!("Summary:");
+    println!("{} volumes, {count} files recorded", volumes.len());
+    println!("{} files stored on more than one volume", groups.len());
+    println!("{} stored more than once", Deduplicator::format_size(total_wasted, 2));
+}
+
+// Bytes taken by every copy but one
+fn wasted(copies: &[Recorded]) -> u64 {
+    copies[0].size * (copies.len() as u64 - 1)
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::images;
+
+const RAW_EXTENSIONS: [&str; 20] = [
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "kdc", "mrw", "nef", "nrw", "orf", "pef", "raf", "raw", "rw2", "sr2", "srf", "srw", "x3f"
+];
+const SIDECAR_EXTENSIONS: [&str; 1] = ["xmp"];
+
+// Seconds a RAW file's capture time may be off from its photo's, as both come from the same shot
+const CAPTURE_GAP: i64 = 2;
+// How much of a RAW file is read for its EXIF data, which sits near the start of every TIFF-based format
+const HEADER_BYTES: u64 = 1024 * 1024;
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+// Base name shared by the files of an exposure, like `img_0042` for IMG_0042.CR2, IMG_0042.JPG and
+// IMG_0042.xmp, or IMG_0042.JPG.xmp for sidecars named after the whole file
+fn base_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    let name = SIDECAR_EXTENSIONS.iter()
+        .find_map(|ext| name.strip_suffix(&format!(".{ext}")))
+        .unwrap_or(&name);
+
+    Some(name.split_once('.').map_or(name, |(base, _)| base).to_owned())
+}
+
+// Whether two files in the same folder are renditions of the same exposure, like a RAW file and the JPEG
+// the camera wrote along with it, which look the same without being copies of each other
+pub fn same_exposure(a: &Path, b: &Path) -> bool {
+    a.parent() == b.parent() && base_name(a).is_some_and(|base| Some(base) == base_name(b))
+}
+
+// RAW files and sidecars in a photo's folder that belong to the same exposure, going by their base name and,
+// for RAW files, when they were taken, which has to be known for both and match
+pub fn companions(path: &Path, taken: Option<i64>) -> Vec<PathBuf> {
+    same_base(path).into_iter()
+        .filter(|other| {
+            has_extension(other, &SIDECAR_EXTENSIONS)
+                || has_extension(other, &RAW_EXTENSIONS)
+                    && raw_taken(other).zip(taken).is_some_and(|(raw, photo)| (raw - photo).abs() <= CAPTURE_GAP)
+        })
+        .collect()
+}
+
+// Only the sidecars of a photo, which go wherever it goes so that none is left describing a photo that's gone
+// RAW files never do, as they're the original the photo was made from
+pub fn sidecars(path: &Path) -> Vec<PathBuf> {
+    same_base(path).into_iter()
+        .filter(|other| has_extension(other, &SIDECAR_EXTENSIONS))
+        .collect()
+}
+
+// Other files in a photo's folder with the same base name
+fn same_base(path: &Path) -> Vec<PathBuf> {
+    let (Some(folder), Some(base)) = (path.parent(), base_name(path)) else {
+        return Vec::new();
+    };
+    let folder = if folder.as_os_str().is_empty() { Path::new(".") } else { folder };
+
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+
+    entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|other| other.file_name() != path.file_name() && base_name(other).as_ref() == Some(&base))
+        .collect()
+}
+
+fn raw_taken(path: &Path) -> Option<i64> {
+    let mut header = Vec::new();
+
+    fs::File::open(path).ok()?
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)
+        .ok()?;
+
+    images::taken_at(&header)
+}
@@ -1,31 +1,106 @@
+use std::convert::TryFrom;
 use std::collections::{HashMap, HashSet};
-use std::fs::Metadata;
-use std::path::Path;
-use std::sync::mpsc;
-use std::time::Instant;
-
+use std::fs;
+use std::io::{self, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use image::ImageFormat;
 use itertools::Itertools;
+use ring::digest::{Context, SHA256};
+use serde_json::{json, Value};
 use threadpool::ThreadPool;
 use image_hasher::{ImageHash, HasherConfig, HashAlg};
 use walkdir::{DirEntry, WalkDir};
 
-use crate::options::Options;
+use crate::artifacts::Artifacts;
+use crate::clip::Clip;
+use crate::deduplicator::Deduplicator;
+use crate::graph::Graph;
+use crate::imagecache::{CachedImage, ImageCache};
+use crate::limits::ScanLimit;
+use crate::options::{ImageClass, Options};
+use crate::progress::Progress;
+use crate::source::{FileInfo, FileSource};
+use crate::storage::Storage;
+use crate::{bundles, caches, deduplicator, hidden, images, paths, reparse, sidecars, subjects, video};
+use crate::subjects::Layout;
+
+// Seconds allowed between consecutive shots of a burst
+const BURST_GAP: i64 = 2;
+
+// How many times smaller than the original a thumbnail is at least, in each dimension
+const THUMBNAIL_SCALE: u32 = 2;
+
+pub struct HashedImage<F = DirEntry> {
+    hash: ImageHash,
+    entry: F,
+    width: u32,
+    height: u32,
+    // When the photo was taken, or else last modified, in seconds since the epoch
+    taken: Option<i64>,
+    class: ImageClass,
+    // Unit length CLIP embedding with --clip-model, compared instead of the hash when both images have one
+    embedding: Option<Vec<f32>>,
+    // Where its subjects are, with --annotate-subjects
+    layout: Option<Layout>,
+    // Size and modification time when it was read, to leave it alone if it changed by the time it's deleted
+    scanned: Option<(u64, Option<SystemTime>)>
+}
 
-pub struct Similarity {
+impl<F> HashedImage<F> {
+    fn from_cache(entry: F, cached: CachedImage) -> Self {
+        Self {
+            hash: cached.hash,
+            entry,
+            width: cached.width,
+            height: cached.height,
+            taken: cached.taken,
+            class: cached.class,
+            embedding: None,
+            layout: None,
+            scanned: None
+        }
+    }
+
+    fn read_as(self, info: &FileInfo) -> Self {
+        Self {
+            scanned: Some((info.size, info.modified)),
+            ..self
+        }
+    }
+
+    fn cached(&self) -> CachedImage {
+        CachedImage {
+            hash: self.hash.clone(),
+            width: self.width,
+            height: self.height,
+            taken: self.taken,
+            class: self.class
+        }
+    }
+}
+
+pub struct Similarity<F = DirEntry> {
     start: Instant,
     options: Options,
-    pool: ThreadPool,
-    hashes: Vec<(ImageHash, Metadata, DirEntry)>
+    // Files are read on one pool and decoded on the other, so decoding can use every core without
+    // as many reads competing for the disk
+    io_pool: ThreadPool,
+    cpu_pool: ThreadPool,
+    hashes: Vec<HashedImage<F>>,
+    // Group IDs passed with --ignore-groups
+    ignored: HashSet<String>,
+    cache: Option<Arc<ImageCache>>,
+    clip: Option<Arc<Clip>>
 }
 
 impl Similarity {
     pub fn new(options: Options) -> Self {
-        Self {
-            start: Instant::now(),
-            pool: ThreadPool::new(options.threads),
-            hashes: Vec::new(),
-            options,
-        }
+        Self::with_options(options)
     }
 
     pub fn execute(mut self) {
@@ -38,16 +113,72 @@ impl Similarity {
         eprintln!("Finished! Took {}ms", self.start.elapsed().as_millis());
     }
 
-    fn list_entries(&self) -> impl Iterator<Item=DirEntry> {
+    // Ranks the images of the library by how similar they are to the given one, leaving out the ones
+    // below the threshold, without comparing the library's images with one another
+    pub fn query(mut self, file: &Path) {
+        let query = WalkDir::new(file)
+            .max_depth(0)
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+            .and_then(|entry| entry.map_err(io::Error::from))
+            .and_then(|entry| Ok((read(&entry)?, entry)));
+
+        let query = match query {
+            Ok((data, entry)) => decode(entry, &data, self.clip.as_deref(), false, !self.options.no_video_frames, true),
+            Err(err) => {
+                eprintln!("Failure while reading the query image: {}", file.to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+
+                None
+            }
+        };
+
+        let Some(query) = query else {
+            return;
+        };
+
+        self.consume();
+
+        let query_path = paths::absolute(file);
+        let matches: Vec<_> = self.candidates()
+            .filter(|hashed| paths::absolute(hashed.entry.path()) != query_path)
+            .map(|hashed| (compare(&query, hashed), hashed))
+            .filter(|&((bits, similarity_score), _)| self.is_similar(bits, similarity_score))
+            .sorted_by_key(|&((bits, _), hashed)| (bits, hashed.entry.path()))
+            .collect();
+
+        println!("{} similar images to {}:", matches.len(), paths::display(file));
+
+        for ((bits, similarity_score), hashed) in matches {
+            println!("{similarity_score} ({bits} bits apart) {}", paths::display(hashed.entry.path()));
+        }
+    }
+
+    pub fn list_entries(&self) -> impl Iterator<Item=DirEntry> {
+        let mut limit = ScanLimit::new(&self.options);
+        let scan_bundles = self.options.scan_bundles;
+        let include_caches = self.options.include_caches;
+        let exclude_caches = self.options.exclude_caches;
+        let include_hidden = self.options.include_hidden;
+        let skip_hidden = self.options.skip_hidden;
+        let artifacts = Artifacts::new(&self.options);
         let no_ignore_errors = self.options.no_ignore_errors;
 
         WalkDir::new(&self.options.path)
             .max_depth(if self.options.no_recursive {
                 1
             } else {
-                std::usize::MAX
+                usize::MAX
             })
             .into_iter()
+            .filter_entry(move |entry| {
+                (scan_bundles || !bundles::is_bundle(entry))
+                    && (include_caches || !caches::is_cache(entry, exclude_caches))
+                    && (include_hidden || !hidden::is_hidden(entry, skip_hidden))
+                    && !artifacts.contains(entry.path())
+            })
             .inspect(move |result| {
                 if let Err(err) = result {
                     if no_ignore_errors {
@@ -56,105 +187,423 @@ impl Similarity {
                 }
             })
             .filter_map(Result::ok)
+            .filter(|entry| !reparse::is_link(entry))
+            .take_while(move |entry| limit.admit(entry))
     }
 
     fn consume(&mut self) {
-        let (tx, rx) = mpsc::channel();
-        let mut iterations = 0;
-
         let entries: Vec<_> = self.list_entries().collect();
         let count = entries.len();
 
-        eprintln!("Found {} files", count);
+        eprintln!("Found {count} files");
+
+        let mut progress = Progress::start();
+
+        let placeholders = self.hash_images(entries, |idx, count| {
+            progress.update(idx, format_args!("Processed {idx} files out of {count}"));
+        });
+
+        progress.finish();
+        eprintln!();
+
+        if placeholders > 0 {
+            eprintln!("Skipped {placeholders} cloud placeholder files, pass --hydrate to download and scan them");
+            eprintln!();
+        }
+    }
+}
+
+impl<F: FileSource> Similarity<F> {
+    // For files from other sources than the walk, which are handed to `hash_images` directly
+    pub fn with_options(options: Options) -> Self {
+        Self {
+            start: Instant::now(),
+            io_pool: ThreadPool::new(options.io_threads.unwrap_or_else(|| Storage::detect(&options.path).threads())),
+            cpu_pool: ThreadPool::new(options.cpu_threads),
+            hashes: Vec::new(),
+            ignored: options.ignore_groups.as_deref().map(read_ignored).unwrap_or_default(),
+            cache: options.image_cache.as_deref().map(|path| Arc::new(ImageCache::load(path))),
+            clip: options.clip_model.as_deref().and_then(load_clip),
+            options,
+        }
+    }
+
+    // Decodes and hashes every image, reporting progress as (done, total)
+    // Returns how many cloud placeholders were skipped
+    pub fn hash_images(&mut self, entries: Vec<F>, mut progress: impl FnMut(usize, usize)) -> usize {
+        let (tx, rx) = mpsc::channel();
+        let mut iterations = 0;
+        let count = entries.len();
 
         self.hashes.reserve(count);
 
+        let placeholders = Arc::new(AtomicUsize::new(0));
+        let no_ignore_errors = self.options.no_ignore_errors;
+
+        // Bounded, so reading can't get ahead of decoding by more than a few files held in memory
+        let decoders = self.cpu_pool.max_count();
+        let (read_tx, read_rx) = mpsc::sync_channel::<(F, FileInfo, Vec<u8>)>(decoders * 2);
+        let read_rx = Arc::new(Mutex::new(read_rx));
+
+        for _ in 0..decoders {
+            let tx = tx.clone();
+            let read_rx = Arc::clone(&read_rx);
+            let cache = self.cache.clone();
+            let clip = self.clip.clone();
+            let annotate_subjects = self.options.annotate_subjects;
+            let video_frames = !self.options.no_video_frames;
+            self.cpu_pool.execute(move || {
+                loop {
+                    let received = read_rx.lock().expect("decoding queue is not poisoned").recv();
+                    let Ok((entry, info, data)) = received else {
+                        break;
+                    };
+
+                    let hashed = decode(entry, &data, clip.as_deref(), annotate_subjects, video_frames, no_ignore_errors)
+                        .map(|hashed| hashed.read_as(&info));
+
+                    if let (Some(cache), Some(hashed)) = (&cache, &hashed) {
+                        if let Some(path) = hashed.entry.local_path() {
+                            cache.insert(path, &info, hashed.cached());
+                        }
+                    }
+
+                    tx.send(hashed).expect("channel is available for sending");
+                }
+            });
+        }
+
         for entry in entries {
             iterations += 1;
 
             let tx = tx.clone();
-            let no_ignore_errors = self.options.no_ignore_errors;
-            self.pool.execute(move || {
+            let read_tx = read_tx.clone();
+            let hydrate = self.options.hydrate;
+            let placeholders = Arc::clone(&placeholders);
+            // The cache only holds hashes, so images are decoded again for their embeddings and subjects
+            let cache = self.cache.clone().filter(|_| self.clip.is_none() && !self.options.annotate_subjects);
+            self.io_pool.execute(move || {
+
+                if !entry.is_file() {
+                    tx.send(None).expect("channel is available for sending");
+                    return;
+                }
 
-                let metadata = match entry.metadata() {
-                    Err(_) => {
-                        tx.send(None).expect("channel is available for sending");
-                        return;
-                    },
-                    Ok(v) => v
+                let Ok(info) = entry.info() else {
+                    tx.send(None).expect("channel is available for sending");
+                    return;
                 };
 
-                if !metadata.is_file() {
+                if !hydrate && info.placeholder {
+                    placeholders.fetch_add(1, Ordering::Relaxed);
                     tx.send(None).expect("channel is available for sending");
                     return;
                 }
 
-                let path = entry.path();
-                let image = image::open(path);
+                let cached = cache.as_ref()
+                    .zip(entry.local_path())
+                    .and_then(|(cache, path)| cache.get(path, &info));
 
-                let image = match image {
+                if let Some(cached) = cached {
+                    tx.send(Some(HashedImage::from_cache(entry, cached).read_as(&info))).expect("channel is available for sending");
+                    return;
+                }
+
+                match read(&entry) {
+                    Ok(data) => read_tx.send((entry, info, data)).expect("decoding queue is available for sending"),
                     Err(err) => {
                         if no_ignore_errors {
-                            eprintln!("Could not read file as image in similarity mode:");
+                            eprintln!("Could not read file in similarity mode:");
                             eprintln!("{err:?}");
-                            eprintln!("{path:?}");
+                            eprintln!("{:?}", paths::display(entry.path()));
                         }
 
                         tx.send(None).expect("channel is available for sending");
-                        return;
-                    },
-                    Ok(image) => image
-                };
-
-                let hash_config = HasherConfig::new()
-                    .hash_alg(HashAlg::DoubleGradient)
-                    .hash_size(16, 16)
-                    .to_hasher();
-                let hash = hash_config.hash_image(&image);
-
-                tx.send(Some((hash, metadata, entry))).expect("channel is available for sending");
+                    }
+                }
             });
         }
 
-        eprint!("{}", ansi_escapes::CursorHide);
+        // The decoders stop once every reader is done with its copy of the queue
+        drop(read_tx);
 
-        for (idx, (hash, metadata, entry)) in rx.iter().take(iterations).filter_map(|x| x).enumerate() {
-            eprint!("\rProcessed {} files out of {}", idx, count);
+        for (idx, hashed) in rx.iter().take(iterations).flatten().enumerate() {
+            progress(idx, count);
 
-            self.hashes.push((hash, metadata, entry));
+            self.hashes.push(hashed);
         }
 
-        eprintln!("{}", ansi_escapes::CursorShow);
-        eprintln!();
+        if let Some(ref cache) = self.cache {
+            if let Err(err) = cache.save() {
+                eprintln!("Failure while writing the image cache: {}", cache.path().to_string_lossy());
+                eprintln!("{err:?}");
+                eprintln!();
+            }
+        }
+
+        placeholders.load(Ordering::Relaxed)
+    }
+
+    // Width and height of every hashed image
+    pub fn dimensions(&self) -> HashMap<&Path, (u32, u32)> {
+        self.hashes.iter()
+            .map(|hashed| (hashed.entry.path(), (hashed.width, hashed.height)))
+            .collect()
     }
 
     fn collect(&self) {
         let start_collect = Instant::now();
-        let combinations = self.hashes.iter().tuple_combinations();
-        let required_similarity = (self.options.similarity_score as f32) / 100.0;
+        let mut duplicate_groups = self.find_groups();
+        let review_groups = self.find_review_groups();
 
-        let mut duplicate_pairs = Vec::new();
+        if let Some(limit) = self.options.limit_groups {
+            duplicate_groups.truncate(limit);
+        }
+
+        eprintln!("Collection done! Took {}ms", start_collect.elapsed().as_millis());
+
+        let bursts = duplicate_groups.iter().filter(|group| group.burst).count();
+
+        if bursts > 0 {
+            eprintln!("{bursts} of those groups are photo bursts, pass --ignore-bursts to leave them out");
+        }
+
+        if let Some(ref path) = self.options.results {
+            match self.write_results(path, &duplicate_groups, &review_groups) {
+                Ok(()) => eprintln!("Wrote {} similarity groups to {}", duplicate_groups.len(), path.display()),
+                Err(err) => {
+                    eprintln!("Failure while writing the results: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+        }
 
-        for (a, b) in combinations {
+        if let Some(ref path) = self.options.export_graph {
+            let graph = self.graph();
 
-            let (hasha, _, filea) = a;
-            let (hashb, _, fileb) = b;
+            match graph.write(path) {
+                Ok(()) => eprintln!("Wrote a graph of {} images and {} similar pairs to {}", graph.nodes.len(), graph.edges.len(), path.display()),
+                Err(err) => {
+                    eprintln!("Failure while writing the graph: {}", path.to_string_lossy());
+                    eprintln!("{err:?}");
+                    eprintln!();
+                }
+            }
+        }
+
+        print_groups(&duplicate_groups);
+
+        if let Some(review_threshold) = self.options.review_threshold {
+            println!();
+            println!("{} groups to review by hand, at least {review_threshold}% similar:", review_groups.len());
+
+            print_groups(&review_groups);
+        }
+
+        if let Some(count) = self.options.report_near_misses {
+            let near_misses = self.near_misses(count);
+
+            println!();
+            println!("{} closest pairs that weren't similar enough:", near_misses.len());
+
+            for (bits, similarity_score, a, b) in near_misses {
+                println!("{similarity_score} ({bits} bits apart) {} {}", paths::display(a), paths::display(b));
+            }
+        }
+
+        if self.options.find_thumbnails {
+            self.report_thumbnails();
+        }
+    }
+
+    // Lists the thumbnails next to their originals, deleting the thumbnails with --delete
+    // Unlike duplicate groups, the smaller copy is always the one to go
+    fn report_thumbnails(&self) {
+        let thumbnails = self.find_thumbnails();
+
+        println!();
+        println!("{} thumbnails of larger images:", thumbnails.len());
+
+        for (thumbnail, original) in &thumbnails {
+            println!(
+                "{} ({}x{}) of {} ({}x{})",
+                paths::display(thumbnail.entry.path()), thumbnail.width, thumbnail.height,
+                paths::display(original.entry.path()), original.width, original.height
+            );
+        }
+
+        if self.options.delete {
+            let deduplicator = Deduplicator::new(self.options.clone());
+
+            for (n, (thumbnail, original)) in thumbnails.iter().enumerate() {
+                let (Some(path), Some(kept)) = (thumbnail.entry.local_path(), original.entry.local_path()) else {
+                    continue;
+                };
+
+                // Sidecars of a thumbnail go along with it, but never the RAW file it came from
+                let companions = sidecars::sidecars(path);
+
+                for companion in &companions {
+                    println!("Deleting {} along with its photo", paths::display(companion));
+                }
+
+                for image in [thumbnail, original] {
+                    if let (Some(path), Some((size, modified))) = (image.entry.local_path(), image.scanned) {
+                        deduplicator.remember_as(path, size, modified);
+                    }
+                }
+
+                let paths: Vec<&Path> = iter::once(path)
+                    .chain(companions.iter().map(PathBuf::as_path))
+                    .collect();
+
+                deduplicator.delete_unchanged(n + 1, kept, &paths);
+            }
+        }
+    }
 
-            let max_dist = hasha.as_bytes().len() * 8;
-            let dist = hasha.dist(hashb);
+    // Pairs each image with the largest image it looks the same as, if that one is at least
+    // THUMBNAIL_SCALE times as wide and as tall. The largest image of each set is never a thumbnail itself
+    pub fn find_thumbnails(&self) -> Vec<(&HashedImage<F>, &HashedImage<F>)> {
+        let mut originals: HashMap<&Path, (&HashedImage<F>, &HashedImage<F>)> = HashMap::new();
 
-            let dist = if dist == 0 {
-                0.0
+        for (a, b) in self.candidates().tuple_combinations() {
+            let (thumbnail, original) = if u64::from(a.width) * u64::from(a.height) < u64::from(b.width) * u64::from(b.height) {
+                (a, b)
             } else {
-                (dist as f32) / (max_dist as f32)
+                (b, a)
             };
-            let similarity_score = 1.0 - dist;
 
-            if similarity_score < required_similarity {
+            if thumbnail.width * THUMBNAIL_SCALE > original.width || thumbnail.height * THUMBNAIL_SCALE > original.height {
                 continue;
             }
 
-            duplicate_pairs.push((similarity_score, filea, fileb));
+            // A small clip showing the same scene as a photo is no thumbnail of it
+            if video::is_video(thumbnail.entry.path()) {
+                continue;
+            }
+
+            let (bits, similarity_score) = distance(&a.hash, &b.hash);
+
+            if !self.is_similar(bits, similarity_score) {
+                continue;
+            }
+
+            let larger = originals.get(thumbnail.entry.path())
+                .is_none_or(|(_, known)| u64::from(original.width) * u64::from(original.height) > u64::from(known.width) * u64::from(known.height));
+
+            if larger {
+                originals.insert(thumbnail.entry.path(), (thumbnail, original));
+            }
+        }
+
+        originals.into_values()
+            .sorted_by(|(a, _), (b, _)| a.entry.path().cmp(b.entry.path()))
+            .collect()
+    }
+
+    // Every compared image, and every pair similar enough to be grouped or, with --review-threshold, reviewed,
+    // left ungrouped so other tools can cluster them their own way
+    #[allow(clippy::cast_precision_loss)]
+    fn graph(&self) -> Graph<'_> {
+        let review = self.options.review_threshold.map(|threshold| (threshold as f32) / 100.0);
+
+        Graph {
+            root: &self.options.path,
+            nodes: self.candidates().map(|hashed| hashed.entry.path()).collect(),
+            edges: self.pairs(|bits, similarity_score| {
+                self.is_similar(bits, similarity_score) || review.is_some_and(|review| similarity_score >= review)
+            })
+        }
+    }
+
+    // Groups are sorted by ID and members by path, so the files of successive runs diff cleanly
+    fn write_results(&self, path: &Path, groups: &[SimilarityGroup<'_>], review: &[SimilarityGroup<'_>]) -> io::Result<()> {
+        let results = json!({
+            "root": paths::absolute(&self.options.path).to_string_lossy(),
+            "similarity_score": self.options.similarity_score,
+            "max_distance": self.options.max_distance,
+            "review_threshold": self.options.review_threshold,
+            "groups": self.group_records(groups),
+            "review": self.group_records(review)
+        });
+
+        fs::write(path, serde_json::to_string_pretty(&results)?)
+    }
+
+    fn group_records(&self, groups: &[SimilarityGroup<'_>]) -> Vec<Value> {
+        groups.iter()
+            .map(|group| {
+                let members: Vec<_> = group.set.iter()
+                    .map(|member| paths::relative_display(&self.options.path, member))
+                    .sorted()
+                    .collect();
+
+                let companions: serde_json::Map<_, _> = group.companions.iter()
+                    .map(|(member, companions)| (
+                        paths::relative_display(&self.options.path, member),
+                        companions.iter().map(|companion| paths::relative_display(&self.options.path, companion)).collect()
+                    ))
+                    .collect();
+
+                json!({
+                    "id": group.id(&self.options.path),
+                    "score": group.similarity_score,
+                    "burst": group.burst,
+                    "same_subjects": group.same_subjects(),
+                    "members": members,
+                    "companions": companions
+                })
+            })
+            .sorted_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()))
+            .collect()
+    }
+
+    pub fn find_groups(&self) -> Vec<SimilarityGroup<'_>> {
+        self.group_pairs(|bits, similarity_score| self.is_similar(bits, similarity_score))
+    }
+
+    // Groups of pairs that fall between --review-threshold and the usual threshold, to be looked at by hand
+    #[allow(clippy::cast_precision_loss)]
+    pub fn find_review_groups(&self) -> Vec<SimilarityGroup<'_>> {
+        let Some(review_threshold) = self.options.review_threshold else {
+            return Vec::new();
+        };
+        let required_similarity = (review_threshold as f32) / 100.0;
+
+        self.group_pairs(|bits, similarity_score| similarity_score >= required_similarity && !self.is_similar(bits, similarity_score))
+    }
+
+    // Every pair of candidates that matches, with their distance in bits and similarity score
+    fn pairs(&self, matches: impl Fn(u32, f32) -> bool) -> Vec<(&Path, &Path, u32, f32)> {
+        self.compared()
+            .filter(|&(_, _, bits, similarity_score)| matches(bits, similarity_score))
+            .collect()
+    }
+
+    // Every pair of candidates, with how far apart they are
+    fn compared(&self) -> impl Iterator<Item = (&Path, &Path, u32, f32)> {
+        self.candidates()
+            .tuple_combinations()
+            // A RAW file that could be decoded looks just like the JPEG shot along with it
+            .filter(|(a, b)| !sidecars::same_exposure(a.entry.path(), b.entry.path()))
+            .map(|(a, b)| {
+                let (bits, similarity_score) = compare(a, b);
+
+                (a.entry.path(), b.entry.path(), bits, similarity_score)
+            })
+    }
+
+    fn group_pairs(&self, matches: impl Fn(u32, f32) -> bool) -> Vec<SimilarityGroup<'_>> {
+        let mut duplicate_pairs = Vec::new();
+
+        for (path_a, path_b, _, similarity_score) in self.pairs(matches) {
+            if !self.ignored.is_empty() && self.ignored.contains(&group_id(&self.options.path, [path_a, path_b])) {
+                continue;
+            }
+
+            duplicate_pairs.push((similarity_score, path_a, path_b));
         }
 
         // Collect all duplicate pairs into *duplicate groups*
@@ -172,45 +621,361 @@ impl Similarity {
 
         for (similarity_score, filea, fileb) in duplicate_pairs {
             let mut group_index = None;
-            if group_index.is_none() && duplicate_group_indices.contains_key(filea.path()) {
-                group_index = duplicate_group_indices.get(filea.path()).map(|n| *n);
+            if group_index.is_none() && duplicate_group_indices.contains_key(filea) {
+                group_index = duplicate_group_indices.get(filea).copied();
             }
-            if group_index.is_none() && duplicate_group_indices.contains_key(fileb.path()) {
-                group_index = duplicate_group_indices.get(fileb.path()).map(|n| *n);
+            if group_index.is_none() && duplicate_group_indices.contains_key(fileb) {
+                group_index = duplicate_group_indices.get(fileb).copied();
             }
             if group_index.is_none() {
                 group_index = Some(duplicate_groups.len());
                 duplicate_groups.push(SimilarityGroup {
                     similarity_score,
-                    set: HashSet::new()
+                    set: HashSet::new(),
+                    burst: false,
+                    companions: HashMap::new(),
+                    layouts: HashMap::new()
                 });
             }
 
             let group_index = group_index.unwrap();
             let group = &mut duplicate_groups[group_index];
 
-            group.set.insert(filea.path());
-            group.set.insert(fileb.path());
+            group.set.insert(filea);
+            group.set.insert(fileb);
 
-            duplicate_group_indices.insert(filea.path(), group_index);
-            duplicate_group_indices.insert(fileb.path(), group_index);
+            duplicate_group_indices.insert(filea, group_index);
+            duplicate_group_indices.insert(fileb, group_index);
         }
 
-        eprintln!("Collection done! Took {}ms", start_collect.elapsed().as_millis());
+        if !self.ignored.is_empty() {
+            duplicate_groups.retain(|group| !self.ignored.contains(&group.id(&self.options.path)));
+        }
 
-        for group in duplicate_groups {
-            print!("{} ", group.similarity_score);
+        let taken: HashMap<&Path, Option<i64>> = self.hashes.iter()
+            .map(|hashed| (hashed.entry.path(), hashed.taken))
+            .collect();
+        let layouts: HashMap<&Path, Layout> = self.hashes.iter()
+            .filter_map(|hashed| Some((hashed.entry.path(), hashed.layout?)))
+            .collect();
+
+        for group in &mut duplicate_groups {
+            group.burst = is_burst(&group.set, &taken);
+            group.companions = group.set.iter()
+                .map(|&member| (member, sidecars::companions(member, taken.get(member).copied().flatten())))
+                .filter(|(_, companions)| !companions.is_empty())
+                .collect();
+            group.layouts = group.set.iter()
+                .filter_map(|&member| Some((member, *layouts.get(member)?)))
+                .collect();
+        }
 
-            for file_path in group.set.iter() {
-                print!("{:?} ", file_path);
+        if self.options.ignore_bursts {
+            duplicate_groups.retain(|group| !group.burst);
+        }
+
+        duplicate_groups
+    }
+
+    // The closest pairs that weren't similar enough, closest first, for --report-near-misses
+    pub fn near_misses(&self, count: usize) -> Vec<(u32, f32, &Path, &Path)> {
+        let mut closest: Vec<(u32, f32, &Path, &Path)> = Vec::new();
+
+        for (a, b, bits, similarity_score) in self.compared() {
+            if self.is_similar(bits, similarity_score) {
+                continue;
             }
 
-            println!();
+            if !self.ignored.is_empty() && self.ignored.contains(&group_id(&self.options.path, [a, b])) {
+                continue;
+            }
+
+            // Kept sorted, closest first, dropping the farthest once there are too many
+            let position = closest.partition_point(|&(closer_bits, closer_score, _, _)| self.is_closer(closer_bits, closer_score, bits, similarity_score));
+
+            if position < count {
+                closest.insert(position, (bits, similarity_score, a, b));
+                closest.truncate(count);
+            }
+        }
+
+        closest
+    }
+
+    // Whether the first pair is at least as close as the second, by whatever decides if they're similar
+    fn is_closer(&self, bits: u32, similarity_score: f32, other_bits: u32, other_score: f32) -> bool {
+        match self.options.max_distance {
+            Some(_) => bits <= other_bits,
+            None => similarity_score >= other_score
         }
     }
+
+    fn candidates(&self) -> impl Iterator<Item = &HashedImage<F>> + Clone {
+        let class = self.options.class;
+        let min_pixels = self.options.min_pixels.unwrap_or(0);
+        let (min_width, min_height) = self.options.min_dimensions.unwrap_or((0, 0));
+
+        self.hashes.iter()
+            .filter(move |hashed| class.is_none_or(|class| hashed.class == class))
+            // Tiny images have too little detail for their hashes to mean much, and match one another all the time
+            .filter(move |hashed| u64::from(hashed.width) * u64::from(hashed.height) >= min_pixels)
+            .filter(move |hashed| hashed.width >= min_width && hashed.height >= min_height)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn is_similar(&self, bits: u32, similarity_score: f32) -> bool {
+        let required_similarity = (self.options.similarity_score as f32) / 100.0;
+
+        self.options.max_distance
+            .map_or(similarity_score >= required_similarity, |max_distance| bits <= max_distance)
+    }
+}
+
+fn print_groups(groups: &[SimilarityGroup<'_>]) {
+    for group in groups {
+        print!("{} ", group.similarity_score);
+
+        for file_path in &group.set {
+            print!("{:?} ", paths::display(file_path));
+
+            if let Some(companions) = group.companions.get(file_path) {
+                print!("(with {}) ", companions.iter().map(|companion| paths::display(companion)).join(", "));
+            }
+        }
+
+        match group.same_subjects() {
+            Some(true) => print!("(same subjects)"),
+            Some(false) => print!("(different subjects: {})", group.set.iter().filter_map(|member| group.layouts.get(member)).map(|layout| layout.subjects).join(", ")),
+            None => {}
+        }
+
+        println!();
+    }
+}
+
+// Bits apart and similarity of two images, going by their embeddings when both have one
+fn compare<F>(a: &HashedImage<F>, b: &HashedImage<F>) -> (u32, f32) {
+    let (bits, similarity_score) = distance(&a.hash, &b.hash);
+
+    match (&a.embedding, &b.embedding) {
+        (Some(a), Some(b)) => (bits, cosine(a, b)),
+        _ => (bits, similarity_score)
+    }
+}
+
+// Cosine similarity of two embeddings of unit length, 1 for the same direction
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn load_clip(path: &Path) -> Option<Arc<Clip>> {
+    match Clip::load(path) {
+        Ok(clip) => Some(Arc::new(clip)),
+        Err(err) => {
+            eprintln!("Failure while loading the CLIP model, comparing perceptual hashes instead: {}", path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            None
+        }
+    }
+}
+
+// How many bits two hashes differ in, and the share of bits they have in common
+#[allow(clippy::cast_precision_loss)]
+fn distance(a: &ImageHash, b: &ImageHash) -> (u32, f32) {
+    let max_dist = a.as_bytes().len() * 8;
+    let bits = a.dist(b);
+
+    let dist = if bits == 0 {
+        0.0
+    } else {
+        (bits as f32) / (max_dist as f32)
+    };
+
+    (bits, 1.0 - dist)
 }
 
 pub struct SimilarityGroup<'a> {
-    similarity_score: f32,
-    set: HashSet<&'a Path>
+    pub similarity_score: f32,
+    pub set: HashSet<&'a Path>,
+    // Photos shot in quick succession, which are similar by nature rather than copies
+    pub burst: bool,
+    // RAW files and sidecars of the members that have any, which belong with them
+    pub companions: HashMap<&'a Path, Vec<PathBuf>>,
+    // Where the subjects of each member are, with --annotate-subjects
+    pub layouts: HashMap<&'a Path, Layout>
+}
+
+impl SimilarityGroup<'_> {
+    pub fn id(&self, root: &Path) -> String {
+        group_id(root, self.set.iter().copied())
+    }
+
+    // Whether every member has its subjects in the same places, as re-exports of one photo do,
+    // unlike shots of the same scene. Only known with --annotate-subjects
+    pub fn same_subjects(&self) -> Option<bool> {
+        let mut layouts = self.layouts.values();
+        let first = layouts.next()?;
+
+        Some(layouts.all(|layout| layout == first))
+    }
+}
+
+// Hash of the sorted member paths relative to the root, so the same files get the same ID
+// however the root was passed, e.g. `3fa2c81d09be4475`
+fn group_id<'a>(root: &Path, members: impl IntoIterator<Item = &'a Path>) -> String {
+    let mut ctx = Context::new(&SHA256);
+
+    for member in members.into_iter().map(|member| paths::relative_display(root, member)).sorted() {
+        // Length-prefixed so paths can't run into one another
+        ctx.update(&(member.len() as u64).to_le_bytes());
+        ctx.update(member.as_bytes());
+    }
+
+    deduplicator::hex(&ctx.finish().as_ref()[..8])
+}
+
+// Whether every photo was taken within a couple seconds of the previous one,
+// or their names only differ by consecutive numbers like IMG_0041.jpg and IMG_0042.jpg
+fn is_burst(set: &HashSet<&Path>, taken: &HashMap<&Path, Option<i64>>) -> bool {
+    let times: Option<Vec<i64>> = set.iter()
+        .map(|path| taken.get(path).copied().flatten())
+        .collect();
+
+    let shot_together = times.is_some_and(|mut times| {
+        times.sort_unstable();
+        times.windows(2).all(|pair| pair[1] - pair[0] <= BURST_GAP)
+    });
+
+    shot_together || is_numbered_sequence(set)
+}
+
+fn is_numbered_sequence(set: &HashSet<&Path>) -> bool {
+    let numbered: Option<Vec<(String, u64)>> = set.iter()
+        .map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+            let number = stem[prefix.len()..].parse().ok()?;
+
+            Some((format!("{}/{prefix}", paths::display(path.parent()?)), number))
+        })
+        .collect();
+
+    let Some(mut numbered) = numbered else {
+        return false;
+    };
+
+    numbered.sort_unstable();
+
+    numbered.windows(2).all(|pair| pair[0].0 == pair[1].0 && pair[1].1 == pair[0].1 + 1)
+}
+
+// One ID per line, where blank lines and anything after a `#` are left out, so reviewers can note why
+fn read_ignored(path: &Path) -> HashSet<String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            // IDs are also accepted as written in review notes, like `0x3fa2c81d09be4475`
+            .map(|id| id.trim_start_matches("0x").to_ascii_lowercase())
+            .filter(|id| !id.is_empty())
+            .collect(),
+        Err(err) => {
+            eprintln!("Failure while reading the ignored groups: {}", path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            HashSet::new()
+        }
+    }
+}
+
+fn read(file: &impl FileSource) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    file.open()?.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+fn decode<F: FileSource>(entry: F, data: &[u8], clip: Option<&Clip>, subjects: bool, video_frames: bool, no_ignore_errors: bool) -> Option<HashedImage<F>> {
+    // Same as image::open, which goes by the extension when it's known and sniffs the contents otherwise
+    let image = ImageFormat::from_path(entry.path())
+        .map_or_else(|_| image::load_from_memory(data), |format| image::load_from_memory_with_format(data, format));
+
+    let image = match image {
+        Err(err) => {
+            // Videos and animations the decoder can't read are compared by a frame ffmpeg takes out of them,
+            // which needs them on disk
+            let frame = entry.local_path()
+                .filter(|path| video_frames && video::has_frames(path))
+                .map(video::poster_frame);
+
+            match frame {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => {
+                    if no_ignore_errors {
+                        eprintln!("Could not take a frame out of video in similarity mode:");
+                        eprintln!("{err:?}");
+                        eprintln!("{}", paths::display(entry.path()));
+                    }
+
+                    return None;
+                },
+                None => {
+                    if no_ignore_errors {
+                        eprintln!("Could not read file as image in similarity mode:");
+                        eprintln!("{err:?}");
+                        eprintln!("{}", paths::display(entry.path()));
+                    }
+
+                    return None;
+                }
+            }
+        },
+        Ok(image) => image
+    };
+
+    let hash_config = HasherConfig::new()
+        .hash_alg(HashAlg::DoubleGradient)
+        .hash_size(16, 16)
+        .to_hasher();
+    let hash = hash_config.hash_image(&image);
+
+    let class = if images::is_screenshot(data, image.width(), image.height()) {
+        ImageClass::Screenshots
+    } else {
+        ImageClass::Photos
+    };
+
+    let taken = images::taken_at(data).or_else(|| {
+        let modified = entry.info().ok()?.modified?;
+
+        i64::try_from(modified.duration_since(UNIX_EPOCH).ok()?.as_secs()).ok()
+    });
+
+    let embedding = clip.and_then(|clip| match clip.embed(&image) {
+        Ok(embedding) => Some(embedding),
+        Err(err) => {
+            if no_ignore_errors {
+                eprintln!("Could not embed image with the CLIP model, comparing its hash instead:");
+                eprintln!("{err:?}");
+                eprintln!("{}", paths::display(entry.path()));
+            }
+
+            None
+        }
+    });
+
+    Some(HashedImage {
+        hash,
+        entry,
+        width: image.width(),
+        height: image.height(),
+        taken,
+        class,
+        embedding,
+        layout: subjects.then(|| subjects::layout(&image)),
+        scanned: None
+    })
 }
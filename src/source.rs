@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::{self, Read};
+use std::mem;
+use std::path::Path;
+use std::time::SystemTime;
+
+use walkdir::{DirEntry, WalkDir};
+
+use crate::reparse;
+
+// What grouping and selection need to know about a file, read in one go as that usually takes a syscall
+#[derive(Debug, Clone, Copy)]
+pub struct FileInfo {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    // Not every platform or backend records this
+    pub created: Option<SystemTime>,
+    // Cloud files whose contents would have to be downloaded first, skipped unless --hydrate is passed
+    pub placeholder: bool
+}
+
+// A file to deduplicate, wherever it comes from: a folder on disk, an archive, a container layer
+// or a remote backend, so they can all go through the same bucketing, hashing and selection
+pub trait FileSource: Send + 'static {
+    // Whether `reopen` can get files back from their path, without which they're never spilled to disk
+    const REOPENABLE: bool = false;
+
+    // Where the file is, for output and for ordering by name, which needn't exist on disk
+    fn path(&self) -> &Path;
+
+    // Folders are walked along with files, but have no contents to compare
+    fn is_file(&self) -> bool;
+
+    fn info(&self) -> io::Result<FileInfo>;
+
+    fn open(&self) -> io::Result<Box<dyn Read + Send>>;
+
+    // Where the file is on disk, if it is, which the hashing options that seek into files
+    // or decode them need, along with acting on duplicates
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
+
+    // Bytes taken by the file in memory, along with its path
+    fn footprint(&self) -> usize where Self: Sized {
+        mem::size_of::<Self>() + self.path().as_os_str().len()
+    }
+
+    // Gets the file back from its path, for files spilled to disk with --max-memory
+    fn reopen(_path: &Path) -> Option<Self> where Self: Sized {
+        None
+    }
+}
+
+impl FileSource for DirEntry {
+    const REOPENABLE: bool = true;
+
+    fn path(&self) -> &Path {
+        Self::path(self)
+    }
+
+    // Known from the walk without another syscall
+    fn is_file(&self) -> bool {
+        self.file_type().is_file()
+    }
+
+    fn info(&self) -> io::Result<FileInfo> {
+        let metadata = self.metadata()?;
+
+        Ok(FileInfo {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+            placeholder: reparse::is_placeholder(&metadata) || reparse::is_icloud_stub(self)
+        })
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(Self::path(self))?))
+    }
+
+    fn local_path(&self) -> Option<&Path> {
+        Some(Self::path(self))
+    }
+
+    // Walking a path without going any deeper yields the entry for the path itself
+    fn reopen(path: &Path) -> Option<Self> {
+        WalkDir::new(path)
+            .max_depth(0)
+            .into_iter()
+            .next()?
+            .ok()
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+use std::path::Path;
+
+// Bytes available to the user on the volume a path is on
+#[cfg(unix)]
+pub fn free(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stats = unsafe { std::mem::zeroed::<libc::statvfs>() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), std::ptr::addr_of_mut!(stats)) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The field types differ between platforms
+    #[allow(clippy::useless_conversion, clippy::unnecessary_cast)]
+    Ok(u64::from(stats.f_bavail) * stats.f_frsize as u64)
+}
+
+#[cfg(windows)]
+pub fn free(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    extern "system" {
+        fn GetDiskFreeSpaceExW(directory: *const u16, available: *mut u64, total: *mut u64, total_free: *mut u64) -> i32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0;
+
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn free(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "free space can't be read on this platform"))
+}
@@ -0,0 +1,94 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Size of the bucket keys records are filed under
+pub const KEY_LEN: usize = 9;
+
+pub type Key = [u8; KEY_LEN];
+
+// Files written out to a temporary file once the size map outgrows --max-memory, as records of
+// their bucket key and path, which are read back once every file is scanned
+pub struct Spill {
+    path: PathBuf,
+    // Gone after the first failure to write, leaving whatever isn't written yet in memory
+    writer: Option<BufWriter<File>>
+}
+
+impl Spill {
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!("deduplicator-spill-{}", std::process::id()));
+        let writer = BufWriter::new(File::create(&path)?);
+
+        Ok(Self {
+            path,
+            writer: Some(writer)
+        })
+    }
+
+    // Returns whether the record was written, which it won't be if spilling failed before
+    pub fn write(&mut self, key: Key, path: &str) -> bool {
+        let Some(ref mut writer) = self.writer else {
+            return false;
+        };
+
+        let len = u32::try_from(path.len()).unwrap_or(u32::MAX).to_le_bytes();
+        let written = writer.write_all(&key)
+            .and_then(|()| writer.write_all(&len))
+            .and_then(|()| writer.write_all(path.as_bytes()));
+
+        if let Err(err) = written {
+            eprintln!("Failure while spilling to: {}", self.path.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+
+            self.writer = None;
+
+            return false;
+        }
+
+        true
+    }
+
+    // Every record written so far, in the order they were
+    pub fn read(&mut self) -> io::Result<impl Iterator<Item = io::Result<(Key, PathBuf)>>> {
+        if let Some(ref mut writer) = self.writer {
+            writer.flush()?;
+        }
+
+        let mut reader = BufReader::new(File::open(&self.path)?);
+
+        Ok(std::iter::from_fn(move || {
+            let mut key = [0; KEY_LEN];
+
+            match reader.read_exact(&mut key) {
+                Ok(()) => {},
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+                Err(err) => return Some(Err(err))
+            }
+
+            Some(read_path(&mut reader).map(|path| (key, path)))
+        }))
+    }
+}
+
+fn read_path(reader: &mut impl Read) -> io::Result<PathBuf> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let mut path = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut path)?;
+
+    String::from_utf8(path)
+        .map(PathBuf::from)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        self.writer = None;
+
+        let _ = fs::remove_file(&self.path);
+    }
+}
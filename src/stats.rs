@@ -0,0 +1,109 @@
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+// What a hash mode run found, where every file is counted at most once per counter
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    // Files sharing a size with at least one other, which are the only ones that get hashed
+    pub candidates: usize,
+    // Candidates whose contents turned out to be unique after all
+    pub false_candidates: usize,
+    // Candidates that couldn't be read for hashing
+    pub unreadable: usize,
+    // Files with the same hash as another but different contents, only looked for with --forensic
+    pub hash_collisions: usize,
+    pub duplicate_groups: usize,
+    // Every file in a duplicate group, including the ones that are kept
+    pub duplicate_files: usize,
+    // Bytes freed by deleting every duplicate but the kept one
+    pub reclaimable_bytes: u64,
+    // Duplicates that were already APFS clones of the kept file, left out of the groups
+    pub clones: usize
+}
+
+impl Stats {
+    pub fn to_json(self) -> Value {
+        json!({
+            "candidates": self.candidates,
+            "false_candidates": self.false_candidates,
+            "unreadable": self.unreadable,
+            "hash_collisions": self.hash_collisions,
+            "duplicate_groups": self.duplicate_groups,
+            "duplicate_files": self.duplicate_files,
+            "reclaimable_bytes": self.reclaimable_bytes,
+            "clones": self.clones
+        })
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.candidates += other.candidates;
+        self.false_candidates += other.false_candidates;
+        self.unreadable += other.unreadable;
+        self.hash_collisions += other.hash_collisions;
+        self.duplicate_groups += other.duplicate_groups;
+        self.duplicate_files += other.duplicate_files;
+        self.reclaimable_bytes += other.reclaimable_bytes;
+        self.clones += other.clones;
+    }
+}
+
+// Time spent in each phase of a hash mode run, which add up to about the total
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    // Listing the folder
+    pub walk: Duration,
+    // Reading the metadata of every file to bucket them by size
+    pub metadata: Duration,
+    // Hashing candidates, and byte-comparing them with --forensic
+    pub hashing: Duration,
+    // Ordering buckets and groups
+    pub grouping: Duration,
+    // Deleting, renaming or linking duplicates, or writing them out for later
+    pub actions: Duration
+}
+
+impl Timings {
+    pub const fn phases(self) -> [(&'static str, Duration); 5] {
+        [
+            ("walk", self.walk),
+            ("metadata", self.metadata),
+            ("hashing", self.hashing),
+            ("grouping", self.grouping),
+            ("actions", self.actions)
+        ]
+    }
+
+    pub fn to_json(self) -> Value {
+        let phases: serde_json::Map<_, _> = self.phases()
+            .iter()
+            .map(|(name, duration)| (format!("{name}_ms"), json!(duration.as_millis())))
+            .collect();
+
+        Value::Object(phases)
+    }
+}
+
+// Peak memory taken by the structures that grow with the number of files, estimated from their contents
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Memory {
+    // Files bucketed by size, before hashing
+    pub sizes: usize,
+    // Digests of the largest bucket hashed
+    pub hashes: usize,
+    // Files written out to disk instead, with --max-memory
+    pub spilled: usize
+}
+
+impl Memory {
+    pub fn to_json(self) -> Value {
+        json!({
+            "sizes_bytes": self.sizes,
+            "hashes_bytes": self.hashes,
+            "spilled_files": self.spilled
+        })
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::Path;
+
+// What kind of device a scanned folder lives on, to pick defaults that don't thrash it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Rotational,
+    Solid,
+    Network,
+    Unknown
+}
+
+// Mount types whose reads go over the network, where latency rather than seeking is the bottleneck
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: [&str; 11] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "fuse.sshfs",
+    "fuse.rclone"
+];
+
+impl Storage {
+    #[cfg(target_os = "linux")]
+    pub fn detect(path: &Path) -> Self {
+        use std::fs;
+        use std::os::unix::fs::MetadataExt;
+
+        if is_network(path) {
+            return Self::Network;
+        }
+
+        let Ok(metadata) = fs::metadata(path) else {
+            return Self::Unknown;
+        };
+
+        let dev = metadata.dev();
+        let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+        let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+        let block = Path::new("/sys/dev/block").join(format!("{major}:{minor}"));
+
+        // Partitions don't have a queue of their own, the disk they're on does
+        for queue in [block.join("queue"), block.join("../queue")] {
+            if let Ok(rotational) = fs::read_to_string(queue.join("rotational")) {
+                return if rotational.trim() == "1" { Self::Rotational } else { Self::Solid };
+            }
+        }
+
+        Self::Unknown
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub const fn detect(_path: &Path) -> Self {
+        Self::Unknown
+    }
+
+    // A couple of threads keep a spinning disk busy, any more and it spends its time seeking between files
+    // Network shares are the opposite, more requests in flight hide the round trips
+    pub fn threads(self) -> usize {
+        match self {
+            Self::Rotational => 2,
+            Self::Network => num_cpus::get().max(4) * 2,
+            Self::Solid | Self::Unknown => num_cpus::get()
+        }
+    }
+
+    // Bytes read from a file at a time while hashing, larger reads mean fewer seeks and round trips
+    pub const fn read_buffer(self) -> usize {
+        match self {
+            Self::Rotational | Self::Network => 1024 * 1024,
+            Self::Solid | Self::Unknown => 64 * 1024
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_network(path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return false;
+    };
+
+    // The mount point is the fifth field, the filesystem type follows the `-` separator
+    mounts.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = fields.nth(4)?.replace("\\040", " ");
+            let fs_type = fields.skip_while(|&field| field != "-").nth(1)?;
+
+            Some((mount_point, fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, fs_type)| NETWORK_FILESYSTEMS.contains(&fs_type))
+}
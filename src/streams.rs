@@ -0,0 +1,126 @@
+use std::ffi::OsString;
+use std::io::{self, Read};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ring::digest::{SHA256, Context};
+
+// Mark of the Web, written by browsers on every download with the URL it came from,
+// which would otherwise keep the same file downloaded twice from matching itself
+const IGNORED_STREAMS: [&str; 1] = ["Zone.Identifier"];
+
+// Folds the named NTFS alternate data streams of a file into its digest, so that files
+// only match when their streams do too, and deleting a copy never drops a stream the kept file lacks
+// Files without streams keep their plain digest, so they still match copies on other filesystems
+pub fn extend_digest(path: &Path, digest: &[u8]) -> io::Result<Vec<u8>> {
+    let mut names = list(path)?;
+    names.retain(|name| !IGNORED_STREAMS.iter().any(|ignored| ignored.eq_ignore_ascii_case(name)));
+
+    if names.is_empty() {
+        return Ok(digest.to_owned());
+    }
+
+    names.sort();
+
+    let mut ctx = Context::new(&SHA256);
+    let mut buf = [0; 1024];
+
+    ctx.update(digest);
+
+    for name in names {
+        let mut stream = fs::File::open(stream_path(path, &name))?;
+
+        ctx.update(&(name.len() as u64).to_le_bytes());
+        ctx.update(name.as_bytes());
+
+        loop {
+            let count = stream.read(&mut buf)?;
+            if count == 0 {
+                break;
+            }
+
+            ctx.update(&buf[..count]);
+        }
+    }
+
+    Ok(ctx.finish().as_ref().to_owned())
+}
+
+fn stream_path(path: &Path, name: &str) -> PathBuf {
+    let mut full = OsString::from(path.as_os_str());
+    full.push(":");
+    full.push(name);
+
+    PathBuf::from(full)
+}
+
+// Names of the file's alternate data streams, without the unnamed main stream
+#[cfg(windows)]
+pub fn list(path: &Path) -> io::Result<Vec<String>> {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+
+    const FIND_STREAM_INFO_STANDARD: u32 = 0;
+    const ERROR_HANDLE_EOF: i32 = 38;
+    // MAX_PATH + 36, for the `:name:$DATA` decoration
+    const MAX_STREAM_NAME: usize = 296;
+
+    #[repr(C)]
+    struct FindStreamData {
+        stream_size: i64,
+        stream_name: [u16; MAX_STREAM_NAME]
+    }
+
+    extern "system" {
+        fn FindFirstStreamW(file_name: *const u16, info_level: u32, data: *mut FindStreamData, flags: u32) -> *mut c_void;
+        fn FindNextStreamW(handle: *mut c_void, data: *mut FindStreamData) -> i32;
+        fn FindClose(handle: *mut c_void) -> i32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = FindStreamData {
+        stream_size: 0,
+        stream_name: [0; MAX_STREAM_NAME]
+    };
+
+    let handle = unsafe { FindFirstStreamW(wide.as_ptr(), FIND_STREAM_INFO_STANDARD, &mut data, 0) };
+
+    // INVALID_HANDLE_VALUE
+    if handle as isize == -1 {
+        let err = io::Error::last_os_error();
+
+        return if err.raw_os_error() == Some(ERROR_HANDLE_EOF) {
+            Ok(Vec::new())
+        } else {
+            Err(err)
+        };
+    }
+
+    let mut names = Vec::new();
+
+    loop {
+        let len = data.stream_name.iter().position(|&c| c == 0).unwrap_or(MAX_STREAM_NAME);
+        let raw = String::from_utf16_lossy(&data.stream_name[..len]);
+
+        // Streams are reported as `:name:$DATA`, and the main one as `::$DATA`
+        if let Some(name) = raw.strip_prefix(':').and_then(|raw| raw.strip_suffix(":$DATA")) {
+            if !name.is_empty() {
+                names.push(name.to_owned());
+            }
+        }
+
+        if unsafe { FindNextStreamW(handle, &mut data) } == 0 {
+            break;
+        }
+    }
+
+    unsafe { FindClose(handle) };
+
+    Ok(names)
+}
+
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+pub const fn list(_path: &Path) -> io::Result<Vec<String>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,82 @@
+use image::DynamicImage;
+use image::imageops::FilterType;
+
+// Side of the grayscale thumbnail subjects are looked for in
+const GRID: usize = 32;
+// Share of the thumbnail a blob takes up at least to count as a subject, in pixels out of GRID * GRID
+const MIN_BLOB: usize = 16;
+// How many standard deviations from the mean brightness a pixel stands out by to be part of a subject
+const CONTRAST: f32 = 1.0;
+
+// Where the things standing out of an image are, found by cheap heuristics rather than any detector:
+// blobs of pixels much brighter or darker than the rest, and which thirds of the image their centers fall in.
+// A re-export of a photo keeps its layout, while another shot of the same scene tends to move something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub subjects: usize,
+    // Cells of a 3 by 3 grid over the image that the center of a subject falls in, one bit each
+    cells: u16
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn layout(image: &DynamicImage) -> Layout {
+    #[allow(clippy::cast_possible_truncation)]
+    let side = GRID as u32;
+    let thumbnail = image.resize_exact(side, side, FilterType::Triangle).to_luma8();
+    let pixels: Vec<f32> = thumbnail.pixels().map(|pixel| f32::from(pixel[0])).collect();
+
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+    let deviation = (pixels.iter().map(|pixel| (pixel - mean).powi(2)).sum::<f32>() / pixels.len() as f32).sqrt();
+
+    // Flat images have nothing standing out
+    if deviation < 1.0 {
+        return Layout { subjects: 0, cells: 0 };
+    }
+
+    let mut salient: Vec<bool> = pixels.iter()
+        .map(|pixel| (pixel - mean).abs() > CONTRAST * deviation)
+        .collect();
+    let mut layout = Layout { subjects: 0, cells: 0 };
+
+    for start in 0..salient.len() {
+        if !salient[start] {
+            continue;
+        }
+
+        // Flood fills the blob, clearing it so it's only counted once
+        let (mut area, mut sum_x, mut sum_y) = (0, 0, 0);
+        let mut stack = vec![start];
+        salient[start] = false;
+
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % GRID, index / GRID);
+
+            area += 1;
+            sum_x += x;
+            sum_y += y;
+
+            let neighbors = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < GRID).then(|| index + 1),
+                (y > 0).then(|| index - GRID),
+                (y + 1 < GRID).then(|| index + GRID)
+            ];
+
+            for &neighbor in neighbors.iter().flatten() {
+                if salient[neighbor] {
+                    salient[neighbor] = false;
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        if area >= MIN_BLOB {
+            let cell = (sum_y / area * 3 / GRID) * 3 + sum_x / area * 3 / GRID;
+
+            layout.subjects += 1;
+            layout.cells |= 1 << cell;
+        }
+    }
+
+    layout
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::options::TextNormalization;
+
+// Plain text, code and markup that's commonly edited on more than one OS
+const TEXT_EXTENSIONS: [&str; 58] = [
+    "txt", "md", "markdown", "rst", "adoc", "tex", "csv", "tsv", "log", "srt", "vtt",
+    "json", "xml", "yml", "yaml", "toml", "ini", "cfg", "conf", "properties", "env",
+    "html", "htm", "css", "scss", "less", "svg",
+    "js", "mjs", "cjs", "jsx", "ts", "tsx", "vue",
+    "rs", "c", "h", "cc", "cpp", "hpp", "cs", "java", "kt", "go", "swift", "py", "rb", "php", "pl", "lua", "sql",
+    "sh", "bash", "zsh", "ps1", "bat", "cmd", "gradle"
+];
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+pub fn is_text(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            TEXT_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+// Contents of a text file with the differences editors and sync tools introduce taken out,
+// or as they are if they have a NUL byte, which text never does
+pub fn normalized(path: &Path, normalizations: &[TextNormalization]) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+
+    if data.contains(&0) {
+        return Ok(data);
+    }
+
+    Ok(normalize(&data, normalizations))
+}
+
+fn normalize(data: &[u8], normalizations: &[TextNormalization]) -> Vec<u8> {
+    let eol = normalizations.contains(&TextNormalization::Eol);
+    let trailing_ws = normalizations.contains(&TextNormalization::TrailingWs);
+
+    let data = if normalizations.contains(&TextNormalization::Bom) {
+        data.strip_prefix(BOM).unwrap_or(data)
+    } else {
+        data
+    };
+
+    let mut normalized = Vec::with_capacity(data.len());
+
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        let (line, newline) = line.strip_suffix(b"\n").map_or((line, false), |line| (line, true));
+        let (mut line, cr) = line.strip_suffix(b"\r").map_or((line, false), |line| (line, true));
+
+        if trailing_ws {
+            while let Some(rest) = line.strip_suffix(b" ").or_else(|| line.strip_suffix(b"\t")) {
+                line = rest;
+            }
+        }
+
+        normalized.extend_from_slice(line);
+
+        // CRLF line endings become LF, like git's autocrlf does
+        if cr && !eol {
+            normalized.push(b'\r');
+        }
+
+        if newline {
+            normalized.push(b'\n');
+        }
+    }
+
+    // Blank lines at the end go too, and with them whether the last line ends in a newline
+    if trailing_ws {
+        while normalized.last().is_some_and(|&byte| matches!(byte, b'\n' | b'\r' | b' ' | b'\t')) {
+            normalized.pop();
+        }
+    }
+
+    normalized
+}
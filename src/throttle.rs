@@ -0,0 +1,59 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::options::Options;
+
+// How often batches of --delete-batch can start when there's no --delete-rate
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Spaces deletions out with --delete-rate and --delete-batch, for network file systems and mounts
+// backed by object storage, which struggle with bursts of deletions, or snapshot every one of them
+pub struct Throttle {
+    // How long each batch takes at least, if deletions are limited at all
+    interval: Option<Duration>,
+    batch: usize,
+    // When the current batch started, and how many deletions it had so far
+    current: Mutex<(Instant, usize)>
+}
+
+impl Throttle {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(options: &Options) -> Self {
+        let batch = options.delete_batch.unwrap_or(1).max(1);
+        let interval = options.delete_rate
+            .filter(|&rate| rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(batch as f64 / rate))
+            .or_else(|| options.delete_batch.map(|_| BATCH_INTERVAL));
+
+        Self {
+            interval,
+            batch,
+            current: Mutex::new((Instant::now(), 0))
+        }
+    }
+
+    // Blocks until the next deletion may go ahead
+    pub fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let mut current = self.current.lock().expect("throttle is never poisoned");
+        let (started, done) = &mut *current;
+
+        if *done >= self.batch {
+            if let Some(left) = interval.checked_sub(started.elapsed()) {
+                thread::sleep(left);
+            }
+
+            *started = Instant::now();
+            *done = 0;
+        }
+
+        *done += 1;
+
+        // Held while sleeping, so deletions from other threads wait their turn too
+        drop(current);
+    }
+}
@@ -0,0 +1,179 @@
+use std::convert::TryFrom;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::options::Options;
+
+// Moves deleted duplicates to the trash with --trash instead of removing them for good, keeping count of
+// what was moved, as it still takes up space on disk until the trash is emptied
+pub struct Trash {
+    // Files and bytes moved so far
+    moved: Mutex<(usize, u64)>
+}
+
+impl Trash {
+    pub fn new(options: &Options) -> Option<Self> {
+        options.trash.then(|| Self {
+            moved: Mutex::new((0, 0))
+        })
+    }
+
+    pub fn put(&self, path: &Path) -> io::Result<()> {
+        let len = path.symlink_metadata()?.len();
+
+        move_to_trash(path)?;
+
+        let mut moved = self.moved.lock().expect("trash counts are never poisoned");
+        moved.0 += 1;
+        moved.1 += len;
+        drop(moved);
+
+        Ok(())
+    }
+
+    pub fn moved(&self) -> (usize, u64) {
+        *self.moved.lock().expect("trash counts are never poisoned")
+    }
+}
+
+// When files trashed now can go for good, with --empty-trash-after, as `YYYY-MM-DD`
+pub fn empty_date(days: u64) -> Option<String> {
+    let date = SystemTime::now() + Duration::from_secs(days * 86400);
+
+    local_time(date).map(|time| time[..10].to_owned())
+}
+
+// The trash of the freedesktop.org spec, used by GNOME, KDE and most other Linux desktops: files go in `files`,
+// and a `.trashinfo` in `info` records where each came from so it can be restored from the file manager
+#[cfg(all(unix, not(target_os = "macos")))]
+fn move_to_trash(path: &Path) -> io::Result<()> {
+    use std::env;
+    use std::fs::{self, DirBuilder, OpenOptions};
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::path::PathBuf;
+
+    // Only the folder is resolved, so a link is trashed rather than what it points to
+    let absolute = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => parent.canonicalize()?.join(name),
+        (_, Some(name)) => env::current_dir()?.join(name),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name"))
+    };
+    let device = fs::symlink_metadata(&absolute)?.dev();
+    let home = env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|data| data.join("Trash"));
+
+    // Files can only be renamed within their filesystem, so ones elsewhere go in the trash at its top
+    let trash = match home {
+        Some(home) if home.ancestors().find_map(|dir| fs::metadata(dir).ok()).is_some_and(|dir| dir.dev() == device) => home,
+        _ => {
+            let top = absolute.ancestors()
+                .skip(1)
+                .take_while(|dir| fs::metadata(dir).is_ok_and(|dir| dir.dev() == device))
+                .last()
+                .ok_or_else(|| io::Error::other("couldn't find the top of the file's filesystem"))?;
+
+            top.join(format!(".Trash-{}", unsafe { libc::getuid() }))
+        }
+    };
+
+    let mut builder = DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(trash.join("files"))?;
+    builder.create(trash.join("info"))?;
+
+    let name = absolute.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let date = local_time(SystemTime::now()).ok_or_else(|| io::Error::other("couldn't read the local time"))?;
+
+    // The info file is made first, as creating it can't overwrite another trashed file's
+    for attempt in 1.. {
+        let trashed = if attempt == 1 { name.clone() } else { format!("{name}.{attempt}") };
+        let info_path = trash.join("info").join(format!("{trashed}.trashinfo"));
+
+        let mut info = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(info) => info,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err)
+        };
+
+        write!(info, "[Trash Info]\nPath={}\nDeletionDate={date}\n", encode_path(&absolute))?;
+
+        if let Err(err) = fs::rename(&absolute, trash.join("files").join(&trashed)) {
+            drop(info);
+            let _ = fs::remove_file(&info_path);
+
+            return Err(err);
+        }
+
+        return Ok(());
+    }
+
+    unreachable!("some name is always free")
+}
+
+// The trash in the user's home folder, which Finder shows, without Finder's record of where files came from
+#[cfg(target_os = "macos")]
+fn move_to_trash(path: &Path) -> io::Result<()> {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    let trash = env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".Trash"))
+        .ok_or_else(|| io::Error::other("HOME isn't set"))?;
+    let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let (stem, ext) = name.rsplit_once('.').map_or((name.as_str(), String::new()), |(stem, ext)| (stem, format!(".{ext}")));
+
+    let target = (1..)
+        .map(|attempt| if attempt == 1 { trash.join(&name) } else { trash.join(format!("{stem} {attempt}{ext}")) })
+        .find(|target| fs::symlink_metadata(target).is_err())
+        .expect("some name is always free");
+
+    fs::rename(path, target)
+}
+
+#[cfg(not(unix))]
+fn move_to_trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "moving files to the recycle bin isn't supported, use --rename-dupes to set them aside instead"))
+}
+
+// Percent-encoded as in URIs, as the spec asks for
+#[cfg(all(unix, not(target_os = "macos")))]
+fn encode_path(path: &Path) -> String {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().iter().fold(String::new(), |mut encoded, &byte| {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+
+        encoded
+    })
+}
+
+// Local time as `YYYY-MM-DDThh:mm:ss`
+#[cfg(unix)]
+fn local_time(time: SystemTime) -> Option<String> {
+    let secs = libc::time_t::try_from(time.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs()).ok()?;
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+
+    if unsafe { libc::localtime_r(std::ptr::addr_of!(secs), std::ptr::addr_of_mut!(tm)) }.is_null() {
+        return None;
+    }
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec
+    ))
+}
+
+#[cfg(not(unix))]
+const fn local_time(_time: SystemTime) -> Option<String> {
+    None
+}
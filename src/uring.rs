@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use io_uring::{opcode, types, IoUring};
+use ring::digest::{Algorithm, Context};
+
+use crate::readahead;
+
+// How many reads are kept in flight at once, each for a different file
+const QUEUE_DEPTH: u32 = 64;
+
+struct Read {
+    file: fs::File,
+    ctx: Context,
+    buf: Vec<u8>,
+    offset: u64
+}
+
+// Hashes many files at once, keeping a read queued for each so fast drives always have work,
+// instead of reading one file at a time and waiting on every read
+// Returns the digest of each file, None for the ones that couldn't be read,
+// or an error if io_uring itself is unavailable, in which case the files should be hashed as usual
+pub fn digests(paths: &[&Path], algorithm: &'static Algorithm, buffer: usize) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let mut ring = IoUring::new(QUEUE_DEPTH)?;
+    let mut results = vec![None; paths.len()];
+    let mut reads: HashMap<usize, Read> = HashMap::new();
+    let mut pending = paths.iter().enumerate();
+
+    loop {
+        while reads.len() < QUEUE_DEPTH as usize {
+            let Some((index, path)) = pending.next() else {
+                break;
+            };
+
+            let Ok(file) = readahead::open_sequential(path) else {
+                continue;
+            };
+
+            let read = reads.entry(index).or_insert(Read {
+                file,
+                ctx: Context::new(algorithm),
+                buf: vec![0; buffer],
+                offset: 0
+            });
+
+            submit(&mut ring, index, read)?;
+        }
+
+        if reads.is_empty() {
+            break;
+        }
+
+        ring.submit_and_wait(1)?;
+
+        let completions: Vec<_> = ring.completion()
+            .map(|entry| (entry.user_data(), entry.result()))
+            .collect();
+
+        for (index, result) in completions {
+            let index = usize::try_from(index).expect("user data is a file index");
+            let Some(read) = reads.get_mut(&index) else {
+                continue;
+            };
+
+            match usize::try_from(result) {
+                // Errors come back as negative errnos
+                Err(_) => {
+                    reads.remove(&index);
+                },
+                Ok(0) => {
+                    let read = reads.remove(&index).expect("read is in flight");
+
+                    results[index] = Some(read.ctx.finish().as_ref().to_owned());
+                },
+                Ok(count) => {
+                    read.ctx.update(&read.buf[..count]);
+                    read.offset += count as u64;
+
+                    submit(&mut ring, index, read)?;
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn submit(ring: &mut IoUring, index: usize, read: &mut Read) -> io::Result<()> {
+    let entry = opcode::Read::new(types::Fd(read.file.as_raw_fd()), read.buf.as_mut_ptr(), read.buf.len() as u32)
+        .offset(read.offset as libc::off_t)
+        .build()
+        .user_data(index as u64);
+
+    // The buffer and file are kept alive in the map of reads until this read completes
+    unsafe { ring.submission().push(&entry) }
+        .map_err(|_| io::Error::other("io_uring submission queue is full"))
+}
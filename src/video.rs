@@ -0,0 +1,47 @@
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use image::{DynamicImage, ImageFormat};
+
+const VIDEO_EXTENSIONS: [&str; 12] = ["mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "3gp", "mts"];
+// Animations the image decoder may not handle, like animated WebP or APNG
+const ANIMATION_EXTENSIONS: [&str; 3] = ["gif", "webp", "apng"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions.iter().any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+pub fn is_video(path: &Path) -> bool {
+    has_extension(path, &VIDEO_EXTENSIONS)
+}
+
+// Whether ffmpeg can be asked for a frame of the file when the image decoder can't read it
+pub fn has_frames(path: &Path) -> bool {
+    is_video(path) || has_extension(path, &ANIMATION_EXTENSIONS)
+}
+
+// Takes a poster frame out of a video with ffmpeg, which has to be on the PATH
+// ffmpeg's thumbnail filter picks the most representative of the first frames rather than the first one,
+// which is often black or a fade in, and still works for clips and animations of a single frame
+pub fn poster_frame(path: &Path) -> io::Result<DynamicImage> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-i"])
+        .arg(path)
+        .args(["-vf", "thumbnail", "-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+        .stdin(Stdio::null())
+        .output()?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        return Err(io::Error::other(format!("ffmpeg exited with {}: {}", output.status, stderr.trim())));
+    }
+
+    image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
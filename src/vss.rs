@@ -0,0 +1,103 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A volume shadow copy, taken so files held open by other programs with sharing restrictions,
+// like Outlook PSTs and databases, can be read as they were at one point in time
+// The shadow copy is deleted along with it
+pub struct Snapshot {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    id: String,
+    // Root of the live volume, like `C:\`
+    volume: PathBuf,
+    // Root of the shadow copy, like `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy3\`
+    device: PathBuf
+}
+
+impl Snapshot {
+    // Where a file of the live volume is in the shadow copy
+    pub fn translate(&self, live: &Path) -> PathBuf {
+        live.strip_prefix(&self.volume)
+            .map_or_else(|_| live.to_owned(), |relative| self.device.join(relative))
+    }
+}
+
+// Shadow copies are made through WMI, which takes an elevated prompt
+#[cfg(windows)]
+impl Snapshot {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        use std::path::{Component, Prefix};
+
+        let absolute = crate::paths::absolute(path);
+
+        let volume = match absolute.components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => PathBuf::from(format!("{}:\\", char::from(letter))),
+                _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "shadow copies can only be taken of local volumes"))
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "the path has no drive letter"))
+        };
+
+        let script = format!(
+            "$result = (Get-WmiObject -List Win32_ShadowCopy).Create('{}', 'ClientAccessible'); \
+            if ($result.ReturnValue -ne 0) {{ exit $result.ReturnValue }}; \
+            $shadow = Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq $result.ShadowID }}; \
+            Write-Output $shadow.ID; \
+            Write-Output $shadow.DeviceObject",
+            volume.display()
+        );
+
+        let output = powershell(&script)?;
+        let mut lines = output.lines().map(str::trim);
+
+        let (Some(id), Some(device)) = (lines.next(), lines.next()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no shadow copy was listed after creating it"));
+        };
+
+        Ok(Self {
+            id: id.to_owned(),
+            volume,
+            // Shadow copy devices only open as folders with a trailing separator
+            device: PathBuf::from(format!("{device}\\"))
+        })
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let script = format!(
+            "Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq '{}' }} | ForEach-Object {{ $_.Delete() }}",
+            self.id
+        );
+
+        if let Err(err) = powershell(&script) {
+            eprintln!("Failure while deleting the shadow copy: {}", self.device.to_string_lossy());
+            eprintln!("{err:?}");
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(windows)]
+fn powershell(script: &str) -> io::Result<String> {
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "powershell exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(windows))]
+impl Snapshot {
+    pub fn create(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "shadow copies are only available on Windows"))
+    }
+}
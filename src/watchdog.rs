@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the watchdog looks at the files being read
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Files being read, and when the last one finished
+struct State {
+    in_flight: Vec<PathBuf>,
+    last_finished: Instant,
+    last_warning: Option<Instant>
+}
+
+// Warns on stderr when no file finishes hashing for a while, naming the ones still being read,
+// as reads from hung network mounts block without an error and the progress line just freezes
+pub struct Watchdog {
+    state: Arc<Mutex<State>>,
+    // Dropped along with the watchdog, which stops its thread
+    _stop: Sender<()>
+}
+
+impl Watchdog {
+    pub fn start(interval: Duration) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            in_flight: Vec::new(),
+            last_finished: Instant::now(),
+            last_warning: None
+        }));
+        let (stop, stopped) = mpsc::channel();
+
+        let watched = Arc::clone(&state);
+        thread::spawn(move || {
+            while stopped.recv_timeout(CHECK_INTERVAL) == Err(RecvTimeoutError::Timeout) {
+                let mut state = watched.lock().expect("watchdog state is never poisoned");
+
+                let stalled = !state.in_flight.is_empty()
+                    && state.last_finished.elapsed() >= interval
+                    && state.last_warning.is_none_or(|warning| warning.elapsed() >= interval);
+
+                if stalled {
+                    eprintln!("No file finished hashing in the last {}s, still reading:", state.last_finished.elapsed().as_secs());
+
+                    for path in &state.in_flight {
+                        eprintln!("  {}", path.to_string_lossy());
+                    }
+
+                    eprintln!();
+
+                    state.last_warning = Some(Instant::now());
+                }
+            }
+        });
+
+        Self {
+            state,
+            _stop: stop
+        }
+    }
+
+    // Marks a file as being read until the returned guard is dropped
+    pub fn reading(&self, path: &Path) -> Reading<'_> {
+        self.state.lock()
+            .expect("watchdog state is never poisoned")
+            .in_flight
+            .push(path.to_owned());
+
+        Reading {
+            watchdog: self,
+            path: path.to_owned()
+        }
+    }
+}
+
+pub struct Reading<'a> {
+    watchdog: &'a Watchdog,
+    path: PathBuf
+}
+
+impl Drop for Reading<'_> {
+    fn drop(&mut self) {
+        let mut state = self.watchdog.state.lock().expect("watchdog state is never poisoned");
+
+        if let Some(index) = state.in_flight.iter().position(|path| *path == self.path) {
+            state.in_flight.swap_remove(index);
+        }
+
+        state.last_finished = Instant::now();
+        state.last_warning = None;
+    }
+}